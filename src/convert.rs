@@ -0,0 +1,502 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+use miette::{Diagnostic, Report, Result};
+
+use crate::interpreter::{NativeFunction, Value};
+
+/// Conversion of a Rust value into a merc [`Value`].
+pub trait IntoValue {
+    fn into_value(self) -> Value;
+}
+
+/// Conversion of a merc [`Value`] back into a Rust value.
+pub trait FromValue: Sized {
+    fn from_value(value: Value) -> Result<Self, ConversionError>;
+}
+
+/// Returned when a [`Value`] does not have the shape a Rust type expects.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConversionError {
+    pub expected: &'static str,
+    pub found: &'static str,
+}
+
+impl ConversionError {
+    pub fn new(expected: &'static str, found: &Value) -> Self {
+        Self {
+            expected,
+            found: found.type_name(),
+        }
+    }
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected {}, got {}", self.expected, self.found)
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+impl Diagnostic for ConversionError {}
+
+/// Returned by a native function rejecting one of its arguments, so the
+/// interpreter can point at that argument in the call.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArgumentError {
+    pub function: String,
+    /// Zero-based position of the argument, where the receiver of a method
+    /// call is argument 0.
+    pub position: usize,
+    /// What is wrong with it, e.g. `expected number, got string`.
+    pub problem: String,
+    /// Whether the function was called as a method, e.g. `s.repeat(3)`, so
+    /// that the message counts only the arguments in parentheses.
+    pub method: bool,
+}
+
+impl ArgumentError {
+    pub fn new(function: &str, position: usize, problem: impl fmt::Display) -> Self {
+        Self {
+            function: function.to_string(),
+            position,
+            problem: problem.to_string(),
+            method: false,
+        }
+    }
+}
+
+impl fmt::Display for ArgumentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (position, kind) = match (self.method, self.position) {
+            (true, 0) => {
+                return write!(f, "Invalid receiver of method '{}': {}", self.function, self.problem)
+            }
+            (true, position) => (position, "method "),
+            (false, position) => (position + 1, ""),
+        };
+        write!(
+            f,
+            "Invalid argument {} to {}'{}': {}",
+            position, kind, self.function, self.problem
+        )
+    }
+}
+
+impl std::error::Error for ArgumentError {}
+
+impl Diagnostic for ArgumentError {}
+
+impl IntoValue for Value {
+    fn into_value(self) -> Value {
+        self
+    }
+}
+
+impl FromValue for Value {
+    fn from_value(value: Value) -> Result<Self, ConversionError> {
+        Ok(value)
+    }
+}
+
+impl IntoValue for () {
+    fn into_value(self) -> Value {
+        Value::Nil
+    }
+}
+
+impl FromValue for () {
+    fn from_value(value: Value) -> Result<Self, ConversionError> {
+        match value {
+            Value::Nil => Ok(()),
+            other => Err(ConversionError::new("nil", &other)),
+        }
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self) -> Value {
+        Value::Boolean(self)
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: Value) -> Result<Self, ConversionError> {
+        match value {
+            Value::Boolean(b) => Ok(b),
+            other => Err(ConversionError::new("boolean", &other)),
+        }
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self) -> Value {
+        Value::String(self)
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self) -> Value {
+        Value::String(self.to_string())
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: Value) -> Result<Self, ConversionError> {
+        match value {
+            Value::String(s) => Ok(s),
+            other => Err(ConversionError::new("string", &other)),
+        }
+    }
+}
+
+macro_rules! impl_float {
+    ($($ty:ty),*) => {$(
+        impl IntoValue for $ty {
+            fn into_value(self) -> Value {
+                Value::Number(self as f64)
+            }
+        }
+
+        impl FromValue for $ty {
+            fn from_value(value: Value) -> Result<Self, ConversionError> {
+                match value {
+                    Value::Number(n) => Ok(n as $ty),
+                    other => Err(ConversionError::new("number", &other)),
+                }
+            }
+        }
+    )*};
+}
+
+impl_float!(f32, f64);
+
+macro_rules! impl_integer {
+    ($($ty:ty),*) => {$(
+        impl IntoValue for $ty {
+            fn into_value(self) -> Value {
+                Value::Number(self as f64)
+            }
+        }
+
+        impl FromValue for $ty {
+            fn from_value(value: Value) -> Result<Self, ConversionError> {
+                // `MAX + 1` is a power of two, so it is exact even where
+                // `MAX` itself rounds up to it, as for `i64` and `u64`.
+                let end = <$ty>::MAX as f64 + 1.0;
+                match value {
+                    Value::Number(n) if n.fract() == 0.0 && n >= <$ty>::MIN as f64 && n < end => {
+                        Ok(n as $ty)
+                    }
+                    Value::Number(_) => Err(ConversionError {
+                        expected: concat!("integer (", stringify!($ty), ")"),
+                        found: "number",
+                    }),
+                    other => Err(ConversionError::new(concat!("integer (", stringify!($ty), ")"), &other)),
+                }
+            }
+        }
+    )*};
+}
+
+impl_integer!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self) -> Value {
+        match self {
+            Some(value) => value.into_value(),
+            None => Value::Nil,
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: Value) -> Result<Self, ConversionError> {
+        match value {
+            Value::Nil => Ok(None),
+            other => T::from_value(other).map(Some),
+        }
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self) -> Value {
+        Value::List(self.into_iter().map(IntoValue::into_value).collect())
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: Value) -> Result<Self, ConversionError> {
+        match value {
+            Value::List(items) => items.into_iter().map(T::from_value).collect(),
+            other => Err(ConversionError::new("list", &other)),
+        }
+    }
+}
+
+impl<T: IntoValue> IntoValue for HashMap<String, T> {
+    fn into_value(self) -> Value {
+        Value::Map(self.into_iter().map(|(k, v)| (k, v.into_value())).collect())
+    }
+}
+
+impl<T: FromValue> FromValue for HashMap<String, T> {
+    fn from_value(value: Value) -> Result<Self, ConversionError> {
+        match value {
+            Value::Map(entries) => entries
+                .into_iter()
+                .map(|(k, v)| Ok((k, T::from_value(v)?)))
+                .collect(),
+            other => Err(ConversionError::new("map", &other)),
+        }
+    }
+}
+
+impl<T: IntoValue> IntoValue for BTreeMap<String, T> {
+    fn into_value(self) -> Value {
+        Value::Map(self.into_iter().map(|(k, v)| (k, v.into_value())).collect())
+    }
+}
+
+impl<T: FromValue> FromValue for BTreeMap<String, T> {
+    fn from_value(value: Value) -> Result<Self, ConversionError> {
+        match value {
+            Value::Map(entries) => entries
+                .into_iter()
+                .map(|(k, v)| Ok((k, T::from_value(v)?)))
+                .collect(),
+            other => Err(ConversionError::new("map", &other)),
+        }
+    }
+}
+
+macro_rules! impl_tuple {
+    ($len:expr, $($name:ident),+) => {
+        impl<$($name: IntoValue),+> IntoValue for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn into_value(self) -> Value {
+                let ($($name,)+) = self;
+                Value::List(vec![$($name.into_value()),+])
+            }
+        }
+
+        impl<$($name: FromValue),+> FromValue for ($($name,)+) {
+            fn from_value(value: Value) -> Result<Self, ConversionError> {
+                match value {
+                    Value::List(items) if items.len() == $len => {
+                        let mut items = items.into_iter();
+                        Ok(($($name::from_value(items.next().unwrap())?,)+))
+                    }
+                    Value::List(_) => Err(ConversionError {
+                        expected: concat!("list of length ", $len),
+                        found: "list",
+                    }),
+                    other => Err(ConversionError::new("list", &other)),
+                }
+            }
+        }
+    };
+}
+
+impl_tuple!(1, A);
+impl_tuple!(2, A, B);
+impl_tuple!(3, A, B, C);
+impl_tuple!(4, A, B, C, D);
+impl_tuple!(5, A, B, C, D, E);
+impl_tuple!(6, A, B, C, D, E, F);
+
+/// Return types accepted from typed native functions: plain values or `Result`s of them.
+pub trait IntoNativeResult {
+    fn into_native_result(self) -> Result<Value>;
+}
+
+impl<T: IntoValue> IntoNativeResult for T {
+    fn into_native_result(self) -> Result<Value> {
+        Ok(self.into_value())
+    }
+}
+
+impl<T: IntoValue> IntoNativeResult for Result<T> {
+    fn into_native_result(self) -> Result<Value> {
+        self.map(IntoValue::into_value)
+    }
+}
+
+/// A plain Rust function whose arguments are converted from merc values.
+///
+/// `Args` is the tuple of argument types and only exists to tell the
+/// implementations for different arities apart.
+pub trait TypedFn<Args>: 'static {
+    const ARITY: usize;
+
+    fn call_typed(&self, name: &str, args: &[Value]) -> Result<Value>;
+
+    fn into_native(self, name: &str) -> NativeFunction
+    where
+        Self: Sized,
+    {
+        let owned_name = name.to_string();
+        NativeFunction::new(name, Some(Self::ARITY), move |args| {
+            self.call_typed(&owned_name, args)
+        })
+    }
+}
+
+fn convert_arg<T: FromValue>(name: &str, position: usize, value: &Value) -> Result<T> {
+    T::from_value(value.clone()).map_err(|err| Report::new(ArgumentError::new(name, position, err)))
+}
+
+macro_rules! impl_typed_fn {
+    ($len:expr $(, $arg:ident)*) => {
+        impl<Func, Ret, $($arg,)*> TypedFn<($($arg,)*)> for Func
+        where
+            Func: Fn($($arg),*) -> Ret + 'static,
+            Ret: IntoNativeResult,
+            $($arg: FromValue,)*
+        {
+            const ARITY: usize = $len;
+
+            #[allow(non_snake_case, unused_variables, unused_mut)]
+            fn call_typed(&self, name: &str, args: &[Value]) -> Result<Value> {
+                let mut args = args.iter().enumerate();
+                $(
+                    let (position, value) = args.next().unwrap();
+                    let $arg = convert_arg::<$arg>(name, position, value)?;
+                )*
+                (self)($($arg),*).into_native_result()
+            }
+        }
+    };
+}
+
+impl_typed_fn!(0);
+impl_typed_fn!(1, A);
+impl_typed_fn!(2, A, B);
+impl_typed_fn!(3, A, B, C);
+impl_typed_fn!(4, A, B, C, D);
+impl_typed_fn!(5, A, B, C, D, E);
+impl_typed_fn!(6, A, B, C, D, E, F);
+
+#[cfg(test)]
+mod tests {
+    use miette::miette;
+
+    use crate::Interpreter;
+
+    use super::*;
+
+    fn interpreter() -> Interpreter<'static> {
+        let mut interpreter = Interpreter::default();
+        interpreter.register_typed_fn("add", |a: f64, b: f64| a + b);
+        interpreter.register_typed_fn("greet", |name: Option<String>| {
+            format!("hello, {}", name.as_deref().unwrap_or("world"))
+        });
+        interpreter.register_typed_fn("total", |items: Vec<i64>| items.iter().sum::<i64>());
+        interpreter.register_typed_fn("keys", |map: HashMap<String, bool>| {
+            let mut keys: Vec<String> = map.into_keys().collect();
+            keys.sort();
+            keys
+        });
+        interpreter.register_typed_fn("swap", |(a, b): (String, f64)| (b, a));
+        interpreter.register_typed_fn("halve", |n: i64| -> Result<i64> {
+            if n % 2 == 0 {
+                Ok(n / 2)
+            } else {
+                Err(miette!("{} is odd", n))
+            }
+        });
+        interpreter
+    }
+
+    fn eval(source: &str) -> Value {
+        interpreter().eval(source).expect("evaluates")
+    }
+
+    /// The message of the error `source` fails with and the code it points at.
+    fn error(source: &str) -> (String, String) {
+        let report = interpreter().eval(source).expect_err("fails");
+        let label = report.labels().and_then(|mut labels| labels.next()).expect("a label");
+        let span = label.offset()..label.offset() + label.len();
+        (report.to_string(), source[span].to_string())
+    }
+
+    #[test]
+    fn integers_reject_numbers_past_their_range() {
+        assert_eq!(i64::from_value(Value::Number(-(2f64.powi(63)))), Ok(i64::MIN));
+        assert!(i64::from_value(Value::Number(2f64.powi(63))).is_err());
+        assert!(u64::from_value(Value::Number(2f64.powi(64))).is_err());
+        assert_eq!(i8::from_value(Value::Number(127.0)), Ok(127));
+        assert!(i8::from_value(Value::Number(128.0)).is_err());
+        assert!(u8::from_value(Value::Number(-1.0)).is_err());
+        assert!(usize::from_value(Value::Number(f64::NAN)).is_err());
+    }
+
+    #[test]
+    fn typed_functions_convert_their_arguments_and_results() {
+        assert_eq!(eval("add(1, 2)"), Value::Number(3.0));
+        assert_eq!(eval(r#"greet("merc")"#), Value::String("hello, merc".to_string()));
+        assert_eq!(eval("greet(nil)"), Value::String("hello, world".to_string()));
+        assert_eq!(eval("total([1, 2, 3])"), Value::Number(6.0));
+        assert_eq!(
+            eval("keys({b: true, a: false})"),
+            Value::List(vec![Value::String("a".to_string()), Value::String("b".to_string())])
+        );
+        assert_eq!(
+            eval(r#"swap(["x", 1])"#),
+            Value::List(vec![Value::Number(1.0), Value::String("x".to_string())])
+        );
+        assert_eq!(eval("halve(8)"), Value::Number(4.0));
+    }
+
+    #[test]
+    fn arguments_of_the_wrong_type_are_errors_at_the_argument() {
+        assert_eq!(
+            error(r#"add(1, "2")"#),
+            (
+                "Invalid argument 2 to 'add': expected number, got string".to_string(),
+                r#""2""#.to_string()
+            )
+        );
+        assert_eq!(
+            error("greet(3)"),
+            (
+                "Invalid argument 1 to 'greet': expected string, got number".to_string(),
+                "3".to_string()
+            )
+        );
+        assert_eq!(
+            error("total([1, 2.5])").0,
+            "Invalid argument 1 to 'total': expected integer (i64), got number"
+        );
+        assert_eq!(
+            error("keys({a: 1})").0,
+            "Invalid argument 1 to 'keys': expected boolean, got number"
+        );
+        assert_eq!(
+            error(r#"swap(["x"])"#).0,
+            "Invalid argument 1 to 'swap': expected list of length 2, got list"
+        );
+    }
+
+    #[test]
+    fn errors_returned_by_typed_functions_are_passed_on() {
+        let report = interpreter().eval("halve(3)").expect_err("fails");
+        assert_eq!(report.to_string(), "3 is odd");
+    }
+
+    #[test]
+    fn functions_are_equal_only_to_themselves() {
+        let mut interpreter = interpreter();
+        let value = interpreter
+            .eval("func a(x) { x }\nlet b = a\nfunc c(x) { x }\n[a, b, c]")
+            .expect("evaluates");
+        let Value::List(functions) = value else { panic!("expected a list, got {}", value) };
+        assert_eq!(functions[0], functions[1]);
+        assert_ne!(functions[0], functions[2]);
+        let same_name = interpreter.eval("func a(x) { x }\na").expect("evaluates");
+        assert_ne!(functions[0], same_name);
+    }
+}
@@ -0,0 +1,346 @@
+//! Statement and branch coverage built on the interpreter's [`Hook`].
+//!
+//! Statements and `if` conditions are identified by their file and spans,
+//! so the hits recorded while running a clone of a function body count for
+//! the function as written, and code imported from other files is covered
+//! too.
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    fs,
+    ops::Range,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use miette::Result;
+
+use crate::{
+    interpreter::{Hook, Interpreter},
+    modules, Parser, S,
+};
+
+/// What ran of one file, or of a program that wasn't read from a file.
+#[derive(Debug, Default)]
+struct Hits {
+    statements: HashMap<Range<usize>, u64>,
+    /// How often each `if` took its `then` and its `else` branch.
+    branches: HashMap<Range<usize>, [u64; 2]>,
+    /// The file's source, to find the statements that never ran.
+    source: Rc<str>,
+}
+
+type Files = HashMap<Option<PathBuf>, Hits>;
+
+/// Records which statements and branches of a program run. Clones share the
+/// same recordings, so one can be handed to the interpreter and the other
+/// kept for the report.
+#[derive(Debug, Clone, Default)]
+pub struct Coverage(Rc<RefCell<Files>>);
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes the file at `path` part of [`Coverage::files`] even if none of
+    /// its code runs.
+    pub fn include(&self, path: &Path, source: &str) {
+        let key = fs::canonicalize(path).ok();
+        self.0.borrow_mut().entry(key).or_insert_with(|| Hits {
+            source: Rc::from(source),
+            ..Hits::default()
+        });
+    }
+
+    /// The coverage of `program`, parsed from the file at `path`.
+    pub fn file(&self, path: &Path, program: &[S]) -> FileCoverage {
+        let key = fs::canonicalize(path).ok();
+        let files = self.0.borrow();
+        let none = Hits::default();
+        let hits = files.get(&key).unwrap_or(&none);
+        let mut nodes = Nodes::default();
+        program.iter().for_each(|statement| nodes.statement(statement));
+
+        let mut lines = BTreeMap::new();
+        for statement in &nodes.statements {
+            let Some(line) = statement.first_token().map(|token| token.row) else {
+                continue;
+            };
+            let count = statement
+                .span()
+                .and_then(|span| hits.statements.get(&span))
+                .copied()
+                .unwrap_or(0);
+            // Nested statements on one line, such as `if a { b }`, make one
+            // line that ran as often as the most executed of them.
+            let line: &mut u64 = lines.entry(line).or_default();
+            *line = (*line).max(count);
+        }
+        let branches = nodes
+            .conditions
+            .iter()
+            .filter_map(|condition| {
+                let line = condition.first_token()?.row;
+                let taken = condition.span().and_then(|span| hits.branches.get(&span)).copied();
+                Some((line, taken))
+            })
+            .collect();
+
+        FileCoverage {
+            path: path.to_path_buf(),
+            lines,
+            branches,
+        }
+    }
+
+    /// The coverage of every file that code ran from, the program's own and
+    /// the modules it imported, sorted by path. Files that no longer parse
+    /// are left out.
+    pub fn files(&self) -> Vec<FileCoverage> {
+        let sources: Vec<(PathBuf, Rc<str>)> = self
+            .0
+            .borrow()
+            .iter()
+            .filter_map(|(path, hits)| Some((path.clone()?, hits.source.clone())))
+            .collect();
+        let mut files: Vec<FileCoverage> = sources
+            .into_iter()
+            .filter_map(|(path, source)| {
+                let mut parser = Parser::new(&source);
+                let program = std::iter::from_fn(|| parser.parse_statement())
+                    .collect::<Result<Vec<_>>>()
+                    .ok()?;
+                let mut file = self.file(&path, &program);
+                file.path = PathBuf::from(modules::display(&path));
+                Some(file)
+            })
+            .collect();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        files
+    }
+
+    /// The hits of the file the interpreter is running code from.
+    fn hits<'a>(files: &'a mut Files, interpreter: &Interpreter<'_>) -> &'a mut Hits {
+        let file = interpreter.current_file().map(Path::to_path_buf);
+        files.entry(file).or_insert_with(|| Hits {
+            source: Rc::from(interpreter.current_source().unwrap_or_default()),
+            ..Hits::default()
+        })
+    }
+}
+
+impl Hook for Coverage {
+    fn statement(&mut self, interpreter: &mut Interpreter<'_>, statement: &S) -> Result<()> {
+        if let Some(span) = statement.span() {
+            let mut files = self.0.borrow_mut();
+            *Self::hits(&mut files, interpreter).statements.entry(span).or_default() += 1;
+        }
+        Ok(())
+    }
+
+    fn branch(&mut self, interpreter: &mut Interpreter<'_>, condition: &S, taken: bool) {
+        if let Some(span) = condition.span() {
+            let mut files = self.0.borrow_mut();
+            let branches = Self::hits(&mut files, interpreter).branches.entry(span).or_default();
+            branches[usize::from(!taken)] += 1;
+        }
+    }
+}
+
+/// Collects the statements and `if` conditions of a program, in source
+/// order.
+#[derive(Default)]
+struct Nodes<'a> {
+    statements: Vec<&'a S>,
+    conditions: Vec<&'a S>,
+}
+
+impl<'a> Nodes<'a> {
+    fn statement(&mut self, statement: &'a S) {
+        self.statements.push(statement);
+        self.expression(statement);
+    }
+
+    fn expression(&mut self, node: &'a S) {
+        match node {
+            S::Atom(_) => {}
+            S::Block(statements) => statements.iter().for_each(|s| self.statement(s)),
+            S::IfExpr {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                self.conditions.push(cond);
+                self.expression(cond);
+                self.expression(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.expression(else_branch);
+                }
+            }
+            S::Cons(_, args) | S::FunCall { args, .. } | S::List(args) => {
+                args.iter().for_each(|arg| self.expression(arg))
+            }
+            S::BinaryExpr { lhs, rhs, .. } => {
+                self.expression(lhs);
+                self.expression(rhs);
+            }
+            S::FunDef { body, .. } => self.expression(body),
+            S::Map(entries) => entries.iter().for_each(|(_, value)| self.expression(value)),
+        }
+    }
+}
+
+/// The coverage of one file.
+#[derive(Debug, Clone)]
+pub struct FileCoverage {
+    pub path: PathBuf,
+    /// How often each line with a statement on it ran.
+    pub lines: BTreeMap<usize, u64>,
+    /// The line of each `if`, with how often its `then` and `else` branches
+    /// were taken, or `None` if its condition never ran.
+    pub branches: Vec<(usize, Option<[u64; 2]>)>,
+}
+
+impl FileCoverage {
+    /// Lines that ran, out of the lines with statements.
+    pub fn lines_hit(&self) -> (usize, usize) {
+        let hit = self.lines.values().filter(|&&count| count > 0).count();
+        (hit, self.lines.len())
+    }
+
+    /// Branches taken, out of two per `if`.
+    pub fn branches_hit(&self) -> (usize, usize) {
+        let hit = self
+            .branches
+            .iter()
+            .flat_map(|(_, taken)| taken.unwrap_or_default())
+            .filter(|&count| count > 0)
+            .count();
+        (hit, self.branches.len() * 2)
+    }
+}
+
+/// The coverage of `files` in the lcov tracefile format.
+pub fn lcov(files: &[FileCoverage]) -> String {
+    let mut out = String::new();
+    for file in files {
+        let _ = writeln!(out, "TN:\nSF:{}", file.path.display());
+        for (line, count) in &file.lines {
+            let _ = writeln!(out, "DA:{},{}", line, count);
+        }
+        let (hit, found) = file.lines_hit();
+        let _ = writeln!(out, "LF:{}\nLH:{}", found, hit);
+        for (block, (line, taken)) in file.branches.iter().enumerate() {
+            for branch in 0..2 {
+                let taken = taken.map_or("-".to_string(), |taken| taken[branch].to_string());
+                let _ = writeln!(out, "BRDA:{},{},{},{}", line, block, branch, taken);
+            }
+        }
+        let (hit, found) = file.branches_hit();
+        let _ = writeln!(out, "BRF:{}\nBRH:{}\nend_of_record", found, hit);
+    }
+    out
+}
+
+/// A table of the line and branch coverage of each file, with a total.
+pub fn summary(files: &[FileCoverage]) -> String {
+    let add = |(hit, found): (usize, usize), (h, f): (usize, usize)| (hit + h, found + f);
+    let mut rows: Vec<(String, String, String)> = Vec::new();
+    let (mut lines, mut branches) = ((0, 0), (0, 0));
+    for file in files {
+        lines = add(lines, file.lines_hit());
+        branches = add(branches, file.branches_hit());
+        rows.push((
+            file.path.display().to_string(),
+            ratio(file.lines_hit()),
+            ratio(file.branches_hit()),
+        ));
+    }
+    rows.push(("Total".to_string(), ratio(lines), ratio(branches)));
+    let width = rows.iter().map(|row| row.0.len()).chain(["File".len()]).max().unwrap_or(0);
+
+    let mut out = String::new();
+    let _ = writeln!(out, "{:<width$}  {:>16}  {:>16}", "File", "Lines", "Branches");
+    for (name, lines, branches) in &rows {
+        let _ = writeln!(out, "{:<width$}  {:>16}  {:>16}", name, lines, branches);
+    }
+    out
+}
+
+fn ratio((hit, found): (usize, usize)) -> String {
+    if found == 0 {
+        return "-".to_string();
+    }
+    format!("{}/{} {:5.1}%", hit, found, hit as f64 / found as f64 * 100.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{output::OutputBuffer, Interpreter};
+
+    use super::*;
+
+    /// Runs `source` under coverage, returning its lcov record.
+    fn lcov_of(source: &str) -> String {
+        let coverage = Coverage::new();
+        let mut interpreter = Interpreter::new(Parser::new(source));
+        interpreter.set_stdout(Box::new(OutputBuffer::new()));
+        interpreter.set_hook(coverage.clone());
+        interpreter.run().unwrap();
+
+        let mut parser = Parser::new(source);
+        let program: Vec<S> = std::iter::from_fn(|| parser.parse_statement())
+            .collect::<Result<_>>()
+            .unwrap();
+        // The program wasn't read from a file, so no path resolves to it.
+        lcov(&[coverage.file(Path::new("program.mc"), &program)])
+    }
+
+    #[test]
+    fn untaken_branches_leave_lines_unhit() {
+        let lcov = lcov_of(
+            r#"func sign(n) {
+    if n < 0 {
+        return "negative"
+    }
+    return "positive"
+}
+print(sign(1))
+print(sign(2))"#,
+        );
+        assert_eq!(
+            lcov,
+            "TN:
+SF:program.mc
+DA:1,1
+DA:2,2
+DA:3,0
+DA:5,2
+DA:7,1
+DA:8,1
+LF:6
+LH:5
+BRDA:2,0,0,0
+BRDA:2,0,1,2
+BRF:2
+BRH:1
+end_of_record
+"
+        );
+    }
+
+    #[test]
+    fn conditions_that_never_ran_have_no_branch_counts() {
+        let lcov = lcov_of(
+            "func never(n) {
+    if n { print(n) } else { print(0) }
+}
+print(1)",
+        );
+        assert!(lcov.contains("DA:1,1\nDA:2,0\nDA:4,1\nLF:3\nLH:2\n"), "{}", lcov);
+        assert!(lcov.contains("BRDA:2,0,0,-\nBRDA:2,0,1,-\nBRF:2\nBRH:0\n"), "{}", lcov);
+    }
+}
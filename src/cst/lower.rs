@@ -0,0 +1,139 @@
+use miette::{miette, LabeledSpan, Report, Result, Severity};
+
+use super::{Parse, SyntaxElement, SyntaxKind, SyntaxNode, SyntaxToken};
+use crate::{
+    lexer::{Token, TokenKind},
+    ScriptError, S,
+};
+
+impl Parse {
+    /// Lowers the tree to the statements [`Parser`](crate::Parser) would
+    /// produce for the same source, positions included, so it can be run by
+    /// the interpreter. Fails with the first syntax error, if any.
+    pub fn to_ast(&self) -> Result<Vec<S>> {
+        let root = self.syntax();
+        let source = root.text();
+        if let Some(error) = self.errors.first() {
+            let report = miette! {
+                severity = Severity::Error,
+                labels = vec![LabeledSpan::at(error.range.clone(), error.message.clone())],
+                "{}", error.message,
+            }
+            .with_source_code(source);
+            return Err(Report::new(ScriptError::Parse(report)));
+        }
+
+        let lower = Lower::new(&source);
+        root.children().iter().map(|node| lower.node(node)).collect()
+    }
+}
+
+struct Lower {
+    /// Byte offset of the start of every line.
+    lines: Vec<usize>,
+}
+
+impl Lower {
+    fn new(source: &str) -> Self {
+        let mut lines = vec![0];
+        lines.extend(source.match_indices('\n').map(|(i, _)| i + 1));
+        Self { lines }
+    }
+
+    fn node(&self, node: &SyntaxNode) -> Result<S> {
+        let children = node.children();
+        let child = |i: usize| {
+            children
+                .get(i)
+                .ok_or_else(|| miette!("Incomplete {:?} node at {:?}", node.kind(), node.text_range()))
+        };
+        let lower = |i: usize| child(i).and_then(|child| self.node(child));
+        let lower_all = |node: &SyntaxNode| {
+            node.children()
+                .iter()
+                .map(|child| self.node(child))
+                .collect::<Result<Vec<_>>>()
+        };
+
+        Ok(match node.kind() {
+            SyntaxKind::Name | SyntaxKind::Literal => S::Atom(self.head(node)?),
+            SyntaxKind::Paren => lower(0)?,
+            SyntaxKind::Block => S::Block(lower_all(node)?),
+            SyntaxKind::Import => S::Cons(self.head(node)?, lower_all(node)?),
+            SyntaxKind::List => S::List(lower_all(node)?),
+            SyntaxKind::Map => S::Map(
+                children
+                    .iter()
+                    .map(|entry| {
+                        let parts = entry.children();
+                        match parts.as_slice() {
+                            [key, value] => Ok((self.node(key)?, self.node(value)?)),
+                            _ => Err(miette!("Incomplete map entry at {:?}", entry.text_range())),
+                        }
+                    })
+                    .collect::<Result<Vec<_>>>()?,
+            ),
+            SyntaxKind::Let => S::Cons(self.head(node)?, vec![lower(0)?, lower(1)?]),
+            SyntaxKind::Return | SyntaxKind::Export | SyntaxKind::Prefix | SyntaxKind::Postfix => {
+                S::Cons(self.head(node)?, vec![lower(0)?])
+            }
+            SyntaxKind::While
+            | SyntaxKind::Test
+            | SyntaxKind::Binary
+            | SyntaxKind::Member
+            | SyntaxKind::Index => S::Cons(self.head(node)?, vec![lower(0)?, lower(1)?]),
+            SyntaxKind::Func => S::FunDef {
+                name: Box::new(lower(0)?),
+                args: lower_all(child(1)?)?,
+                body: Box::new(lower(2)?),
+            },
+            SyntaxKind::Call => S::FunCall {
+                name: Box::new(lower(0)?),
+                args: lower_all(child(1)?)?,
+            },
+            SyntaxKind::If => {
+                let else_branch = match children.get(2) {
+                    Some(branch) => match self.node(&branch.children()[0])? {
+                        S::Block(statements) if statements.is_empty() => None,
+                        block => Some(Box::new(block)),
+                    },
+                    None => None,
+                };
+                S::IfExpr {
+                    cond: Box::new(lower(0)?),
+                    then_branch: Box::new(lower(1)?),
+                    else_branch,
+                }
+            }
+            kind => return Err(miette!("Cannot lower a {:?} node", kind)),
+        })
+    }
+
+    /// The first meaningful token directly inside `node`: the keyword or
+    /// operator for most nodes, the token itself for names and literals.
+    fn head(&self, node: &SyntaxNode) -> Result<Token> {
+        node.children_with_tokens()
+            .into_iter()
+            .find_map(|child| match child {
+                SyntaxElement::Token(token)
+                    if !token.is_trivia() && token.kind() != &TokenKind::NewLine =>
+                {
+                    Some(self.token(&token))
+                }
+                _ => None,
+            })
+            .ok_or_else(|| miette!("Missing token in {:?} node", node.kind()))
+    }
+
+    fn token(&self, token: &SyntaxToken) -> Token {
+        let range = token.text_range();
+        let row = self.lines.partition_point(|start| *start <= range.start);
+        Token {
+            kind: token.kind().clone(),
+            row,
+            column: range.start - self.lines[row - 1] + 1,
+            index: range.end + 1,
+            start: range.start,
+        }
+    }
+}
@@ -0,0 +1,484 @@
+//! A lossless concrete syntax tree.
+//!
+//! Unlike [`S`](crate::S), the tree keeps every byte of the source,
+//! including whitespace, comments, newlines and semicolons, so tools can
+//! inspect and rewrite code without losing its layout. It is built in two
+//! layers: immutable [`GreenNode`]s that only know their kind, text and
+//! children and can be shared between trees, and [`SyntaxNode`]s created on
+//! demand on top of them that also know their position and parent.
+
+use std::{fmt, ops::Range, rc::Rc};
+
+use crate::TokenKind;
+
+mod lower;
+mod parser;
+
+pub use parser::parse;
+
+/// The kinds of interior nodes. Leaves are tokens and keep their
+/// [`TokenKind`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SyntaxKind {
+    /// A whole program.
+    Root,
+    /// `let name = value`
+    Let,
+    /// `func name(params) { ... }`
+    Func,
+    /// The parenthesised parameter list of a function.
+    Params,
+    /// `{ ... }` containing statements.
+    Block,
+    /// `if cond { ... }` with an optional [`SyntaxKind::Else`].
+    If,
+    /// `else { ... }`
+    Else,
+    /// `while cond { ... }`
+    While,
+    /// `test "name" { ... }`
+    Test,
+    /// `return value`
+    Return,
+    /// `import "path" as name` or `from "path" import names`
+    Import,
+    /// `export` followed by a [`SyntaxKind::Let`] or [`SyntaxKind::Func`].
+    Export,
+    /// `lhs op rhs`
+    Binary,
+    /// `-x` or `+x`
+    Prefix,
+    /// `x!`
+    Postfix,
+    /// `(expr)`
+    Paren,
+    /// `callee(args)`
+    Call,
+    /// The parenthesised argument list of a call.
+    Args,
+    /// `object.name`
+    Member,
+    /// `object[index]`
+    Index,
+    /// `[items]`
+    List,
+    /// `{key: value, ...}`
+    Map,
+    /// `key: value` inside a map.
+    Entry,
+    /// A number, string, boolean or `nil`.
+    Literal,
+    /// An identifier.
+    Name,
+    /// Tokens that could not be parsed.
+    Error,
+}
+
+/// A token together with its exact source text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GreenToken {
+    kind: TokenKind,
+    text: String,
+}
+
+impl GreenToken {
+    pub fn new(kind: TokenKind, text: impl Into<String>) -> Self {
+        Self {
+            kind,
+            text: text.into(),
+        }
+    }
+
+    pub fn kind(&self) -> &TokenKind {
+        &self.kind
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GreenElement {
+    Node(Rc<GreenNode>),
+    Token(Rc<GreenToken>),
+}
+
+impl GreenElement {
+    /// Length of the element's text in bytes.
+    pub fn width(&self) -> usize {
+        match self {
+            GreenElement::Node(node) => node.width,
+            GreenElement::Token(token) => token.text.len(),
+        }
+    }
+}
+
+/// An interior node that knows its children but not where it sits in the
+/// source, so identical subtrees can be shared.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GreenNode {
+    kind: SyntaxKind,
+    width: usize,
+    children: Vec<GreenElement>,
+}
+
+impl GreenNode {
+    pub fn new(kind: SyntaxKind, children: Vec<GreenElement>) -> Self {
+        Self {
+            kind,
+            width: children.iter().map(GreenElement::width).sum(),
+            children,
+        }
+    }
+
+    pub fn kind(&self) -> SyntaxKind {
+        self.kind
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn children(&self) -> &[GreenElement] {
+        &self.children
+    }
+}
+
+/// Assembles a green tree from a flat sequence of tokens and node
+/// boundaries.
+#[derive(Default)]
+pub struct GreenBuilder {
+    parents: Vec<(SyntaxKind, usize)>,
+    children: Vec<GreenElement>,
+}
+
+/// A position in the builder that a node can later be started from, for
+/// nodes such as binary expressions whose kind is only known after their
+/// first child has been built.
+#[derive(Debug, Clone, Copy)]
+pub struct Checkpoint(usize);
+
+impl GreenBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn start_node(&mut self, kind: SyntaxKind) {
+        self.parents.push((kind, self.children.len()));
+    }
+
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint(self.children.len())
+    }
+
+    /// Starts a node that contains everything added since `checkpoint`.
+    pub fn start_node_at(&mut self, checkpoint: Checkpoint, kind: SyntaxKind) {
+        self.parents.push((kind, checkpoint.0));
+    }
+
+    pub fn token(&mut self, kind: TokenKind, text: &str) {
+        self.children
+            .push(GreenElement::Token(Rc::new(GreenToken::new(kind, text))));
+    }
+
+    pub fn finish_node(&mut self) {
+        let (kind, first) = self.parents.pop().expect("finish_node without start_node");
+        let children = self.children.split_off(first);
+        self.children
+            .push(GreenElement::Node(Rc::new(GreenNode::new(kind, children))));
+    }
+
+    /// Returns the root node; every started node must have been finished.
+    pub fn finish(mut self) -> GreenNode {
+        assert!(self.parents.is_empty(), "unfinished nodes");
+        match self.children.pop() {
+            Some(GreenElement::Node(node)) if self.children.is_empty() => {
+                Rc::try_unwrap(node).unwrap_or_else(|node| (*node).clone())
+            }
+            _ => panic!("the builder must produce exactly one root node"),
+        }
+    }
+}
+
+/// A node of the tree positioned in the source, with access to its parent.
+#[derive(Clone)]
+pub struct SyntaxNode(Rc<NodeData>);
+
+struct NodeData {
+    green: Rc<GreenNode>,
+    offset: usize,
+    parent: Option<SyntaxNode>,
+}
+
+/// A token positioned in the source.
+#[derive(Clone)]
+pub struct SyntaxToken {
+    green: Rc<GreenToken>,
+    offset: usize,
+    parent: SyntaxNode,
+}
+
+#[derive(Clone)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+impl SyntaxNode {
+    pub fn new_root(green: GreenNode) -> Self {
+        Self(Rc::new(NodeData {
+            green: Rc::new(green),
+            offset: 0,
+            parent: None,
+        }))
+    }
+
+    pub fn kind(&self) -> SyntaxKind {
+        self.0.green.kind
+    }
+
+    pub fn green(&self) -> &GreenNode {
+        &self.0.green
+    }
+
+    pub fn parent(&self) -> Option<&SyntaxNode> {
+        self.0.parent.as_ref()
+    }
+
+    /// Byte range of the node's text, trivia included.
+    pub fn text_range(&self) -> Range<usize> {
+        self.0.offset..self.0.offset + self.0.green.width
+    }
+
+    /// The exact source text of the node.
+    pub fn text(&self) -> String {
+        let mut text = String::with_capacity(self.0.green.width);
+        for token in self.tokens() {
+            text.push_str(token.text());
+        }
+        text
+    }
+
+    pub fn children_with_tokens(&self) -> Vec<SyntaxElement> {
+        let mut offset = self.0.offset;
+        let mut children = Vec::with_capacity(self.0.green.children.len());
+        for child in &self.0.green.children {
+            children.push(match child {
+                GreenElement::Node(green) => SyntaxElement::Node(SyntaxNode(Rc::new(NodeData {
+                    green: green.clone(),
+                    offset,
+                    parent: Some(self.clone()),
+                }))),
+                GreenElement::Token(green) => SyntaxElement::Token(SyntaxToken {
+                    green: green.clone(),
+                    offset,
+                    parent: self.clone(),
+                }),
+            });
+            offset += child.width();
+        }
+        children
+    }
+
+    /// The child nodes, skipping tokens.
+    pub fn children(&self) -> Vec<SyntaxNode> {
+        self.children_with_tokens()
+            .into_iter()
+            .filter_map(|child| match child {
+                SyntaxElement::Node(node) => Some(node),
+                SyntaxElement::Token(_) => None,
+            })
+            .collect()
+    }
+
+    /// Every token in the node, in source order.
+    pub fn tokens(&self) -> Vec<SyntaxToken> {
+        let mut tokens = Vec::new();
+        for child in self.children_with_tokens() {
+            match child {
+                SyntaxElement::Node(node) => tokens.extend(node.tokens()),
+                SyntaxElement::Token(token) => tokens.push(token),
+            }
+        }
+        tokens
+    }
+
+    /// Renders the tree with one node or token per line, e.g.
+    /// `Let@0..9` followed by its indented children.
+    pub fn debug_tree(&self) -> String {
+        let mut out = String::new();
+        self.write_tree(0, &mut out);
+        out
+    }
+
+    fn write_tree(&self, depth: usize, out: &mut String) {
+        let range = self.text_range();
+        out.push_str(&format!(
+            "{}{:?}@{}..{}\n",
+            "  ".repeat(depth),
+            self.kind(),
+            range.start,
+            range.end
+        ));
+        for child in self.children_with_tokens() {
+            match child {
+                SyntaxElement::Node(node) => node.write_tree(depth + 1, out),
+                SyntaxElement::Token(token) => {
+                    let range = token.text_range();
+                    out.push_str(&format!(
+                        "{}{:?}@{}..{} {:?}\n",
+                        "  ".repeat(depth + 1),
+                        token.kind(),
+                        range.start,
+                        range.end,
+                        token.text()
+                    ));
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text())
+    }
+}
+
+impl fmt::Debug for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let range = self.text_range();
+        write!(f, "{:?}@{}..{}", self.kind(), range.start, range.end)
+    }
+}
+
+impl SyntaxToken {
+    pub fn kind(&self) -> &TokenKind {
+        &self.green.kind
+    }
+
+    pub fn text(&self) -> &str {
+        &self.green.text
+    }
+
+    pub fn text_range(&self) -> Range<usize> {
+        self.offset..self.offset + self.green.text.len()
+    }
+
+    pub fn parent(&self) -> &SyntaxNode {
+        &self.parent
+    }
+
+    /// Whitespace and comments, which carry no meaning.
+    pub fn is_trivia(&self) -> bool {
+        is_trivia(&self.green.kind)
+    }
+}
+
+impl fmt::Debug for SyntaxToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let range = self.text_range();
+        write!(f, "{:?}@{}..{} {:?}", self.kind(), range.start, range.end, self.text())
+    }
+}
+
+fn is_trivia(kind: &TokenKind) -> bool {
+    matches!(kind, TokenKind::Whitespace(_) | TokenKind::Comment(_))
+}
+
+/// A problem found while building the tree. The tree is still complete:
+/// the offending tokens are kept in [`SyntaxKind::Error`] nodes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxError {
+    pub message: String,
+    pub range: Range<usize>,
+}
+
+/// The result of [`parse`]: a tree covering the whole source and any errors
+/// found in it.
+pub struct Parse {
+    green: GreenNode,
+    errors: Vec<SyntaxError>,
+}
+
+impl Parse {
+    pub fn syntax(&self) -> SyntaxNode {
+        SyntaxNode::new_root(self.green.clone())
+    }
+
+    pub fn green(&self) -> &GreenNode {
+        &self.green
+    }
+
+    pub fn errors(&self) -> &[SyntaxError] {
+        &self.errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Parser, S};
+
+    const SOURCES: &[&str] = &[
+        "let x = 1 + 2 * -3; // comment\n",
+        "func fib(n) {\n    if (n < 2) { return n }\n    return fib(n - 1) + fib(n - 2)\n}\n",
+        "let m = {\"a\": [1, 2][0], \"b\": f(x)!}\nprint(m.a, \"s\".upper())\n",
+        "import \"lib.mc\" as lib\nfrom \"lib.mc\" import a, b\nexport let c = 1\n",
+        "while x <  3 { let y = x }\n\n\ttest \"t\" { assert(true) };;\n",
+    ];
+
+    /// The statements of `source` as S-expressions, as the interpreter's
+    /// parser sees them.
+    fn ast(source: &str) -> Vec<String> {
+        let mut parser = Parser::new(source);
+        std::iter::from_fn(|| parser.parse_statement())
+            .map(|statement| statement.unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn reproduces_the_source_exactly() {
+        for source in SOURCES {
+            let parse = parse(source);
+            assert!(parse.errors().is_empty(), "{:?}: {:?}", source, parse.errors());
+            assert_eq!(parse.syntax().text(), *source);
+        }
+    }
+
+    #[test]
+    fn reproduces_invalid_source_and_reports_errors() {
+        for source in ["let = 1", "let x = (1 + ", "print(1) @ 2", "export 3"] {
+            let parse = parse(source);
+            assert_eq!(parse.syntax().text(), source);
+            assert!(!parse.errors().is_empty(), "no errors for {:?}", source);
+            assert!(parse.to_ast().is_err());
+        }
+    }
+
+    #[test]
+    fn lowers_to_the_statements_the_parser_gives() {
+        for source in SOURCES {
+            let lowered: Vec<String> = parse(source)
+                .to_ast()
+                .unwrap()
+                .iter()
+                .map(S::to_string)
+                .collect();
+            assert_eq!(lowered, ast(source), "{:?}", source);
+        }
+    }
+
+    #[test]
+    fn positions_nodes_and_tokens() {
+        let root = parse("let x = 1").syntax();
+        let statement = &root.children()[0];
+        assert_eq!(statement.kind(), SyntaxKind::Let);
+        assert_eq!(statement.text_range(), 0..9);
+        let one = statement.tokens().into_iter().last().unwrap();
+        assert_eq!(one.text(), "1");
+        assert_eq!(one.text_range(), 8..9);
+        assert_eq!(one.parent().kind(), SyntaxKind::Literal);
+    }
+}
@@ -0,0 +1,586 @@
+use std::ops::Range;
+
+use super::{is_trivia, GreenBuilder, Parse, SyntaxError, SyntaxKind};
+use crate::{Lexer, TokenKind};
+
+/// Parses `source` into a lossless syntax tree. Parsing never fails: the
+/// tree always reproduces the source exactly, and problems are reported in
+/// [`Parse::errors`].
+///
+/// The grammar follows [`Parser`](crate::Parser) rule for rule, so that
+/// [`Parse::to_ast`] gives the same statements the interpreter would run.
+pub fn parse(source: &str) -> Parse {
+    let mut parser = CstParser::new(source);
+    parser.root();
+    // Lexer errors are found before parsing starts.
+    parser.errors.sort_by_key(|error| error.range.start);
+    Parse {
+        green: parser.builder.finish(),
+        errors: parser.errors,
+    }
+}
+
+struct CstParser<'s> {
+    tokens: Vec<(TokenKind, &'s str, Range<usize>)>,
+    pos: usize,
+    builder: GreenBuilder,
+    errors: Vec<SyntaxError>,
+}
+
+impl<'s> CstParser<'s> {
+    fn new(source: &'s str) -> Self {
+        let mut lexer = Lexer::with_trivia(source);
+        let mut tokens = Vec::new();
+        let mut start = 0;
+        let mut errors = Vec::new();
+        while let Some(token) = lexer.next() {
+            let end = lexer.offset();
+            let kind = match token {
+                Ok(token) => token.kind,
+                Err(err) => {
+                    errors.push(SyntaxError {
+                        message: err.to_string(),
+                        range: start..end,
+                    });
+                    TokenKind::Unknown(source[start..end].to_string())
+                }
+            };
+            // The lexer reads ahead over spaces when looking for the `=` of
+            // operators such as `<=`, which belong in a trivia token of
+            // their own when the `=` isn't there.
+            let text = &source[start..end];
+            let trimmed = match kind {
+                TokenKind::Whitespace(_) | TokenKind::Unknown(_) => text,
+                _ => text.trim_end_matches([' ', '\t']),
+            };
+            let split = start + trimmed.len();
+            tokens.push((kind, trimmed, start..split));
+            if split < end {
+                let space = &source[split..end];
+                tokens.push((TokenKind::Whitespace(space.to_string()), space, split..end));
+            }
+            start = end;
+        }
+
+        Self {
+            tokens,
+            pos: 0,
+            builder: GreenBuilder::new(),
+            errors,
+        }
+    }
+
+    fn root(&mut self) {
+        self.builder.start_node(SyntaxKind::Root);
+        while let Some(kind) = self.peek() {
+            match kind {
+                TokenKind::NewLine | TokenKind::Semicolon => self.bump(),
+                _ => self.statement(),
+            }
+        }
+        self.eat_trivia();
+        self.builder.finish_node();
+    }
+
+    fn statement(&mut self) {
+        match self.peek() {
+            None => {}
+            Some(kind) if starts_expression(kind) => self.operand(),
+            Some(TokenKind::Let) => self.let_statement(),
+            Some(TokenKind::LeftBrace) => {
+                self.builder.start_node(SyntaxKind::Block);
+                self.bump();
+                self.block_body();
+                self.builder.finish_node();
+            }
+            Some(TokenKind::Fun) => self.function(),
+            Some(TokenKind::If) => self.if_expression(),
+            Some(TokenKind::Return) => {
+                self.builder.start_node(SyntaxKind::Return);
+                self.bump();
+                self.operand();
+                self.builder.finish_node();
+            }
+            Some(TokenKind::While) => {
+                self.builder.start_node(SyntaxKind::While);
+                self.bump();
+                self.operand();
+                self.block();
+                self.builder.finish_node();
+            }
+            Some(TokenKind::Test) => self.test(),
+            Some(TokenKind::Import | TokenKind::From) => self.import(),
+            Some(TokenKind::Export) => {
+                self.builder.start_node(SyntaxKind::Export);
+                self.bump();
+                match self.peek() {
+                    Some(TokenKind::Let | TokenKind::Fun) => self.statement(),
+                    _ => self.error("Only `let` and `func` declarations can be exported"),
+                }
+                self.builder.finish_node();
+            }
+            // Already reported by the lexer.
+            Some(TokenKind::Unknown(_)) => {
+                self.builder.start_node(SyntaxKind::Error);
+                self.bump();
+                self.builder.finish_node();
+            }
+            Some(kind) => {
+                let message = format!("Unexpected token: {:?}", kind);
+                self.error_and_bump(message);
+            }
+        }
+    }
+
+    fn let_statement(&mut self) {
+        self.builder.start_node(SyntaxKind::Let);
+        self.bump();
+        if self.name() && self.expect(TokenKind::Equal, "Expected Equal") {
+            if self.at(&TokenKind::LeftBrace) {
+                // `let x = {` starts a map when a key or `}` follows, and a block otherwise.
+                let checkpoint = self.builder.checkpoint();
+                self.bump();
+                self.skip_newlines();
+                match self.peek() {
+                    Some(TokenKind::String(_) | TokenKind::RightBrace) => {
+                        self.builder.start_node_at(checkpoint, SyntaxKind::Map);
+                        self.map_body();
+                    }
+                    _ => {
+                        self.builder.start_node_at(checkpoint, SyntaxKind::Block);
+                        self.block_body();
+                    }
+                }
+                self.builder.finish_node();
+            } else {
+                self.operand();
+            }
+        }
+        self.builder.finish_node();
+    }
+
+    fn function(&mut self) {
+        self.builder.start_node(SyntaxKind::Func);
+        self.bump();
+        if self.name() {
+            if self.at(&TokenKind::LeftParen) {
+                self.builder.start_node(SyntaxKind::Params);
+                self.arguments();
+                self.builder.finish_node();
+                self.block();
+            } else {
+                self.error("Expected LeftParen after function name");
+            }
+        }
+        self.builder.finish_node();
+    }
+
+    fn test(&mut self) {
+        self.builder.start_node(SyntaxKind::Test);
+        self.bump();
+        if matches!(self.peek(), Some(TokenKind::String(_))) {
+            self.builder.start_node(SyntaxKind::Literal);
+            self.bump();
+            self.builder.finish_node();
+            self.block();
+        } else {
+            self.error("Expected the test's name");
+        }
+        self.builder.finish_node();
+    }
+
+    fn import(&mut self) {
+        self.builder.start_node(SyntaxKind::Import);
+        let from = self.at(&TokenKind::From);
+        self.bump();
+        if matches!(self.peek(), Some(TokenKind::String(_))) {
+            self.builder.start_node(SyntaxKind::Literal);
+            self.bump();
+            self.builder.finish_node();
+            if !from {
+                if self.expect(TokenKind::As, "Expected `as`") {
+                    self.name();
+                }
+            } else if self.expect(TokenKind::Import, "Expected `import`") {
+                while self.name() && self.at(&TokenKind::Comma) {
+                    self.bump();
+                }
+            }
+        } else {
+            self.error("Expected the module's path");
+        }
+        self.builder.finish_node();
+    }
+
+    fn if_expression(&mut self) {
+        self.builder.start_node(SyntaxKind::If);
+        self.bump();
+        self.operand();
+        self.block();
+        self.skip_newlines();
+        if self.at(&TokenKind::Else) {
+            self.builder.start_node(SyntaxKind::Else);
+            self.bump();
+            self.block();
+            self.builder.finish_node();
+        }
+        self.builder.finish_node();
+    }
+
+    /// A `{ ... }` block, possibly on a later line.
+    fn block(&mut self) {
+        self.skip_newlines();
+        if self.at(&TokenKind::LeftBrace) {
+            self.builder.start_node(SyntaxKind::Block);
+            self.bump();
+            self.block_body();
+            self.builder.finish_node();
+        } else {
+            self.error("Expected LeftBrace");
+        }
+    }
+
+    /// The statements of a block after its `{`, up to and including the `}`.
+    fn block_body(&mut self) {
+        loop {
+            match self.peek() {
+                None => return self.error("Unexpected end of input"),
+                Some(TokenKind::NewLine | TokenKind::Semicolon) => self.bump(),
+                Some(TokenKind::RightBrace) => return self.bump(),
+                Some(_) => self.statement(),
+            }
+        }
+    }
+
+    /// An expression that must be present.
+    fn operand(&mut self) {
+        if !self.expression(0) {
+            self.error("Unexpected end of input");
+        }
+    }
+
+    /// Parses an expression whose operators bind at least as tightly as
+    /// `min_bp`, returning false at the end of input.
+    fn expression(&mut self, min_bp: u8) -> bool {
+        let checkpoint = self.builder.checkpoint();
+        match self.peek() {
+            None => return false,
+            Some(TokenKind::NewLine) => {
+                self.bump();
+                return self.expression(0);
+            }
+            Some(TokenKind::Plus | TokenKind::Minus) => {
+                self.builder.start_node(SyntaxKind::Prefix);
+                self.bump();
+                self.operand_at(1);
+                self.builder.finish_node();
+            }
+            Some(TokenKind::LeftParen) => {
+                self.builder.start_node(SyntaxKind::Paren);
+                self.bump();
+                if !self.expression(0) {
+                    self.builder.finish_node();
+                    return false;
+                }
+                self.expect(TokenKind::RightParen, "Expected RightParen");
+                self.builder.finish_node();
+                self.postfix(checkpoint);
+            }
+            Some(TokenKind::LeftBracket) => {
+                self.builder.start_node(SyntaxKind::List);
+                self.bump();
+                self.list_body();
+                self.builder.finish_node();
+                self.postfix(checkpoint);
+            }
+            Some(TokenKind::LeftBrace) => {
+                self.builder.start_node(SyntaxKind::Map);
+                self.bump();
+                self.map_body();
+                self.builder.finish_node();
+                self.postfix(checkpoint);
+            }
+            Some(TokenKind::Unknown(_)) => {
+                self.builder.start_node(SyntaxKind::Error);
+                self.bump();
+                self.builder.finish_node();
+                return true;
+            }
+            Some(kind) => {
+                let kind = match kind {
+                    TokenKind::Identifier(_) => SyntaxKind::Name,
+                    _ => SyntaxKind::Literal,
+                };
+                self.builder.start_node(kind);
+                self.bump();
+                self.builder.finish_node();
+                self.postfix(checkpoint);
+            }
+        }
+
+        while let Some(operator) = self.peek() {
+            if let Some(l_bp) = postfix_binding_power(operator) {
+                if l_bp < min_bp {
+                    break;
+                }
+                self.builder.start_node_at(checkpoint, SyntaxKind::Postfix);
+                self.bump();
+                self.builder.finish_node();
+                continue;
+            }
+
+            if let Some((l_bp, r_bp)) = infix_binding_power(operator) {
+                if l_bp < min_bp {
+                    break;
+                }
+                self.builder.start_node_at(checkpoint, SyntaxKind::Binary);
+                self.bump();
+                self.operand_at(r_bp);
+                self.builder.finish_node();
+                continue;
+            }
+
+            break;
+        }
+        true
+    }
+
+    fn operand_at(&mut self, min_bp: u8) {
+        if !self.expression(min_bp) {
+            self.error("Unexpected end of input");
+        }
+    }
+
+    /// Calls, member accesses and indexing applied to the expression started
+    /// at `checkpoint`.
+    fn postfix(&mut self, checkpoint: super::Checkpoint) {
+        loop {
+            match self.peek() {
+                Some(TokenKind::LeftParen) => {
+                    self.builder.start_node_at(checkpoint, SyntaxKind::Call);
+                    self.builder.start_node(SyntaxKind::Args);
+                    self.arguments();
+                    self.builder.finish_node();
+                    self.builder.finish_node();
+                }
+                Some(TokenKind::Dot) => {
+                    self.builder.start_node_at(checkpoint, SyntaxKind::Member);
+                    self.bump();
+                    if !self.at_identifier() {
+                        self.error("Expected Identifier after '.'");
+                        self.builder.finish_node();
+                        return;
+                    }
+                    self.name();
+                    self.builder.finish_node();
+                }
+                Some(TokenKind::LeftBracket) => {
+                    self.builder.start_node_at(checkpoint, SyntaxKind::Index);
+                    self.bump();
+                    self.operand();
+                    self.expect(TokenKind::RightBracket, "Expected RightBracket");
+                    self.builder.finish_node();
+                }
+                _ => return,
+            }
+        }
+    }
+
+    /// A parenthesised, comma separated list of expressions.
+    fn arguments(&mut self) {
+        self.bump();
+        if self.at(&TokenKind::RightParen) {
+            return self.bump();
+        }
+        loop {
+            self.skip_newlines();
+            self.operand();
+            self.skip_newlines();
+            match self.peek() {
+                Some(TokenKind::Comma) => self.bump(),
+                Some(TokenKind::RightParen) => return self.bump(),
+                None => return self.error("Unexpected end of input"),
+                Some(_) => return self.error("Expected ',' or ')'"),
+            }
+        }
+    }
+
+    /// The items of a list after its `[`, up to and including the `]`.
+    fn list_body(&mut self) {
+        loop {
+            self.skip_newlines();
+            if self.at(&TokenKind::RightBracket) {
+                return self.bump();
+            }
+            self.operand();
+            self.skip_newlines();
+            match self.peek() {
+                Some(TokenKind::Comma) => self.bump(),
+                Some(TokenKind::RightBracket) => return self.bump(),
+                None => return self.error("Expected ',' or ']', Got: EOF"),
+                Some(_) => return self.error("Expected ',' or ']'"),
+            }
+        }
+    }
+
+    /// The entries of a map after its `{`, up to and including the `}`.
+    fn map_body(&mut self) {
+        loop {
+            self.skip_newlines();
+            match self.peek() {
+                Some(TokenKind::RightBrace) => return self.bump(),
+                Some(TokenKind::String(_) | TokenKind::Identifier(_)) => {}
+                None => return self.error("Expected map key, got EOF"),
+                Some(_) => return self.error("Expected map key"),
+            }
+
+            self.builder.start_node(SyntaxKind::Entry);
+            let kind = match self.peek() {
+                Some(TokenKind::Identifier(_)) => SyntaxKind::Name,
+                _ => SyntaxKind::Literal,
+            };
+            self.builder.start_node(kind);
+            self.bump();
+            self.builder.finish_node();
+            if !self.expect(TokenKind::Colon, "Expected Colon after map key") {
+                return self.builder.finish_node();
+            }
+            self.operand();
+            self.builder.finish_node();
+            self.skip_newlines();
+
+            match self.peek() {
+                Some(TokenKind::Comma) => self.bump(),
+                Some(TokenKind::RightBrace) => return self.bump(),
+                None => return self.error("Expected ',' or '}', Got: EOF"),
+                Some(_) => return self.error("Expected ',' or '}'"),
+            }
+        }
+    }
+
+    /// An identifier wrapped in a [`SyntaxKind::Name`] node.
+    fn name(&mut self) -> bool {
+        if !self.at_identifier() {
+            self.error("Expected Identifier");
+            return false;
+        }
+        self.builder.start_node(SyntaxKind::Name);
+        self.bump();
+        self.builder.finish_node();
+        true
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.at(&TokenKind::NewLine) {
+            self.bump();
+        }
+    }
+
+    /// The next token that is not trivia.
+    fn peek(&self) -> Option<&TokenKind> {
+        self.tokens[self.pos..]
+            .iter()
+            .map(|(kind, ..)| kind)
+            .find(|kind| !is_trivia(kind))
+    }
+
+    fn at(&self, kind: &TokenKind) -> bool {
+        self.peek() == Some(kind)
+    }
+
+    fn at_identifier(&self) -> bool {
+        matches!(self.peek(), Some(TokenKind::Identifier(_)))
+    }
+
+    fn expect(&mut self, kind: TokenKind, message: &str) -> bool {
+        if self.at(&kind) {
+            self.bump();
+            true
+        } else {
+            self.error(message);
+            false
+        }
+    }
+
+    /// Adds leading trivia and then the next token to the current node.
+    fn bump(&mut self) {
+        self.eat_trivia();
+        if let Some((kind, text, _)) = self.tokens.get(self.pos) {
+            self.builder.token(kind.clone(), text);
+            self.pos += 1;
+        }
+    }
+
+    fn eat_trivia(&mut self) {
+        while let Some((kind, text, _)) = self.tokens.get(self.pos) {
+            if !is_trivia(kind) {
+                break;
+            }
+            self.builder.token(kind.clone(), text);
+            self.pos += 1;
+        }
+    }
+
+    /// Records an error at the next token, or at the end of input.
+    fn error(&mut self, message: impl Into<String>) {
+        let range = self.tokens[self.pos..]
+            .iter()
+            .find(|(kind, ..)| !is_trivia(kind))
+            .map(|(.., range)| range.clone())
+            .unwrap_or_else(|| {
+                let end = self.tokens.last().map_or(0, |(.., range)| range.end);
+                end..end
+            });
+        self.errors.push(SyntaxError {
+            message: message.into(),
+            range,
+        });
+    }
+
+    fn error_and_bump(&mut self, message: String) {
+        self.error(message);
+        self.builder.start_node(SyntaxKind::Error);
+        self.bump();
+        self.builder.finish_node();
+    }
+}
+
+/// Tokens that start an expression statement, as in [`Parser`](crate::Parser).
+fn starts_expression(kind: &TokenKind) -> bool {
+    matches!(
+        kind,
+        TokenKind::Identifier(_)
+            | TokenKind::Number(_)
+            | TokenKind::String(_)
+            | TokenKind::True
+            | TokenKind::False
+            | TokenKind::Nil
+            | TokenKind::LeftParen
+            | TokenKind::LeftBracket
+            | TokenKind::Minus
+            | TokenKind::Plus
+            | TokenKind::Bang
+    )
+}
+
+fn infix_binding_power(kind: &TokenKind) -> Option<(u8, u8)> {
+    match kind {
+        TokenKind::Plus | TokenKind::Minus => Some((1, 2)),
+        TokenKind::Star | TokenKind::Slash => Some((3, 4)),
+        TokenKind::EqualEqual
+        | TokenKind::BangEqual
+        | TokenKind::Less
+        | TokenKind::LessEqual
+        | TokenKind::Greater
+        | TokenKind::GreaterEqual => Some((5, 5)),
+        TokenKind::Or | TokenKind::And => Some((6, 7)),
+        _ => None,
+    }
+}
+
+fn postfix_binding_power(kind: &TokenKind) -> Option<u8> {
+    match kind {
+        TokenKind::Bang => Some(6),
+        _ => None,
+    }
+}
@@ -0,0 +1,622 @@
+//! A debug adapter for merc, speaking the Debug Adapter Protocol over stdin
+//! and stdout.
+//!
+//! Requests are read on a separate thread so that `pause` and
+//! `setBreakpoints` reach the debugger while the script is running. The
+//! script itself runs on the calling thread, with its output forwarded to
+//! the client as `output` events.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::{BTreeSet, HashMap},
+    fs,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+};
+
+use miette::{Report, Result};
+use serde_json::{json, Value as Json};
+
+use super::{Breakpoints, Debugger, Frontend, Interrupt, Pause, Reason, Resume};
+use crate::{stdlib::Capabilities, Interpreter, ScriptError, Value};
+
+/// Scripts are single-threaded; this is the id of the one thread.
+const THREAD: i64 = 1;
+
+/// Runs the adapter for one debugging session.
+pub fn run() -> Result<()> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut stdin = io::stdin().lock();
+        while let Ok(Some(message)) = read_message(&mut stdin) {
+            if sender.send(message).is_err() {
+                break;
+            }
+        }
+    });
+
+    let session = Session {
+        client: Client::default(),
+        requests: Rc::new(receiver),
+        breakpoints: Rc::default(),
+        disconnected: Rc::default(),
+    };
+    let Some(launch) = session.configure() else {
+        return Ok(());
+    };
+    let exit_code = session.launch(&launch);
+    session.client.event("exited", json!({ "exitCode": exit_code }));
+    session.client.event("terminated", json!({}));
+
+    if !session.disconnected.get() {
+        session.wait_for_disconnect();
+    }
+    Ok(())
+}
+
+/// A request from the client.
+struct Request {
+    seq: i64,
+    command: String,
+    arguments: Json,
+}
+
+impl Request {
+    fn from_json(message: Json) -> Option<Self> {
+        if message["type"] != "request" {
+            return None;
+        }
+        Some(Self {
+            seq: message["seq"].as_i64()?,
+            command: message["command"].as_str()?.to_string(),
+            arguments: message["arguments"].clone(),
+        })
+    }
+}
+
+/// Reads one `Content-Length` framed request, or `None` at the end of input.
+fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Request>> {
+    loop {
+        let mut length = None;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header)? == 0 {
+                return Ok(None);
+            }
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.eq_ignore_ascii_case("Content-Length") {
+                    length = value.trim().parse::<usize>().ok();
+                }
+            }
+        }
+        let Some(length) = length else {
+            continue;
+        };
+
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body)?;
+        let message = serde_json::from_slice(&body)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        if let Some(request) = Request::from_json(message) {
+            return Ok(Some(request));
+        }
+    }
+}
+
+/// Writes responses and events to stdout, numbering them.
+#[derive(Clone, Default)]
+struct Client {
+    seq: Rc<Cell<i64>>,
+}
+
+impl Client {
+    fn send(&self, mut message: Json) {
+        self.seq.set(self.seq.get() + 1);
+        message["seq"] = json!(self.seq.get());
+        let body = message.to_string();
+        let mut stdout = io::stdout().lock();
+        let _ = write!(stdout, "Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let _ = stdout.flush();
+    }
+
+    fn respond(&self, request: &Request, body: Json) {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request.seq,
+            "command": request.command,
+            "success": true,
+            "body": body,
+        }));
+    }
+
+    fn fail(&self, request: &Request, message: impl Into<String>) {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request.seq,
+            "command": request.command,
+            "success": false,
+            "message": message.into(),
+        }));
+    }
+
+    fn event(&self, event: &str, body: Json) {
+        self.send(json!({ "type": "event", "event": event, "body": body }));
+    }
+}
+
+/// Forwards script output to the client as `output` events, a line at a
+/// time.
+struct OutputEvents {
+    client: Client,
+    category: &'static str,
+    buffer: Vec<u8>,
+}
+
+impl OutputEvents {
+    fn send(&mut self, end: usize) {
+        let output: Vec<u8> = self.buffer.drain(..end).collect();
+        let output = String::from_utf8_lossy(&output);
+        self.client
+            .event("output", json!({ "category": self.category, "output": output }));
+    }
+}
+
+impl Write for OutputEvents {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        if let Some(newline) = self.buffer.iter().rposition(|&byte| byte == b'\n') {
+            self.send(newline + 1);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.buffer.is_empty() {
+            self.send(self.buffer.len());
+        }
+        Ok(())
+    }
+}
+
+impl Drop for OutputEvents {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+/// The arguments of a `launch` request.
+struct Launch {
+    program: String,
+    source: String,
+    args: Vec<String>,
+    /// Directories the program may use through `fs`.
+    allow_fs: Vec<PathBuf>,
+    stop_on_entry: bool,
+}
+
+/// State shared between the session and the frontend it hands to the
+/// debugger.
+#[derive(Clone)]
+struct Session {
+    client: Client,
+    requests: Rc<Receiver<Request>>,
+    /// Breakpoint lines by source path, as set before the script starts.
+    breakpoints: Rc<RefCell<HashMap<PathBuf, BTreeSet<usize>>>>,
+    disconnected: Rc<Cell<bool>>,
+}
+
+impl Session {
+    /// Answers requests until the client has launched a program and finished
+    /// setting breakpoints, or `None` if it disconnects first.
+    fn configure(&self) -> Option<Launch> {
+        let mut launch = None;
+        let mut configured = false;
+        while launch.is_none() || !configured {
+            let request = self.requests.recv().ok()?;
+            match request.command.as_str() {
+                "initialize" => {
+                    self.client.respond(
+                        &request,
+                        json!({
+                            "supportsConfigurationDoneRequest": true,
+                            "supportsEvaluateForHovers": true,
+                            "supportsTerminateRequest": true,
+                        }),
+                    );
+                    self.client.event("initialized", json!({}));
+                }
+                "launch" => match Self::launch_arguments(&request.arguments) {
+                    Ok(arguments) => {
+                        self.client.respond(&request, json!({}));
+                        launch = Some(arguments);
+                    }
+                    Err(message) => self.client.fail(&request, message),
+                },
+                "configurationDone" => {
+                    self.client.respond(&request, json!({}));
+                    configured = true;
+                }
+                "setBreakpoints" => {
+                    let path = canonical(request.arguments["source"]["path"].as_str().unwrap_or(""));
+                    let mut breakpoints = self.breakpoints.borrow_mut();
+                    let lines = breakpoints.entry(path).or_default();
+                    self.client.respond(&request, set_breakpoints(&request, lines));
+                }
+                "disconnect" => {
+                    self.client.respond(&request, json!({}));
+                    return None;
+                }
+                _ => self.common(&request),
+            }
+        }
+        launch
+    }
+
+    fn launch_arguments(arguments: &Json) -> std::result::Result<Launch, String> {
+        let program = arguments["program"]
+            .as_str()
+            .ok_or("Missing `program` to debug")?;
+        let source = fs::read_to_string(program)
+            .map_err(|err| format!("Failed to read file: {}: {}", program, err))?;
+        let args = arguments["args"]
+            .as_array()
+            .map(|args| {
+                args.iter()
+                    .filter_map(|arg| arg.as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let allow_fs = arguments["allowFs"]
+            .as_array()
+            .map(|dirs| dirs.iter().filter_map(|dir| dir.as_str().map(PathBuf::from)).collect())
+            .unwrap_or_default();
+        Ok(Launch {
+            program: program.to_string(),
+            source,
+            args,
+            allow_fs,
+            stop_on_entry: arguments["stopOnEntry"].as_bool().unwrap_or(false),
+        })
+    }
+
+    /// Runs the program under the debugger, returning its exit code.
+    fn launch(&self, launch: &Launch) -> i32 {
+        let mut interpreter = Interpreter::default();
+        interpreter.set_file(Path::new(&launch.program));
+        // Stdin carries the protocol, so the program mustn't read it.
+        interpreter.set_capabilities(Capabilities {
+            stdin: false,
+            fs: launch.allow_fs.clone(),
+            ..Capabilities::default()
+        });
+        for (category, stderr) in [("stdout", false), ("stderr", true)] {
+            let events = Box::new(OutputEvents {
+                client: self.client.clone(),
+                category,
+                buffer: Vec::new(),
+            });
+            if stderr {
+                interpreter.set_stderr(events);
+            } else {
+                interpreter.set_stdout(events);
+            }
+        }
+        interpreter.variables.insert(
+            "args".to_string(),
+            Value::List(launch.args.iter().cloned().map(Value::String).collect()),
+        );
+
+        let adapter = Adapter {
+            session: self.clone(),
+            program: canonical(&launch.program),
+            references: Vec::new(),
+        };
+        // Breakpoints in other files pause in the modules the program imports.
+        let debugger = self
+            .breakpoints
+            .borrow()
+            .iter()
+            .flat_map(|(path, lines)| lines.iter().map(move |line| (path, *line)))
+            .fold(Debugger::new(adapter), |debugger, (path, line)| {
+                debugger.breakpoint_in(path, line)
+            })
+            .stop_on_entry(launch.stop_on_entry);
+
+        match debugger.run_in(interpreter, &launch.source) {
+            Ok(()) => 0,
+            Err(report) => {
+                self.client
+                    .event("output", json!({ "category": "stderr", "output": format!("{:?}\n", report) }));
+                exit_code(&report)
+            }
+        }
+    }
+
+    /// Answers requests after the program has ended, until the client
+    /// disconnects.
+    fn wait_for_disconnect(&self) {
+        while let Ok(request) = self.requests.recv() {
+            match request.command.as_str() {
+                "disconnect" => {
+                    self.client.respond(&request, json!({}));
+                    return;
+                }
+                "threads" => self.common(&request),
+                _ => self.client.fail(&request, "The program has ended"),
+            }
+        }
+    }
+
+    /// Requests answered the same way whether or not the program is running.
+    fn common(&self, request: &Request) {
+        match request.command.as_str() {
+            "threads" => self.client.respond(
+                request,
+                json!({ "threads": [{ "id": THREAD, "name": "main" }] }),
+            ),
+            command => self
+                .client
+                .fail(request, format!("Unsupported request '{}'", command)),
+        }
+    }
+}
+
+/// Answers `setBreakpoints`, replacing `lines` with the requested ones.
+fn set_breakpoints(request: &Request, lines: &mut BTreeSet<usize>) -> Json {
+    lines.clear();
+    let breakpoints: Vec<Json> = request.arguments["breakpoints"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|breakpoint| breakpoint["line"].as_u64())
+        .map(|line| {
+            lines.insert(line as usize);
+            json!({ "verified": true, "line": line })
+        })
+        .collect();
+    json!({ "breakpoints": breakpoints })
+}
+
+/// Paths are compared canonicalized, since clients send absolute ones.
+fn canonical(path: &str) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path))
+}
+
+fn exit_code(report: &Report) -> i32 {
+    match report.downcast_ref::<ScriptError>() {
+        Some(ScriptError::Parse(_)) => 65,
+        Some(ScriptError::Runtime(_)) => 70,
+        None => 1,
+    }
+}
+
+/// What a `variablesReference` handed to the client points at. References
+/// are only valid until the program resumes.
+enum Variables {
+    Globals,
+    /// The locals of the function at this index of the stack.
+    Locals(usize),
+    /// The items of a list or map.
+    Children(Value),
+}
+
+/// The frontend that answers the client while the program is paused.
+struct Adapter {
+    session: Session,
+    program: PathBuf,
+    references: Vec<Variables>,
+}
+
+impl Adapter {
+    fn reference(&mut self, variables: Variables) -> usize {
+        self.references.push(variables);
+        self.references.len()
+    }
+
+    /// A variable for the client, expandable if it is a list or map.
+    fn variable(&mut self, name: String, value: &Value) -> Json {
+        let reference = match value {
+            Value::List(items) if !items.is_empty() => self.reference(Variables::Children(value.clone())),
+            Value::Map(entries) if !entries.is_empty() => {
+                self.reference(Variables::Children(value.clone()))
+            }
+            _ => 0,
+        };
+        json!({
+            "name": name,
+            "value": value.repr(),
+            "type": value.type_name(),
+            "variablesReference": reference,
+        })
+    }
+
+    /// The source of a frame running code from `file`, or from the program.
+    fn source(&self, file: Option<&Path>) -> Json {
+        let path = file.unwrap_or(&self.program);
+        let name = path.file_name().map(|name| name.to_string_lossy().into_owned());
+        json!({ "name": name, "path": path })
+    }
+
+    /// Answers a request that doesn't resume the program.
+    fn paused(&mut self, pause: &mut Pause, request: &Request) {
+        let client = self.session.client.clone();
+        match request.command.as_str() {
+            "stackTrace" => {
+                let frames: Vec<Json> = pause
+                    .stack
+                    .iter()
+                    .enumerate()
+                    .rev()
+                    .map(|(id, frame)| {
+                        json!({
+                            "id": id,
+                            "name": frame.function,
+                            "source": self.source(frame.file.as_deref()),
+                            "line": frame.line,
+                            "column": 1,
+                        })
+                    })
+                    .collect();
+                let total = frames.len();
+                client.respond(request, json!({ "stackFrames": frames, "totalFrames": total }));
+            }
+            "scopes" => {
+                let frame = request.arguments["frameId"].as_u64().unwrap_or(0) as usize;
+                let mut scopes = Vec::new();
+                if frame > 0 && frame < pause.stack.len() {
+                    let reference = self.reference(Variables::Locals(frame));
+                    scopes.push(json!({ "name": "Locals", "variablesReference": reference, "expensive": false }));
+                }
+                let reference = self.reference(Variables::Globals);
+                scopes.push(json!({ "name": "Globals", "variablesReference": reference, "expensive": false }));
+                client.respond(request, json!({ "scopes": scopes }));
+            }
+            "variables" => {
+                let reference = request.arguments["variablesReference"].as_u64().unwrap_or(0) as usize;
+                let values: Vec<(String, Value)> = match reference
+                    .checked_sub(1)
+                    .and_then(|index| self.references.get(index))
+                {
+                    Some(Variables::Globals) => sorted(&pause.interpreter.variables),
+                    // The script frame has no locals, so function frames
+                    // start at 1 on the stack but 0 in the interpreter.
+                    Some(Variables::Locals(frame)) => pause
+                        .interpreter
+                        .frames()
+                        .get(frame - 1)
+                        .map(sorted)
+                        .unwrap_or_default(),
+                    Some(Variables::Children(Value::List(items))) => items
+                        .iter()
+                        .enumerate()
+                        .map(|(i, item)| (format!("[{}]", i), item.clone()))
+                        .collect(),
+                    Some(Variables::Children(Value::Map(entries))) => entries
+                        .iter()
+                        .map(|(key, value)| (key.clone(), value.clone()))
+                        .collect(),
+                    _ => return client.fail(request, "Unknown variables reference"),
+                };
+                let variables: Vec<Json> = values
+                    .into_iter()
+                    .map(|(name, value)| self.variable(name, &value))
+                    .collect();
+                client.respond(request, json!({ "variables": variables }));
+            }
+            "evaluate" => {
+                let expression = request.arguments["expression"].as_str().unwrap_or("");
+                match pause.interpreter.eval(expression) {
+                    Ok(value) => {
+                        let variable = self.variable(String::new(), &value);
+                        client.respond(
+                            request,
+                            json!({
+                                "result": variable["value"],
+                                "type": variable["type"],
+                                "variablesReference": variable["variablesReference"],
+                            }),
+                        );
+                    }
+                    Err(err) => client.fail(request, err.to_string()),
+                }
+            }
+            "setBreakpoints" => self.set_breakpoints(request, pause.breakpoints),
+            "pause" => client.respond(request, json!({})),
+            _ => self.session.common(request),
+        }
+    }
+
+    fn set_breakpoints(&self, request: &Request, breakpoints: &mut Breakpoints) {
+        let path = canonical(request.arguments["source"]["path"].as_str().unwrap_or(""));
+        let lines = breakpoints.entry(Some(path)).or_default();
+        self.session.client.respond(request, set_breakpoints(request, lines));
+    }
+
+    /// Acknowledges a request that ends the program.
+    fn stop(&self, request: &Request) {
+        if request.command == "disconnect" {
+            self.session.disconnected.set(true);
+        }
+        self.session.client.respond(request, json!({}));
+    }
+}
+
+impl Frontend for Adapter {
+    fn pause(&mut self, mut pause: Pause) -> Resume {
+        self.references.clear();
+        let reason = match pause.reason {
+            Reason::Entry => "entry",
+            Reason::Breakpoint => "breakpoint",
+            Reason::Step => "step",
+            Reason::Pause => "pause",
+        };
+        self.session.client.event(
+            "stopped",
+            json!({ "reason": reason, "threadId": THREAD, "allThreadsStopped": true }),
+        );
+
+        let requests = self.session.requests.clone();
+        while let Ok(request) = requests.recv() {
+            let resume = match request.command.as_str() {
+                "continue" => Resume::Continue,
+                "next" => Resume::StepOver,
+                "stepIn" => Resume::StepInto,
+                "stepOut" => Resume::StepOut,
+                "disconnect" | "terminate" => {
+                    self.stop(&request);
+                    return Resume::Stop;
+                }
+                _ => {
+                    self.paused(&mut pause, &request);
+                    continue;
+                }
+            };
+            self.session
+                .client
+                .respond(&request, json!({ "allThreadsContinued": true }));
+            return resume;
+        }
+        Resume::Stop
+    }
+
+    fn poll(&mut self, breakpoints: &mut Breakpoints) -> Option<Interrupt> {
+        loop {
+            let request = match self.session.requests.try_recv() {
+                Ok(request) => request,
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => return Some(Interrupt::Stop),
+            };
+            match request.command.as_str() {
+                "pause" => {
+                    self.session.client.respond(&request, json!({}));
+                    return Some(Interrupt::Pause);
+                }
+                "disconnect" | "terminate" => {
+                    self.stop(&request);
+                    return Some(Interrupt::Stop);
+                }
+                "setBreakpoints" => self.set_breakpoints(&request, breakpoints),
+                "threads" => self.session.common(&request),
+                _ => self.session.client.fail(&request, "The program is running"),
+            }
+        }
+    }
+}
+
+/// Variables sorted by name.
+fn sorted(variables: &HashMap<String, Value>) -> Vec<(String, Value)> {
+    let mut variables: Vec<_> = variables
+        .iter()
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    variables.sort_by(|a, b| a.0.cmp(&b.0));
+    variables
+}
+
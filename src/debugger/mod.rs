@@ -0,0 +1,419 @@
+//! A step debugger built on the interpreter's [`Hook`].
+//!
+//! [`Debugger`] decides where to pause: at line breakpoints, in the program
+//! or the modules it imports, or after a step into, over or out of a
+//! function call. What happens while paused is
+//! up to a [`Frontend`], such as the [`Terminal`] behind `merc debug` or the
+//! debug adapter behind `merc dap`.
+
+use std::{
+    cell::Cell,
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use miette::{miette, Report, Result};
+
+use crate::{
+    interpreter::{Hook, Interpreter},
+    Parser, ScriptError, S,
+};
+
+pub mod dap;
+mod terminal;
+
+pub use terminal::Terminal;
+
+/// Breakpoint lines by file, or under `None` for a program that wasn't read
+/// from a file.
+pub type Breakpoints = BTreeMap<Option<PathBuf>, BTreeSet<usize>>;
+
+/// A function call in progress, outermost first.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    /// The function's name, or `<script>` for top-level code.
+    pub function: String,
+    /// The file of the statement the frame is running, which is a module's
+    /// for the functions it defines.
+    pub file: Option<PathBuf>,
+    /// The line of the statement the frame is running.
+    pub line: usize,
+}
+
+/// Why the program paused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    /// Before the first statement.
+    Entry,
+    Breakpoint,
+    Step,
+    /// The frontend asked to pause while the program was running.
+    Pause,
+}
+
+/// How to carry on after a pause.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    /// Run until the next breakpoint.
+    Continue,
+    /// Pause at the next line, entering function calls.
+    StepInto,
+    /// Pause at the next line of the current function.
+    StepOver,
+    /// Pause once the current function returns.
+    StepOut,
+    /// End the program.
+    Stop,
+}
+
+/// The state of a paused program, handed to the frontend.
+pub struct Pause<'p, 'i> {
+    pub interpreter: &'p mut Interpreter<'i>,
+    pub reason: Reason,
+    /// The call stack, outermost first; the last frame is where the program paused.
+    pub stack: &'p [Frame],
+    /// Lines to pause at, which the frontend may change.
+    pub breakpoints: &'p mut Breakpoints,
+}
+
+impl Pause<'_, '_> {
+    /// The file the program paused in.
+    pub fn file(&self) -> Option<&Path> {
+        self.stack.last().and_then(|frame| frame.file.as_deref())
+    }
+
+    /// The line the program paused at.
+    pub fn line(&self) -> usize {
+        self.stack.last().map_or(0, |frame| frame.line)
+    }
+
+    /// The breakpoint lines of the file the program paused in.
+    pub fn file_breakpoints(&mut self) -> &mut BTreeSet<usize> {
+        let file = self.file().map(Path::to_path_buf);
+        self.breakpoints.entry(file).or_default()
+    }
+}
+
+/// What a frontend can ask for while the program runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interrupt {
+    Pause,
+    Stop,
+}
+
+/// What the user sees while the program is paused.
+pub trait Frontend {
+    /// Shows the paused program and waits for the user to say how to go on.
+    fn pause(&mut self, pause: Pause) -> Resume;
+
+    /// Called before every statement, so that a frontend taking requests
+    /// while the program runs can change breakpoints or interrupt it.
+    fn poll(&mut self, _breakpoints: &mut Breakpoints) -> Option<Interrupt> {
+        None
+    }
+}
+
+/// Where to pause next.
+#[derive(Debug, Clone, Copy)]
+enum Mode {
+    Continue,
+    StepInto,
+    StepOver(usize),
+    StepOut(usize),
+}
+
+/// Pauses the program at breakpoints and steps, handing control to a
+/// [`Frontend`] each time.
+pub struct Debugger<F> {
+    frontend: F,
+    breakpoints: Breakpoints,
+    stack: Vec<Frame>,
+    mode: Mode,
+    /// Where the previous statement ran, as file, line and stack depth;
+    /// pausing only happens on reaching a new line.
+    previous: (Option<PathBuf>, usize, usize),
+    stopped: Rc<Cell<bool>>,
+}
+
+impl<F: Frontend> Debugger<F> {
+    pub fn new(frontend: F) -> Self {
+        Self {
+            frontend,
+            breakpoints: Breakpoints::new(),
+            stack: vec![Frame {
+                function: "<script>".to_string(),
+                file: None,
+                line: 0,
+            }],
+            mode: Mode::Continue,
+            previous: (None, 0, 0),
+            stopped: Rc::new(Cell::new(false)),
+        }
+    }
+
+    /// Pauses at `line` of the program being debugged, counting from 1.
+    pub fn breakpoint(mut self, line: usize) -> Self {
+        self.breakpoints.entry(None).or_default().insert(line);
+        self
+    }
+
+    /// Pauses at `line` of the file at `path`, such as a module the program
+    /// imports.
+    pub fn breakpoint_in(mut self, path: &Path, line: usize) -> Self {
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        self.breakpoints.entry(Some(path)).or_default().insert(line);
+        self
+    }
+
+    /// Pauses before the first statement.
+    pub fn stop_on_entry(mut self, stop: bool) -> Self {
+        if stop {
+            self.mode = Mode::StepInto;
+        }
+        self
+    }
+
+    /// Runs `source` under the debugger. Stopping from the frontend ends the
+    /// program quietly; parse and runtime errors are returned.
+    pub fn run(self, source: &str) -> Result<()>
+    where
+        F: 'static,
+    {
+        self.run_in(Interpreter::default(), source)
+    }
+
+    /// Like [`Debugger::run`], in an interpreter set up by the caller, e.g.
+    /// with its output redirected.
+    pub fn run_in(mut self, mut interpreter: Interpreter<'_>, source: &str) -> Result<()>
+    where
+        F: 'static,
+    {
+        // Lines given with `breakpoint` belong to the program's file.
+        let file = interpreter.current_file().map(Path::to_path_buf);
+        if file.is_some() {
+            if let Some(lines) = self.breakpoints.remove(&None) {
+                self.breakpoints.entry(file.clone()).or_default().extend(lines);
+            }
+        }
+        self.stack[0].file = file;
+
+        let mut parser = Parser::new(source);
+        let mut program = Vec::new();
+        while let Some(statement) = parser.parse_statement() {
+            program.push(statement.map_err(|e| Report::new(ScriptError::Parse(e)))?);
+        }
+
+        let stopped = self.stopped.clone();
+        interpreter.set_source(source);
+        interpreter.set_hook(self);
+        for statement in &program {
+            if let Err(err) = interpreter.execute(statement) {
+                return if stopped.get() { Ok(()) } else { Err(err) };
+            }
+        }
+        Ok(())
+    }
+
+    fn reason(&self, file: &Option<PathBuf>, line: usize) -> Option<Reason> {
+        let depth = self.stack.len();
+        // Further statements on the line that just ran don't pause again.
+        let (previous_file, previous_line, previous_depth) = &self.previous;
+        if (file, line, depth) == (previous_file, *previous_line, *previous_depth) {
+            return None;
+        }
+        if self.breakpoints.get(file).is_some_and(|lines| lines.contains(&line)) {
+            return Some(Reason::Breakpoint);
+        }
+        let step = match self.mode {
+            Mode::Continue => false,
+            Mode::StepInto => true,
+            Mode::StepOver(from) => depth <= from,
+            Mode::StepOut(from) => depth < from,
+        };
+        let first = (*previous_line, *previous_depth) == (0, 0);
+        step.then_some(if first { Reason::Entry } else { Reason::Step })
+    }
+
+    /// Marks the program as stopped, returning the error that unwinds it.
+    fn stop(&self) -> Report {
+        self.stopped.set(true);
+        miette!("Stopped by the debugger")
+    }
+}
+
+impl<F: Frontend> Hook for Debugger<F> {
+    fn statement(&mut self, interpreter: &mut Interpreter<'_>, statement: &S) -> Result<()> {
+        let Some(line) = statement.first_token().map(|token| token.row) else {
+            return Ok(());
+        };
+        let file = interpreter.current_file().map(Path::to_path_buf);
+        if let Some(frame) = self.stack.last_mut() {
+            frame.file.clone_from(&file);
+            frame.line = line;
+        }
+        let reason = match self.frontend.poll(&mut self.breakpoints) {
+            Some(Interrupt::Pause) => Some(Reason::Pause),
+            Some(Interrupt::Stop) => return Err(self.stop()),
+            None => self.reason(&file, line),
+        };
+        self.previous = (file, line, self.stack.len());
+        let Some(reason) = reason else {
+            return Ok(());
+        };
+
+        let resume = self.frontend.pause(Pause {
+            interpreter,
+            reason,
+            stack: &self.stack,
+            breakpoints: &mut self.breakpoints,
+        });
+        let depth = self.stack.len();
+        self.mode = match resume {
+            Resume::Continue => Mode::Continue,
+            Resume::StepInto => Mode::StepInto,
+            Resume::StepOver => Mode::StepOver(depth),
+            Resume::StepOut => Mode::StepOut(depth),
+            Resume::Stop => return Err(self.stop()),
+        };
+        Ok(())
+    }
+
+    fn enter(&mut self, _interpreter: &mut Interpreter<'_>, name: &str) {
+        let caller = self.stack.last();
+        let frame = Frame {
+            function: name.to_string(),
+            file: caller.and_then(|frame| frame.file.clone()),
+            line: caller.map_or(0, |frame| frame.line),
+        };
+        self.stack.push(frame);
+    }
+
+    fn exit(&mut self, _interpreter: &mut Interpreter<'_>, _name: &str) {
+        self.stack.pop();
+        // So that calling the same one-line function again pauses again.
+        self.previous = (None, 0, usize::MAX);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::VecDeque};
+
+    use crate::output::OutputBuffer;
+
+    use super::*;
+
+    /// What the program looked like at one pause.
+    #[derive(Debug, PartialEq)]
+    struct Stop {
+        reason: Reason,
+        functions: Vec<String>,
+        line: usize,
+        /// The sorted names of the locals, or of the globals at the top level.
+        names: Vec<String>,
+    }
+
+    /// A frontend that records every pause and resumes as scripted, then
+    /// continues once the script runs out.
+    struct Scripted {
+        resumes: VecDeque<Resume>,
+        stops: Rc<RefCell<Vec<Stop>>>,
+    }
+
+    impl Frontend for Scripted {
+        fn pause(&mut self, pause: Pause) -> Resume {
+            let scope = match pause.interpreter.locals() {
+                Some(locals) => locals,
+                None => &pause.interpreter.variables,
+            };
+            let mut names: Vec<String> = scope.keys().cloned().collect();
+            names.sort();
+            self.stops.borrow_mut().push(Stop {
+                reason: pause.reason,
+                functions: pause.stack.iter().map(|frame| frame.function.clone()).collect(),
+                line: pause.line(),
+                names,
+            });
+            self.resumes.pop_front().unwrap_or(Resume::Continue)
+        }
+    }
+
+    const PROGRAM: &str = "let base = 10
+func add(a, b) {
+    let sum = a + b
+    sum + base
+}
+let x = add(1, 2)
+print(x)";
+
+    /// Debugs [`PROGRAM`] with `setup` applied, returning the pauses and the output.
+    fn debug(
+        resumes: &[Resume],
+        setup: impl FnOnce(Debugger<Scripted>) -> Debugger<Scripted>,
+    ) -> (Vec<Stop>, String) {
+        let stops = Rc::new(RefCell::new(Vec::new()));
+        let frontend = Scripted {
+            resumes: resumes.iter().copied().collect(),
+            stops: stops.clone(),
+        };
+        let output = OutputBuffer::new();
+        let mut interpreter = Interpreter::default();
+        interpreter.set_stdout(Box::new(output.clone()));
+        setup(Debugger::new(frontend)).run_in(interpreter, PROGRAM).unwrap();
+        let stops = stops.take();
+        (stops, output.contents())
+    }
+
+    fn stop(reason: Reason, functions: &[&str], line: usize, names: &[&str]) -> Stop {
+        Stop {
+            reason,
+            functions: functions.iter().map(|f| f.to_string()).collect(),
+            line,
+            names: names.iter().map(|n| n.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn breakpoints_pause_with_the_function_locals_visible() {
+        let (stops, output) = debug(&[], |debugger| debugger.breakpoint(4));
+        assert_eq!(
+            stops,
+            [stop(Reason::Breakpoint, &["<script>", "add"], 4, &["a", "b", "sum"])]
+        );
+        assert_eq!(output, "13\n");
+    }
+
+    #[test]
+    fn top_level_breakpoints_see_the_globals_defined_so_far() {
+        let (stops, _) = debug(&[], |debugger| debugger.breakpoint(7));
+        assert_eq!(stops, [stop(Reason::Breakpoint, &["<script>"], 7, &["add", "base", "x"])]);
+    }
+
+    #[test]
+    fn stepping_follows_calls_and_returns() {
+        let resumes = [Resume::StepInto, Resume::StepOver, Resume::StepOut];
+        let (stops, output) = debug(&resumes, |debugger| debugger.breakpoint(6));
+        let lines: Vec<(Reason, usize, usize)> =
+            stops.iter().map(|stop| (stop.reason, stop.functions.len(), stop.line)).collect();
+        assert_eq!(
+            lines,
+            [
+                (Reason::Breakpoint, 1, 6),
+                (Reason::Step, 2, 3),
+                (Reason::Step, 2, 4),
+                (Reason::Step, 1, 7),
+            ]
+        );
+        assert_eq!(output, "13\n");
+    }
+
+    #[test]
+    fn stopping_ends_the_program_quietly() {
+        let (stops, output) =
+            debug(&[Resume::Stop], |debugger| debugger.stop_on_entry(true));
+        assert_eq!(stops, [stop(Reason::Entry, &["<script>"], 1, &[])]);
+        assert_eq!(output, "");
+    }
+}
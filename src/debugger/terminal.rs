@@ -0,0 +1,262 @@
+use std::path::Path;
+
+use colored::*;
+use miette::{IntoDiagnostic, Result};
+use rustyline::{error::ReadlineError, DefaultEditor};
+
+use super::{Frontend, Pause, Reason, Resume};
+use crate::{highlight::Highlighter, modules};
+
+/// Debugger commands with their short alias, argument and description.
+pub const COMMANDS: &[(&str, &str, &str, &str)] = &[
+    ("continue", "c", "", "Run until the next breakpoint"),
+    ("step", "s", "", "Run to the next line, stepping into calls"),
+    ("next", "n", "", "Run to the next line of this function"),
+    ("finish", "f", "", "Run until this function returns"),
+    ("break", "b", "[line]", "Set a breakpoint in this file, or list them without a line"),
+    ("delete", "d", "<line>", "Remove a breakpoint from this file"),
+    ("locals", "l", "", "Show the variables of the current function"),
+    ("print", "p", "<expr>", "Evaluate an expression in the current scope"),
+    ("watch", "w", "<expr>", "Show an expression's value at every pause"),
+    ("unwatch", "", "<n>", "Stop watching expression number n"),
+    ("backtrace", "bt", "", "Show the function calls in progress"),
+    ("list", "", "", "Show the code around the current line"),
+    ("help", "h", "", "Show this list of commands"),
+    ("quit", "q", "", "End the program"),
+];
+
+/// Runs the debugger in the terminal, reading commands at a `(debug)` prompt.
+/// Lines are shown from the file the program paused in, which is a module's
+/// while running code imported from it.
+pub struct Terminal {
+    editor: DefaultEditor,
+    /// The name of the program, for code that isn't from a file.
+    file: String,
+    watches: Vec<String>,
+    /// The previous command, repeated by an empty line.
+    last: String,
+}
+
+impl Terminal {
+    pub fn new(file: &str) -> Result<Self> {
+        Ok(Self {
+            editor: DefaultEditor::new().into_diagnostic()?,
+            file: file.to_string(),
+            watches: Vec::new(),
+            last: String::new(),
+        })
+    }
+
+    /// How to refer to `file` in messages.
+    fn name(&self, file: Option<&Path>) -> String {
+        file.map_or_else(|| self.file.clone(), modules::display)
+    }
+
+    /// The lines of the file the program paused in.
+    fn lines<'p>(pause: &'p Pause) -> Vec<&'p str> {
+        pause.interpreter.current_source().unwrap_or_default().lines().collect()
+    }
+
+    fn show_location(&self, pause: &Pause) {
+        let frame = pause.stack.last().expect("the script frame is never popped");
+        let what = match pause.reason {
+            Reason::Entry => "Paused at the start",
+            Reason::Breakpoint => "Breakpoint",
+            Reason::Step => "Stopped",
+            Reason::Pause => "Paused",
+        };
+        println!(
+            "{} {} {}:{}",
+            format!("{} in", what).bright_green(),
+            frame.function.bright_blue(),
+            self.name(frame.file.as_deref()),
+            frame.line
+        );
+        self.show_lines(pause, frame.line, frame.line);
+    }
+
+    fn show_lines(&self, pause: &Pause, from: usize, to: usize) {
+        let highlighter = Highlighter::global();
+        let lines = Self::lines(pause);
+        let file = pause.file().map(Path::to_path_buf);
+        let breakpoints = pause.breakpoints.get(&file);
+        for number in from.max(1)..=to.min(lines.len()) {
+            let marker = if number == pause.line() { "→" } else { " " };
+            let breakpoint = breakpoints.is_some_and(|lines| lines.contains(&number));
+            println!(
+                "{}{} {} │ {}",
+                if breakpoint { "●" } else { " " }.bright_red(),
+                marker.bright_yellow(),
+                format!("{:>4}", number).dimmed(),
+                highlighter.highlight(lines[number - 1])
+            );
+        }
+    }
+
+    fn show_watches(&self, pause: &mut Pause) {
+        for (i, watch) in self.watches.iter().enumerate() {
+            let value = match pause.interpreter.eval(watch) {
+                Ok(value) => value.repr(),
+                Err(err) => err.to_string().bright_red().to_string(),
+            };
+            println!("{} {} = {}", format!("{}:", i + 1).dimmed(), watch, value);
+        }
+    }
+
+    /// Runs one command, returning how to resume if it ends the pause.
+    fn command(&mut self, pause: &mut Pause, input: &str) -> Option<Resume> {
+        let (name, arg) = match input.split_once(char::is_whitespace) {
+            Some((name, arg)) => (name, arg.trim()),
+            None => (input, ""),
+        };
+        let command = COMMANDS
+            .iter()
+            .find(|(command, alias, ..)| name == *command || (!alias.is_empty() && name == *alias))
+            .map(|(command, ..)| *command);
+
+        match command {
+            Some("continue") => return Some(Resume::Continue),
+            Some("step") => return Some(Resume::StepInto),
+            Some("next") => return Some(Resume::StepOver),
+            Some("finish") => return Some(Resume::StepOut),
+            Some("quit") => return Some(Resume::Stop),
+            Some("break") if arg.is_empty() => {
+                if pause.breakpoints.values().all(|lines| lines.is_empty()) {
+                    println!("{}", "No breakpoints".bright_yellow());
+                }
+                for (file, lines) in pause.breakpoints.iter() {
+                    for line in lines {
+                        println!("  {}:{}", self.name(file.as_deref()), line);
+                    }
+                }
+            }
+            Some("break") => {
+                let file = self.name(pause.file());
+                match Self::line_number(pause, arg) {
+                    Some(line) => {
+                        pause.file_breakpoints().insert(line);
+                        println!("{} {}:{}", "Breakpoint set at".bright_green(), file, line);
+                    }
+                    None => error(format!("No line {} in {}", arg, file)),
+                }
+            }
+            Some("delete") => {
+                let file = self.name(pause.file());
+                match arg.parse::<usize>() {
+                    Ok(line) if pause.file_breakpoints().remove(&line) => {
+                        println!("{} {}:{}", "Breakpoint removed from".bright_green(), file, line)
+                    }
+                    _ => error(format!("No breakpoint at line {} of {}", arg, file)),
+                }
+            }
+            Some("locals") => {
+                let variables = pause
+                    .interpreter
+                    .locals()
+                    .unwrap_or(&pause.interpreter.variables);
+                let mut variables: Vec<_> = variables.iter().collect();
+                variables.sort_by(|a, b| a.0.cmp(b.0));
+                if variables.is_empty() {
+                    println!("{}", "No variables defined".bright_yellow());
+                }
+                for (name, value) in variables {
+                    println!("  {} = {}", name.bright_blue(), value.repr());
+                }
+            }
+            Some("print") if !arg.is_empty() => match pause.interpreter.eval(arg) {
+                Ok(value) => println!("{}", value.repr()),
+                Err(err) => println!("{:?}", err),
+            },
+            Some("watch") if !arg.is_empty() => {
+                self.watches.push(arg.to_string());
+                self.show_watches(pause);
+            }
+            Some("unwatch") => match arg.parse::<usize>() {
+                Ok(n) if (1..=self.watches.len()).contains(&n) => {
+                    self.watches.remove(n - 1);
+                }
+                _ => error(format!("No watch expression number {}", arg)),
+            },
+            Some("backtrace") => {
+                for (i, frame) in pause.stack.iter().rev().enumerate() {
+                    println!(
+                        "{} {} at {}:{}",
+                        format!("#{}", i).dimmed(),
+                        frame.function.bright_blue(),
+                        self.name(frame.file.as_deref()),
+                        frame.line
+                    );
+                }
+            }
+            Some("list") => {
+                let line = pause.line();
+                self.show_lines(pause, line.saturating_sub(5), line + 5);
+            }
+            Some("help") => print_help(),
+            Some(command) => {
+                let usage = COMMANDS
+                    .iter()
+                    .find(|(name, ..)| *name == command)
+                    .map_or("", |(_, _, usage, _)| usage);
+                error(format!("Usage: {} {}", command, usage));
+            }
+            None => error(format!("Unknown command '{}', type help to list the commands", name)),
+        }
+        None
+    }
+
+    /// Parses a line number that exists in the file the program paused in.
+    fn line_number(pause: &Pause, arg: &str) -> Option<usize> {
+        arg.parse()
+            .ok()
+            .filter(|line| (1..=Self::lines(pause).len()).contains(line))
+    }
+}
+
+impl Frontend for Terminal {
+    fn pause(&mut self, mut pause: Pause) -> Resume {
+        self.show_location(&pause);
+        self.show_watches(&mut pause);
+
+        loop {
+            let input = match self.editor.readline(&format!("{} ", "(debug)".bright_blue())) {
+                Ok(input) => input.trim().to_string(),
+                Err(ReadlineError::Interrupted | ReadlineError::Eof) => return Resume::Stop,
+                Err(err) => {
+                    error(err.to_string());
+                    return Resume::Stop;
+                }
+            };
+            let input = if input.is_empty() {
+                self.last.clone()
+            } else {
+                let _ = self.editor.add_history_entry(&input);
+                self.last = input.clone();
+                input
+            };
+            if input.is_empty() {
+                continue;
+            }
+            if let Some(resume) = self.command(&mut pause, &input) {
+                return resume;
+            }
+        }
+    }
+}
+
+pub fn print_help() {
+    println!("{}", "Debugger commands:".bright_green());
+    for (name, alias, arg, description) in COMMANDS {
+        let name = if alias.is_empty() {
+            name.to_string()
+        } else {
+            format!("{} ({})", name, alias)
+        };
+        let usage = format!("{} {}", name, arg);
+        println!("  {:<22} - {}", usage.trim_end(), description);
+    }
+}
+
+fn error(message: String) {
+    println!("{} {}", "Error:".bright_red(), message);
+}
@@ -0,0 +1,162 @@
+use std::io::Write;
+
+use miette::{miette, Result};
+
+use crate::{
+    convert::TypedFn,
+    interpreter::{Interpreter, Value},
+    stdlib::{Capabilities, Module},
+};
+
+/// Entry point for embedding merc in a Rust program.
+///
+/// An `Engine` keeps its globals and registered host functions alive across
+/// calls to [`Engine::eval`], so scripts can be loaded once and called into
+/// repeatedly.
+#[derive(Default)]
+pub struct Engine {
+    interpreter: Interpreter<'static>,
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Evaluates `source` and returns the value of its last statement.
+    pub fn eval(&mut self, source: &str) -> Result<Value> {
+        self.interpreter.eval(source)
+    }
+
+    /// Calls the merc or native function bound to `name`.
+    pub fn call(&mut self, name: &str, args: &[Value]) -> Result<Value> {
+        let func = self
+            .interpreter
+            .lookup(name)
+            .ok_or_else(|| miette!("Undefined function: {}", name))?;
+        self.interpreter.call_value(&func, args.to_vec())
+    }
+
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        self.interpreter.variables.insert(name.to_string(), value);
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<&Value> {
+        self.interpreter.variables.get(name)
+    }
+
+    /// Registers a host closure taking exactly `arity` arguments.
+    pub fn register_fn<F>(&mut self, name: &str, arity: usize, func: F)
+    where
+        F: Fn(&[Value]) -> Result<Value> + 'static,
+    {
+        self.interpreter.register_fn(name, arity, func);
+    }
+
+    /// Registers a plain Rust function such as `|a: f64, b: f64| a + b`;
+    /// arguments are converted with [`FromValue`](crate::convert::FromValue)
+    /// and the arity is taken from the function's signature.
+    pub fn register_typed_fn<Args, F>(&mut self, name: &str, func: F)
+    where
+        F: TypedFn<Args>,
+    {
+        self.interpreter.register_typed_fn(name, func);
+    }
+
+    /// Makes a native module available to scripts under its name.
+    pub fn register_module(&mut self, module: Module) {
+        self.interpreter.register_module(module);
+    }
+
+    /// Registers a host closure accepting any number of arguments.
+    pub fn register_variadic_fn<F>(&mut self, name: &str, func: F)
+    where
+        F: Fn(&[Value]) -> Result<Value> + 'static,
+    {
+        self.interpreter.register_variadic_fn(name, func);
+    }
+
+    /// Sends everything scripts print to `writer`, e.g. an
+    /// [`OutputBuffer`](crate::output::OutputBuffer) to capture it.
+    pub fn set_stdout(&mut self, writer: Box<dyn Write>) {
+        self.interpreter.set_stdout(writer);
+    }
+
+    pub fn set_stderr(&mut self, writer: Box<dyn Write>) {
+        self.interpreter.set_stderr(writer);
+    }
+
+    /// Limits what scripts may do through the `io` and `fs` modules; by
+    /// default they may read stdin but not use the filesystem.
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+        self.interpreter.set_capabilities(capabilities);
+    }
+
+    pub fn interpreter(&mut self) -> &mut Interpreter<'static> {
+        &mut self.interpreter
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::output::OutputBuffer;
+
+    use super::*;
+
+    #[test]
+    fn eval_returns_the_last_value() {
+        let mut engine = Engine::new();
+        assert_eq!(engine.eval("let x = 4\nx * 2").unwrap(), Value::Number(8.0));
+    }
+
+    #[test]
+    fn globals_persist_between_evals() {
+        let mut engine = Engine::new();
+        engine.set_global("name", Value::String("merc".to_string()));
+        assert_eq!(
+            engine.eval(r#"name + "!""#).unwrap(),
+            Value::String("merc!".to_string())
+        );
+        engine.eval("let count = 3").unwrap();
+        assert_eq!(engine.get_global("count"), Some(&Value::Number(3.0)));
+        assert_eq!(engine.get_global("missing"), None);
+    }
+
+    #[test]
+    fn call_runs_script_functions() {
+        let mut engine = Engine::new();
+        engine.eval("func add(a, b) { a + b }").unwrap();
+        let sum = engine.call("add", &[Value::Number(1.0), Value::Number(2.0)]);
+        assert_eq!(sum.unwrap(), Value::Number(3.0));
+        let error = engine.call("missing", &[]).unwrap_err();
+        assert_eq!(error.to_string(), "Undefined function: missing");
+    }
+
+    #[test]
+    fn registered_functions_check_their_arity() {
+        let mut engine = Engine::new();
+        engine.register_fn("double", 1, |args| match args {
+            [Value::Number(n)] => Ok(Value::Number(n * 2.0)),
+            _ => Err(miette!("double expects a number")),
+        });
+        engine.register_variadic_fn("count", |args| Ok(Value::Number(args.len() as f64)));
+
+        assert_eq!(engine.eval("double(21)").unwrap(), Value::Number(42.0));
+        assert_eq!(engine.eval("count(1, 2, 3)").unwrap(), Value::Number(3.0));
+        assert_eq!(engine.eval("count()").unwrap(), Value::Number(0.0));
+        let error = engine.eval("double(1, 2)").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Wrong number of arguments to 'double': expected 1, got 2"
+        );
+    }
+
+    #[test]
+    fn set_stdout_captures_printed_output() {
+        let mut engine = Engine::new();
+        let buffer = OutputBuffer::new();
+        engine.set_stdout(Box::new(buffer.clone()));
+        engine.eval("print(\"hello\")\nprint(42)").unwrap();
+        assert_eq!(buffer.contents(), "hello\n42\n");
+    }
+}
@@ -0,0 +1,126 @@
+use std::{fmt, ops::Range};
+
+use miette::{Diagnostic, LabeledSpan, Report, Severity, SourceCode};
+
+/// A failed script run, tagged with the phase that failed so callers can
+/// tell malformed input from errors raised while evaluating it.
+#[derive(Debug)]
+pub enum ScriptError {
+    Parse(Report),
+    Runtime(Report),
+}
+
+impl ScriptError {
+    pub fn report(&self) -> &Report {
+        match self {
+            ScriptError::Parse(report) | ScriptError::Runtime(report) => report,
+        }
+    }
+
+    pub fn is_parse(&self) -> bool {
+        matches!(self, ScriptError::Parse(_))
+    }
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.report())
+    }
+}
+
+impl std::error::Error for ScriptError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.report().source()
+    }
+}
+
+impl Diagnostic for ScriptError {
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        self.report().code()
+    }
+
+    fn severity(&self) -> Option<Severity> {
+        self.report().severity()
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        self.report().help()
+    }
+
+    fn url<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        self.report().url()
+    }
+
+    fn source_code(&self) -> Option<&dyn SourceCode> {
+        self.report().source_code()
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        self.report().labels()
+    }
+
+    fn diagnostic_source(&self) -> Option<&dyn Diagnostic> {
+        self.report().diagnostic_source()
+    }
+
+    fn related<'a>(&'a self) -> Option<Box<dyn Iterator<Item = &'a dyn Diagnostic> + 'a>> {
+        self.report().related()
+    }
+}
+
+/// A runtime error pointing at the code that raised it, such as the
+/// argument a native function rejected.
+#[derive(Debug)]
+pub struct Located {
+    report: Report,
+    label: LabeledSpan,
+    source: String,
+}
+
+impl Located {
+    pub fn new(report: Report, span: Range<usize>, label: String, source: &str) -> Self {
+        Self {
+            report,
+            label: LabeledSpan::at(span, label),
+            source: source.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Located {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.report)
+    }
+}
+
+impl std::error::Error for Located {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.report.source()
+    }
+}
+
+impl Diagnostic for Located {
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        self.report.code()
+    }
+
+    fn severity(&self) -> Option<Severity> {
+        self.report.severity()
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        self.report.help()
+    }
+
+    fn source_code(&self) -> Option<&dyn SourceCode> {
+        Some(&self.source)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        Some(Box::new(std::iter::once(self.label.clone())))
+    }
+
+    fn diagnostic_source(&self) -> Option<&dyn Diagnostic> {
+        self.report.diagnostic_source()
+    }
+}
@@ -0,0 +1,246 @@
+use miette::{miette, Report, Result};
+
+use crate::{
+    cst::{self, SyntaxKind, SyntaxToken},
+    lexer::TokenKind,
+    Parser, ScriptError,
+};
+
+const INDENT: &str = "    ";
+
+/// Formats merc source in the canonical style: four-space indentation,
+/// single spaces around binary operators, no optional semicolons and at
+/// most one blank line in a row. Comments are kept where they are.
+///
+/// The tokens come from the lossless [concrete syntax tree](crate::cst),
+/// which keeps literals and comments exactly as written and tells what each
+/// token belongs to, e.g. whether a `-` is prefix or a `(` starts a call.
+pub fn format(source: &str) -> Result<String> {
+    let before = syntax(source)?;
+
+    let tokens = cst::parse(source).syntax().tokens();
+    let formatted = Formatter::new(tokens).format();
+
+    // Formatting only moves whitespace around, so the program must parse to
+    // the same syntax tree afterwards.
+    if syntax(&formatted)? != before {
+        return Err(miette!(
+            "Formatting changed the meaning of the program, leaving it unformatted"
+        ));
+    }
+    Ok(formatted)
+}
+
+/// The program's statements as S-expressions, for comparing two sources.
+fn syntax(source: &str) -> Result<Vec<String>> {
+    let mut parser = Parser::new(source);
+    let mut statements = Vec::new();
+    while let Some(statement) = parser.parse_statement() {
+        let statement = statement.map_err(|e| Report::new(ScriptError::Parse(e)))?;
+        statements.push(statement.to_string());
+    }
+    Ok(statements)
+}
+
+/// A token with the text it is printed as.
+struct Piece {
+    token: SyntaxToken,
+    text: String,
+}
+
+impl Piece {
+    fn kind(&self) -> &TokenKind {
+        self.token.kind()
+    }
+}
+
+struct Formatter {
+    /// The source split at newlines, without whitespace and newline tokens.
+    lines: Vec<Vec<Piece>>,
+    /// Indentation level inside each bracket that is still open.
+    open: Vec<usize>,
+    out: String,
+}
+
+impl Formatter {
+    fn new(tokens: Vec<SyntaxToken>) -> Self {
+        let mut lines = vec![Vec::new()];
+        for token in tokens {
+            let text = match token.kind() {
+                TokenKind::NewLine => {
+                    lines.push(Vec::new());
+                    continue;
+                }
+                TokenKind::Whitespace(_) => continue,
+                TokenKind::Comment(_) => token.text().trim_end().to_string(),
+                _ => token.text().to_string(),
+            };
+            lines.last_mut().expect("at least one line").push(Piece { token, text });
+        }
+
+        Self {
+            lines,
+            open: Vec::new(),
+            out: String::new(),
+        }
+    }
+
+    fn format(mut self) -> String {
+        let lines = std::mem::take(&mut self.lines);
+        let mut blank = false;
+        let mut after_open = true;
+
+        for mut line in lines {
+            drop_optional_semicolons(&mut line);
+            if line.is_empty() {
+                blank = true;
+                continue;
+            }
+
+            let closes_first = is_closer(line[0].kind());
+            if blank && !after_open && !closes_first {
+                self.out.push('\n');
+            }
+            blank = false;
+
+            self.write_line(&line);
+            after_open = line
+                .iter()
+                .rev()
+                .find(|piece| !matches!(piece.kind(), TokenKind::Comment(_)))
+                .is_some_and(|piece| is_opener(piece.kind()));
+        }
+        self.out
+    }
+
+    fn write_line(&mut self, line: &[Piece]) {
+        // A line that starts by closing a bracket lines up with the line that opened it.
+        let inner = self.open.last().copied().unwrap_or(0);
+        let level = if is_closer(line[0].kind()) {
+            inner.saturating_sub(1)
+        } else {
+            inner
+        };
+
+        self.out.push_str(&INDENT.repeat(level));
+        let mut prev: Option<&Piece> = None;
+        for piece in line {
+            if prev.is_some_and(|prev| space_between(&prev.token, &piece.token)) {
+                self.out.push(' ');
+            }
+            self.out.push_str(&piece.text);
+
+            if is_opener(piece.kind()) {
+                self.open.push(level + 1);
+            } else if is_closer(piece.kind()) {
+                self.open.pop();
+            }
+            prev = Some(piece);
+        }
+        self.out.push('\n');
+    }
+}
+
+/// Removes semicolons that separate nothing: those ending a line or a block
+/// and repeated ones.
+fn drop_optional_semicolons(line: &mut Vec<Piece>) {
+    let mut i = 0;
+    while i < line.len() {
+        let optional = *line[i].kind() == TokenKind::Semicolon
+            && line.get(i + 1).is_none_or(|next| {
+                matches!(
+                    next.kind(),
+                    TokenKind::Semicolon | TokenKind::RightBrace | TokenKind::Comment(_)
+                )
+            });
+        if optional {
+            line.remove(i);
+        } else {
+            i += 1;
+        }
+    }
+}
+
+/// Whether a space goes between `prev` and `next`, which follow each other
+/// on a line.
+fn space_between(prev: &SyntaxToken, next: &SyntaxToken) -> bool {
+    use TokenKind::*;
+
+    // The operator of `-x` or `+x`.
+    if prev.parent().kind() == SyntaxKind::Prefix && matches!(prev.kind(), Plus | Minus) {
+        return false;
+    }
+    match (prev.kind(), next.kind()) {
+        (_, Comment(_)) => true,
+        (LeftBrace, RightBrace) => false,
+        (_, RightBrace) | (LeftBrace, _) => true,
+        (_, RightParen | RightBracket | Comma | Dot | Colon | Semicolon) => false,
+        (LeftParen | LeftBracket | Dot, _) => false,
+        // Calls, parameter lists and indexing bind to what they apply to.
+        (_, LeftParen | LeftBracket) => !matches!(
+            next.parent().kind(),
+            SyntaxKind::Args | SyntaxKind::Params | SyntaxKind::Index
+        ),
+        (_, Bang) => next.parent().kind() != SyntaxKind::Postfix,
+        _ => true,
+    }
+}
+
+fn is_opener(kind: &TokenKind) -> bool {
+    matches!(
+        kind,
+        TokenKind::LeftParen | TokenKind::LeftBracket | TokenKind::LeftBrace
+    )
+}
+
+fn is_closer(kind: &TokenKind) -> bool {
+    matches!(
+        kind,
+        TokenKind::RightParen | TokenKind::RightBracket | TokenKind::RightBrace
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_in_the_canonical_style() {
+        let source = "let a = -1;  let b = a - -2;;\nfunc g( x , y ){ return +x*y }\nif(a<b){print( \"x\" )}else{ print(\"y\") ; }\n";
+        let expected = "let a = -1; let b = a - -2\nfunc g(x, y) { return +x * y }\nif (a < b) { print(\"x\") } else { print(\"y\") }\n";
+        assert_eq!(format(source).unwrap(), expected);
+    }
+
+    #[test]
+    fn keeps_comments_literals_and_one_blank_line() {
+        let source = "// about a\nlet a = 1.50   // trailing\n\n\n\nlet s = \"a\\tb\"\n";
+        let expected = "// about a\nlet a = 1.50 // trailing\n\nlet s = \"a\\tb\"\n";
+        assert_eq!(format(source).unwrap(), expected);
+    }
+
+    #[test]
+    fn indents_blocks_and_brackets() {
+        let source = "func f(n) {\nif (n < 2) {\nreturn [\n1,\n2]\n}\nreturn n!\n}\n";
+        let expected =
+            "func f(n) {\n    if (n < 2) {\n        return [\n            1,\n            2]\n    }\n    return n!\n}\n";
+        assert_eq!(format(source).unwrap(), expected);
+    }
+
+    #[test]
+    fn is_idempotent() {
+        let sources = [
+            "let d = [1,2 , 3][0] + f( 1 )[ 2 ];\nlet m = { \"k\" : -a };\n",
+            "while a > 0 { let a = a - 1 }\ntest \"t\" { assert_eq(g(1,2), 2) }\n",
+            "import \"lib.mc\" as lib\nfrom \"lib.mc\" import a, b\nexport func f() {\n  return lib.a   }\n",
+        ];
+        for source in sources {
+            let once = format(source).unwrap();
+            assert_eq!(format(&once).unwrap(), once, "formatting {:?} twice", source);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_programs() {
+        assert!(format("let = 1").is_err());
+    }
+}
@@ -0,0 +1,126 @@
+use std::sync::OnceLock;
+
+use colored::control::SHOULD_COLORIZE;
+use syntect::{
+    easy::HighlightLines,
+    highlighting::{Theme, ThemeSet},
+    parsing::{SyntaxDefinition, SyntaxReference, SyntaxSet, SyntaxSetBuilder},
+    util::{as_24_bit_terminal_escaped, LinesWithEndings},
+};
+
+use crate::lexer::KEYWORDS;
+
+const THEME: &str = "base16-ocean.dark";
+
+/// Keywords that are values rather than control flow, scoped as constants.
+const CONSTANTS: &[&str] = &["true", "false", "nil"];
+
+/// Highlights merc source for terminal output using a syntax definition
+/// generated from the lexer's keyword list.
+pub struct Highlighter {
+    syntaxes: SyntaxSet,
+    theme: Theme,
+}
+
+impl Highlighter {
+    pub fn new() -> Self {
+        let definition = SyntaxDefinition::load_from_str(&syntax_definition(), true, None)
+            .expect("generated merc syntax definition is valid");
+        let mut builder = SyntaxSetBuilder::new();
+        builder.add(definition);
+
+        let mut themes = ThemeSet::load_defaults();
+        let theme = themes.themes.remove(THEME).expect("default theme exists");
+
+        Self {
+            syntaxes: builder.build(),
+            theme,
+        }
+    }
+
+    /// Returns the shared highlighter, building it on first use.
+    pub fn global() -> &'static Self {
+        static HIGHLIGHTER: OnceLock<Highlighter> = OnceLock::new();
+        HIGHLIGHTER.get_or_init(Highlighter::new)
+    }
+
+    /// Wraps `code` in terminal colour escapes, or returns it unchanged when
+    /// colour output is disabled.
+    pub fn highlight(&self, code: &str) -> String {
+        if !SHOULD_COLORIZE.should_colorize() {
+            return code.to_string();
+        }
+
+        let mut lines = HighlightLines::new(self.syntax(), &self.theme);
+        let mut highlighted = String::new();
+        for line in LinesWithEndings::from(code) {
+            match lines.highlight_line(line, &self.syntaxes) {
+                Ok(ranges) => highlighted.push_str(&as_24_bit_terminal_escaped(&ranges, false)),
+                Err(_) => highlighted.push_str(line),
+            }
+        }
+        highlighted.push_str("\x1b[0m");
+        highlighted
+    }
+
+    fn syntax(&self) -> &SyntaxReference {
+        self.syntaxes
+            .find_syntax_by_extension("mc")
+            .expect("merc syntax is registered")
+    }
+}
+
+impl Default for Highlighter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Builds the sublime-syntax definition for merc.
+pub fn syntax_definition() -> String {
+    let keywords: Vec<&str> = KEYWORDS
+        .iter()
+        .copied()
+        .filter(|keyword| !CONSTANTS.contains(keyword))
+        .collect();
+
+    format!(
+        r#"%YAML 1.2
+---
+name: merc
+file_extensions: [mc]
+scope: source.merc
+contexts:
+  main:
+    - match: '//.*$'
+      scope: comment.line.double-slash.merc
+    - match: '"'
+      scope: punctuation.definition.string.begin.merc
+      push: string
+    - match: '\b(func)\s+([A-Za-z_][A-Za-z0-9_]*)'
+      captures:
+        1: storage.type.function.merc
+        2: entity.name.function.merc
+    - match: '\b({constants})\b'
+      scope: constant.language.merc
+    - match: '\b({keywords})\b'
+      scope: keyword.control.merc
+    - match: '\b[0-9]+(\.[0-9]+)?\b'
+      scope: constant.numeric.merc
+    - match: '\b([A-Za-z_][A-Za-z0-9_]*)\s*(?=\()'
+      captures:
+        1: variable.function.merc
+    - match: '==|!=|<=|>=|[-+*/=<>!]'
+      scope: keyword.operator.merc
+  string:
+    - meta_scope: string.quoted.double.merc
+    - match: '\\.'
+      scope: constant.character.escape.merc
+    - match: '"'
+      scope: punctuation.definition.string.end.merc
+      pop: true
+"#,
+        constants = CONSTANTS.join("|"),
+        keywords = keywords.join("|"),
+    )
+}
@@ -1,7 +1,21 @@
-use crate::{lexer::Token, Op, Parser, TokenKind, S};
-use colored::*;
-use miette::{miette, Error, Result};
-use std::{collections::HashMap, io::Write};
+use crate::{
+    convert::{ArgumentError, IntoValue, TypedFn},
+    error::{Located, ScriptError},
+    lexer::Token,
+    modules::{self, Environment, Modules},
+    output::Output,
+    stdlib::{self, Capabilities, Module},
+    Op, Parser, TokenKind, S,
+};
+use miette::{miette, IntoDiagnostic, Report, Result, WrapErr};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    fmt, fs,
+    io::Write,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
 
 #[derive(Debug, Clone)]
 pub enum Value {
@@ -9,19 +23,190 @@ pub enum Value {
     String(String),
     Boolean(bool),
     Nil,
+    List(Vec<Value>),
+    Map(BTreeMap<String, Value>),
     Function {
         name: String,
         params: Vec<String>,
-        body: Box<S>,
+        /// Shared by every copy of the function, so that copies compare equal
+        /// and separately defined functions don't.
+        body: Rc<S>,
+        /// The imported file the function was defined in, whose globals it
+        /// sees when called; `None` for the program's own functions.
+        module: Option<Rc<Path>>,
     },
+    NativeFunction(NativeFunction),
+}
+
+impl Value {
+    /// The name of this value's type, as shown in error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Boolean(_) => "boolean",
+            Value::Nil => "nil",
+            Value::List(_) => "list",
+            Value::Map(_) => "map",
+            Value::Function { .. } | Value::NativeFunction(_) => "function",
+        }
+    }
+
+    /// The value as it would be written in a script, with strings quoted
+    /// so that `"1"` and `1` look different.
+    pub fn repr(&self) -> String {
+        match self {
+            Value::String(s) => format!("{:?}", s),
+            other => other.to_string(),
+        }
+    }
+
+    /// A one-line summary of what kind of value this is, e.g. `function
+    /// taking 2 arguments`.
+    pub fn describe(&self) -> String {
+        let arguments = |count: usize| match count {
+            1 => "1 argument".to_string(),
+            count => format!("{} arguments", count),
+        };
+        match self {
+            Value::NativeFunction(native) => match native.arity {
+                Some(arity) => format!("native function taking {}", arguments(arity)),
+                None => "native function taking any number of arguments".to_string(),
+            },
+            Value::Function { params, .. } => format!("function taking {}", arguments(params.len())),
+            other => other.type_name().to_string(),
+        }
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Number(a), Value::Number(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Boolean(a), Value::Boolean(b)) => a == b,
+            (Value::Nil, Value::Nil) => true,
+            (Value::List(a), Value::List(b)) => a == b,
+            (Value::Map(a), Value::Map(b)) => a == b,
+            (Value::Function { body: a, .. }, Value::Function { body: b, .. }) => Rc::ptr_eq(a, b),
+            (Value::NativeFunction(a), Value::NativeFunction(b)) => Rc::ptr_eq(&a.func, &b.func),
+            _ => false,
+        }
+    }
+}
+
+pub type NativeFn = dyn Fn(&[Value]) -> Result<Value>;
+
+/// A host closure callable from merc code.
+#[derive(Clone)]
+pub struct NativeFunction {
+    pub name: String,
+    /// Number of arguments the function accepts, `None` for variadic functions.
+    pub arity: Option<usize>,
+    func: Rc<NativeFn>,
+}
+
+impl NativeFunction {
+    pub fn new<F>(name: &str, arity: Option<usize>, func: F) -> Self
+    where
+        F: Fn(&[Value]) -> Result<Value> + 'static,
+    {
+        Self {
+            name: name.to_string(),
+            arity,
+            func: Rc::new(func),
+        }
+    }
+
+    pub fn call(&self, args: &[Value]) -> Result<Value> {
+        if let Some(arity) = self.arity {
+            if args.len() != arity {
+                return Err(miette!(
+                    "Wrong number of arguments to '{}': expected {}, got {}",
+                    self.name,
+                    arity,
+                    args.len()
+                ));
+            }
+        }
+        (self.func)(args)
+    }
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NativeFunction")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .finish()
+    }
+}
+
+/// Watches a program run, for tools such as debuggers. Hooks are not
+/// called for code evaluated while a hook method is running.
+pub trait Hook {
+    /// Called before each statement runs; an error stops the program.
+    fn statement(&mut self, _interpreter: &mut Interpreter<'_>, _statement: &S) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called when a user-defined function is entered, with its parameters bound.
+    fn enter(&mut self, _interpreter: &mut Interpreter<'_>, _name: &str) {}
+
+    /// Called when a user-defined function returns or fails.
+    fn exit(&mut self, _interpreter: &mut Interpreter<'_>, _name: &str) {}
+
+    /// Called when an `if` condition has been evaluated, with whether the
+    /// `then` branch runs.
+    fn branch(&mut self, _interpreter: &mut Interpreter<'_>, _condition: &S, _taken: bool) {}
 }
 
 pub struct Interpreter<'a> {
     pub parser: Parser<'a>,
     pub current_token: Option<Token>,
+    /// Global bindings, visible from every scope.
     pub variables: HashMap<String, Value>,
+    /// Local scopes of the functions currently being called, innermost last.
+    frames: Vec<HashMap<String, Value>>,
+    /// Native functions and modules, shadowed by any user binding of the same name.
+    builtins: HashMap<String, Value>,
+    stdout: Output,
+    stderr: Output,
+    hook: Option<Box<dyn Hook>>,
+    /// The file being run, against whose directory imports are resolved.
+    file: Option<PathBuf>,
+    /// The modules imported so far, shared with the interpreters loading them.
+    modules: Rc<RefCell<Modules>>,
+    /// The imported file this interpreter is evaluating, if any.
+    module: Option<Rc<Path>>,
+    /// The environment of the module whose function is being called, whose
+    /// globals are used in place of `variables`.
+    module_environment: Option<Rc<Environment>>,
+    /// The source of the program, for pointing at the code behind errors.
+    source: Option<Rc<str>>,
+    /// What the script may do outside the interpreter, shared with the
+    /// `io` and `fs` modules and the interpreters loading modules.
+    capabilities: Rc<Capabilities>,
+    /// The value of the `return` being unwound to the function call it
+    /// returns from.
+    returned: Option<Value>,
 }
 
+/// Raised by `return` to unwind the function body; the call it returns
+/// from turns it back into the value in [`Interpreter::returned`].
+#[derive(Debug)]
+struct Return;
+
+impl fmt::Display for Return {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "'return' outside of a function")
+    }
+}
+
+impl std::error::Error for Return {}
+
+impl miette::Diagnostic for Return {}
+
 impl std::fmt::Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -29,39 +214,354 @@ impl std::fmt::Display for Value {
             Value::String(s) => write!(f, "{}", s),
             Value::Boolean(b) => write!(f, "{}", b),
             Value::Nil => write!(f, "nil"),
+            Value::List(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write_nested(f, item)?;
+                }
+                write!(f, "]")
+            }
+            Value::Map(entries) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{:?}: ", key)?;
+                    write_nested(f, value)?;
+                }
+                write!(f, "}}")
+            }
             Value::Function { name, .. } => write!(f, "<function {}>", name),
+            Value::NativeFunction(native) => write!(f, "<native function {}>", native.name),
         }
     }
 }
 
+/// Strings nested in lists and maps are quoted so `["a, b"]` stays readable.
+fn write_nested(f: &mut fmt::Formatter<'_>, value: &Value) -> fmt::Result {
+    match value {
+        Value::String(s) => write!(f, "{:?}", s),
+        other => write!(f, "{}", other),
+    }
+}
+
+impl Default for Interpreter<'_> {
+    fn default() -> Self {
+        Self::new(Parser::new(""))
+    }
+}
+
 impl<'a> Interpreter<'a> {
     pub fn new(parser: Parser<'a>) -> Self {
-        Self {
+        let parser_source = parser.source();
+        let mut interpreter = Self {
             parser,
             current_token: None,
             variables: HashMap::new(),
+            frames: Vec::new(),
+            builtins: HashMap::new(),
+            stdout: Output::stdout(),
+            stderr: Output::stderr(),
+            hook: None,
+            file: None,
+            modules: Rc::default(),
+            module: None,
+            module_environment: None,
+            source: Some(Rc::from(parser_source)),
+            capabilities: Rc::default(),
+            returned: None,
+        };
+        interpreter.register_builtins();
+        interpreter
+    }
+
+    fn register_builtins(&mut self) {
+        self.register_module(stdlib::json::module());
+        self.register_module(stdlib::math::module());
+        self.register_module(stdlib::string::module());
+        self.set_capabilities(Capabilities::default());
+        self.register_fn("len", 1, stdlib::string::len);
+        self.register_variadic_fn("format", stdlib::string::format);
+        self.register_fn("to_number", 1, stdlib::string::to_number);
+        self.register_fn("to_string", 1, stdlib::string::to_string);
+        self.register_variadic_fn("assert", stdlib::assert::assert);
+        self.register_fn("assert_eq", 2, stdlib::assert::assert_eq);
+
+        let stdout = self.stdout.clone();
+        self.register_variadic_fn("print", move |args| {
+            let mut output = String::new();
+            for value in args {
+                output.push_str(&format!("{}", value));
+            }
+            writeln!(&stdout, "{}", output).into_diagnostic()?;
+            Ok(Value::Nil)
+        });
+    }
+
+    /// Sends everything scripts print to `writer` instead of the process stdout.
+    pub fn set_stdout(&mut self, writer: Box<dyn Write>) {
+        self.stdout.redirect(writer);
+    }
+
+    /// Sends error reports printed on the script's behalf to `writer`
+    /// instead of the process stderr.
+    pub fn set_stderr(&mut self, writer: Box<dyn Write>) {
+        self.stderr.redirect(writer);
+    }
+
+    /// Limits what `import` and the `io` and `fs` modules may do, e.g. to
+    /// [`Capabilities::none`] for untrusted scripts.
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+        let capabilities = Rc::new(capabilities);
+        self.register_module(stdlib::io::module(capabilities.clone()));
+        self.register_module(stdlib::fs::module(capabilities.clone()));
+        self.capabilities = capabilities;
+    }
+
+    /// Resolves the program's imports relative to the directory of `path`
+    /// instead of the working directory.
+    pub fn set_file(&mut self, path: &Path) {
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        self.modules = Rc::new(RefCell::new(Modules::new(path.clone())));
+        self.file = Some(path);
+    }
+
+    /// Calls `hook` as the program runs, replacing any previous hook.
+    pub fn set_hook(&mut self, hook: impl Hook + 'static) {
+        self.hook = Some(Box::new(hook));
+    }
+
+    /// Runs `f` with the hook taken out, so that code it evaluates doesn't
+    /// reach the hook again.
+    fn with_hook<R>(&mut self, f: impl FnOnce(&mut dyn Hook, &mut Self) -> R) -> Option<R> {
+        let mut hook = self.hook.take()?;
+        let result = f(hook.as_mut(), self);
+        self.hook = Some(hook);
+        Some(result)
+    }
+
+    /// A handle to the interpreter's stdout, for native functions that print.
+    pub fn stdout(&self) -> Output {
+        self.stdout.clone()
+    }
+
+    pub fn stderr(&self) -> Output {
+        self.stderr.clone()
+    }
+
+    /// Registers a host function taking exactly `arity` arguments.
+    pub fn register_fn<F>(&mut self, name: &str, arity: usize, func: F)
+    where
+        F: Fn(&[Value]) -> Result<Value> + 'static,
+    {
+        self.builtins.insert(
+            name.to_string(),
+            Value::NativeFunction(NativeFunction::new(name, Some(arity), func)),
+        );
+    }
+
+    /// Registers a plain Rust function, converting its arguments and return value.
+    pub fn register_typed_fn<Args, F>(&mut self, name: &str, func: F)
+    where
+        F: TypedFn<Args>,
+    {
+        self.builtins
+            .insert(name.to_string(), Value::NativeFunction(func.into_native(name)));
+    }
+
+    /// Registers a host function accepting any number of arguments.
+    pub fn register_variadic_fn<F>(&mut self, name: &str, func: F)
+    where
+        F: Fn(&[Value]) -> Result<Value> + 'static,
+    {
+        self.builtins.insert(
+            name.to_string(),
+            Value::NativeFunction(NativeFunction::new(name, None, func)),
+        );
+    }
+
+    /// Makes a native module available to scripts under its name.
+    pub fn register_module(&mut self, module: Module) {
+        self.builtins
+            .insert(module.name().to_string(), module.into_value());
+    }
+
+    /// Parses and evaluates `source` against the current environment,
+    /// returning the value of the last statement.
+    pub fn eval(&mut self, source: &str) -> Result<Value> {
+        let outer = self.source.replace(Rc::from(source));
+        let mut parser = Parser::new(source);
+        let mut result = Ok(Value::Nil);
+        while let Some(statement) = parser.parse_statement() {
+            result = statement
+                .map_err(|e| Report::new(ScriptError::Parse(e)))
+                .and_then(|ast| self.execute(&ast));
+            if result.is_err() {
+                break;
+            }
         }
+        self.source = outer;
+        result
     }
-    pub fn run(&mut self) -> Result<()> {
-        self.expr()
+
+    /// The source text of the statements about to be run with
+    /// [`Interpreter::execute`], so runtime errors can point into it.
+    pub fn set_source(&mut self, source: &str) {
+        self.source = Some(Rc::from(source));
     }
 
-    pub fn replace_db(&mut self, db: HashMap<String, Value>) {
-        self.variables = db;
+    /// Names of the native functions and modules available to scripts.
+    pub fn builtin_names(&self) -> impl Iterator<Item = &String> {
+        self.builtins.keys()
     }
 
-    pub fn expr(&mut self) -> Result<()> {
-        while let Some(statement) = self.parser.parse_statement() {
-            match statement {
-                Ok(ast) => {
-                    if let Err(e) = self.evaluate(&ast) {
-                        println!("Error: {}", e);
-                    }
+    /// Looks a name up in the innermost local scope, then the globals, then the builtins.
+    pub fn lookup(&self, name: &str) -> Option<Value> {
+        self.frames
+            .last()
+            .and_then(|frame| frame.get(name))
+            .or_else(|| match &self.module_environment {
+                Some(environment) => environment.globals.get(name),
+                None => self.variables.get(name),
+            })
+            .cloned()
+            .or_else(|| self.builtins.get(name).cloned())
+    }
+
+    /// The local variables of the innermost function being called, or
+    /// `None` at the top level.
+    pub fn locals(&self) -> Option<&HashMap<String, Value>> {
+        self.frames.last()
+    }
+
+    /// The file of the code running now: that of an imported module while
+    /// its code runs, otherwise the program's own, if it has one. Hooks use
+    /// it to tell apart statements from different files.
+    pub fn current_file(&self) -> Option<&Path> {
+        match &self.module_environment {
+            Some(environment) => Some(&environment.path),
+            None => self.file.as_deref(),
+        }
+    }
+
+    /// The source of [`Interpreter::current_file`], which the spans and
+    /// lines of the running statements refer to.
+    pub fn current_source(&self) -> Option<&str> {
+        match &self.module_environment {
+            Some(environment) => Some(&environment.source),
+            None => self.source.as_deref(),
+        }
+    }
+
+    /// The local variables of every function being called, outermost first.
+    pub fn frames(&self) -> &[HashMap<String, Value>] {
+        &self.frames
+    }
+
+    /// Binds `name` in the innermost local scope, or globally at the top level.
+    fn define(&mut self, name: &str, value: Value) {
+        match self.frames.last_mut() {
+            Some(frame) => frame.insert(name.to_string(), value),
+            None => self.variables.insert(name.to_string(), value),
+        };
+    }
+
+    /// Calls a user-defined or native function with already evaluated arguments.
+    pub fn call_value(&mut self, func: &Value, args: Vec<Value>) -> Result<Value> {
+        match func {
+            Value::Function {
+                name,
+                params,
+                body,
+                module,
+            } => {
+                if args.len() != params.len() {
+                    return Err(miette!(
+                        "Wrong number of arguments to '{}': expected {}, got {}",
+                        name,
+                        params.len(),
+                        args.len()
+                    ));
                 }
-                Err(e) => println!("Parse error: {}", e),
+
+                // A function from another module runs against that module's
+                // globals, and the program's own functions against `variables`.
+                let environment = match module {
+                    Some(module) if Some(module) != self.module.as_ref() => {
+                        self.modules.borrow().environment(module)
+                    }
+                    _ => None,
+                };
+                let caller = std::mem::replace(&mut self.module_environment, environment);
+
+                let scope = params.iter().cloned().zip(args).collect();
+                self.frames.push(scope);
+                self.with_hook(|hook, interpreter| hook.enter(interpreter, name));
+                let result = match self.evaluate(body) {
+                    Err(error) if error.downcast_ref::<Return>().is_some() => {
+                        Ok(self.returned.take().unwrap_or(Value::Nil))
+                    }
+                    result => result,
+                };
+                self.with_hook(|hook, interpreter| hook.exit(interpreter, name));
+                self.frames.pop();
+                self.module_environment = caller;
+
+                result
             }
+            Value::NativeFunction(native) => native.call(&args),
+            other => Err(miette!("'{}' is not a function", other)),
         }
-        Ok(())
+    }
+
+    /// Runs every statement of the parsed program and returns the value of
+    /// the last one, stopping at the first parse or runtime error.
+    pub fn run(&mut self) -> Result<Value> {
+        let mut result = Value::Nil;
+        while let Some(statement) = self.parser.parse_statement() {
+            let ast = statement.map_err(|e| Report::new(ScriptError::Parse(e)))?;
+            result = self.execute(&ast)?;
+        }
+        Ok(result)
+    }
+
+    /// Evaluates one parsed statement against the current environment.
+    pub fn execute(&mut self, statement: &S) -> Result<Value> {
+        self.before(statement)
+            .and_then(|_| self.evaluate(statement))
+            .map_err(|e| Report::new(ScriptError::Runtime(e)))
+    }
+
+    /// Runs the body of a `test "name" { ... }` statement, which other
+    /// runs skip.
+    pub fn run_test(&mut self, test: &S) -> Result<()> {
+        match test {
+            S::Cons(
+                Token {
+                    kind: TokenKind::Test,
+                    ..
+                },
+                args,
+            ) if args.len() == 2 => self
+                .before(test)
+                .and_then(|_| self.evaluate(&args[1]))
+                .map(|_| ())
+                .map_err(|e| Report::new(ScriptError::Runtime(e))),
+            _ => Err(miette!("Not a test: {}", test)),
+        }
+    }
+
+    fn before(&mut self, statement: &S) -> Result<()> {
+        self.with_hook(|hook, interpreter| hook.statement(interpreter, statement))
+            .unwrap_or(Ok(()))
+    }
+
+    pub fn replace_db(&mut self, db: HashMap<String, Value>) {
+        self.variables = db;
     }
 
     fn evaluate(&mut self, expr: &S) -> Result<Value> {
@@ -77,6 +577,27 @@ impl<'a> Interpreter<'a> {
             S::Block(statements) => self.evaluate_block(statements),
             S::FunDef { name, args, body } => self.evaluate_function_def(name, args, body),
             S::FunCall { name, args } => self.evaluate_function_call(name, args),
+            S::List(items) => {
+                let mut values = Vec::with_capacity(items.len());
+                for item in items {
+                    values.push(self.evaluate(item)?);
+                }
+                Ok(Value::List(values))
+            }
+            S::Map(entries) => {
+                let mut map = BTreeMap::new();
+                for (key, value) in entries {
+                    let key = match key {
+                        S::Atom(Token {
+                            kind: TokenKind::Identifier(key) | TokenKind::String(key),
+                            ..
+                        }) => key.clone(),
+                        _ => return Err(miette!("Map keys must be strings or identifiers")),
+                    };
+                    map.insert(key, self.evaluate(value)?);
+                }
+                Ok(Value::Map(map))
+            }
         }
     }
 
@@ -88,9 +609,7 @@ impl<'a> Interpreter<'a> {
             TokenKind::False => Ok(Value::Boolean(false)),
             TokenKind::Nil => Ok(Value::Nil),
             TokenKind::Identifier(name) => self
-                .variables
-                .get(name)
-                .cloned()
+                .lookup(name)
                 .ok_or_else(|| miette!("Undefined variable: {}", name)),
             _ => Err(miette!("Invalid atomic expression: {:?}", token)),
         }
@@ -102,7 +621,7 @@ impl<'a> Interpreter<'a> {
                 if let [S::Atom(name_token), value_expr] = args {
                     if let TokenKind::Identifier(name) = &name_token.kind {
                         let value = self.evaluate(value_expr)?;
-                        self.variables.insert(name.clone(), value.clone());
+                        self.define(name, value.clone());
                         Ok(value)
                     } else {
                         Err(miette!("Expected identifier in let binding"))
@@ -113,7 +632,8 @@ impl<'a> Interpreter<'a> {
             }
             TokenKind::Return => {
                 if let [value_expr] = args {
-                    self.evaluate(value_expr)
+                    self.returned = Some(self.evaluate(value_expr)?);
+                    Err(Report::new(Return))
                 } else {
                     Err(miette!("Invalid return expression"))
                 }
@@ -128,10 +648,88 @@ impl<'a> Interpreter<'a> {
                     Err(miette!("Invalid while expression"))
                 }
             }
+            // Tests only run under `merc test`, through `run_test`.
+            TokenKind::Test => Ok(Value::Nil),
+            TokenKind::Import | TokenKind::From => self.evaluate_import(token, args),
+            TokenKind::Export => {
+                if let [declaration] = args {
+                    self.evaluate(declaration)
+                } else {
+                    Err(miette!("Invalid export statement"))
+                }
+            }
+            TokenKind::Dot => {
+                if let [object, S::Atom(Token {
+                    kind: TokenKind::Identifier(member),
+                    ..
+                })] = args
+                {
+                    let object = self.evaluate(object)?;
+                    self.get_member(object, member)
+                } else {
+                    Err(miette!("Invalid member access"))
+                }
+            }
+            TokenKind::LeftBracket => {
+                if let [object, index] = args {
+                    let object = self.evaluate(object)?;
+                    let index = self.evaluate(index)?;
+                    self.index(object, index)
+                } else {
+                    Err(miette!("Invalid index expression"))
+                }
+            }
+            TokenKind::Minus | TokenKind::Plus if args.len() == 1 => {
+                match (&token.kind, self.evaluate(&args[0])?) {
+                    (TokenKind::Minus, Value::Number(n)) => Ok(Value::Number(-n)),
+                    (_, Value::Number(n)) => Ok(Value::Number(n)),
+                    (_, other) => Err(miette!(
+                        "Invalid operand for unary {}: {}",
+                        token.kind.lexeme(),
+                        other.type_name()
+                    )),
+                }
+            }
             _ => self.evaluate_binary_operation(token, args),
         }
     }
 
+    fn get_member(&self, object: Value, member: &str) -> Result<Value> {
+        match object {
+            Value::Map(mut entries) => entries
+                .remove(member)
+                .ok_or_else(|| miette!("Map has no key '{}'", member)),
+            other => Err(miette!(
+                "Cannot access '{}' on a value of type {}",
+                member,
+                other.type_name()
+            )),
+        }
+    }
+
+    fn index(&self, object: Value, index: Value) -> Result<Value> {
+        match (object, index) {
+            (Value::List(items), Value::Number(n)) => {
+                if n < 0.0 || n.fract() != 0.0 || n as usize >= items.len() {
+                    return Err(miette!(
+                        "Index {} out of bounds for list of length {}",
+                        n,
+                        items.len()
+                    ));
+                }
+                Ok(items[n as usize].clone())
+            }
+            (Value::Map(mut entries), Value::String(key)) => entries
+                .remove(&key)
+                .ok_or_else(|| miette!("Map has no key '{}'", key)),
+            (object, index) => Err(miette!(
+                "Cannot index a {} with a {}",
+                object.type_name(),
+                index.type_name()
+            )),
+        }
+    }
+
     fn evaluate_binary_operation(&mut self, token: &Token, args: &[S]) -> Result<Value> {
         if args.len() != 2 {
             return Err(miette!("Binary operation requires exactly two operands"));
@@ -174,6 +772,10 @@ impl<'a> Interpreter<'a> {
         else_branch: &Option<Box<S>>,
     ) -> Result<Value> {
         let condition = self.evaluate(cond)?;
+        if let Value::Boolean(taken) = &condition {
+            let taken = *taken;
+            self.with_hook(|hook, interpreter| hook.branch(interpreter, cond, taken));
+        }
 
         match condition {
             Value::Boolean(true) => self.evaluate(then_branch),
@@ -191,6 +793,7 @@ impl<'a> Interpreter<'a> {
     fn evaluate_block(&mut self, statements: &[S]) -> Result<Value> {
         let mut result = Value::Nil;
         for stmt in statements {
+            self.before(stmt)?;
             result = self.evaluate(stmt)?;
         }
         Ok(result)
@@ -218,76 +821,254 @@ impl<'a> Interpreter<'a> {
             let func = Value::Function {
                 name: name_str.clone(),
                 params,
-                body: Box::new(body.clone()),
+                body: Rc::new(body.clone()),
+                module: self.module.clone(),
             };
 
-            self.variables.insert(name_str.clone(), func.clone());
+            self.define(name_str, func.clone());
             Ok(func)
         } else {
             Err(miette!("Function name must be an identifier"))
         }
     }
 
-    fn evaluate_function_call(&mut self, name: &S, args: &[S]) -> Result<Value> {
-        if let S::Atom(Token {
-            kind: TokenKind::Identifier(name_str),
+    /// Binds the module imported by `import "path" as name` to `name`, or
+    /// the names listed by `from "path" import a, b`.
+    fn evaluate_import(&mut self, token: &Token, args: &[S]) -> Result<Value> {
+        let [S::Atom(Token {
+            kind: TokenKind::String(path),
             ..
-        }) = name
-        {
-            let func = match self.variables.get(name_str) {
-                Some(Value::Function {
-                    params,
-                    body,
-                    name: fname,
-                }) => Value::Function {
-                    name: fname.clone(),
-                    params: params.clone(),
-                    body: body.clone(),
+        }), names @ ..] = args
+        else {
+            return Err(miette!("Invalid import statement"));
+        };
+        let names = names.iter().map(|name| match name {
+            S::Atom(Token {
+                kind: TokenKind::Identifier(name),
+                ..
+            }) => Ok(name),
+            _ => Err(miette!("Expected a name to import")),
+        });
+
+        let mut exports = self.import(path)?;
+        if token.kind == TokenKind::Import {
+            for name in names {
+                self.define(name?, Value::Map(exports.clone()));
+            }
+            return Ok(Value::Nil);
+        }
+        for name in names {
+            let name = name?;
+            let value = exports.remove(name).ok_or_else(|| {
+                miette!(
+                    help = "only names marked `export`, or if there are none all names not starting with `_`, can be imported",
+                    "Module '{}' has no export named '{}'",
+                    path,
+                    name
+                )
+            })?;
+            self.define(name, value);
+        }
+        Ok(Value::Nil)
+    }
+
+    /// The exports of the module at `path`, evaluating it unless it was
+    /// already imported.
+    fn import(&mut self, path: &str) -> Result<BTreeMap<String, Value>> {
+        self.capabilities.check_import(path)?;
+        let base = self.file.as_deref().and_then(Path::parent).unwrap_or(Path::new(""));
+        let file = base.join(path);
+        let file = fs::canonicalize(&file)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Cannot import '{}': {}", path, file.display()))?;
+        if let Some(exports) = self.modules.borrow().exports(&file) {
+            return Ok(exports);
+        }
+
+        self.modules.borrow_mut().enter(&file)?;
+        let result = self.load(&file);
+        self.modules.borrow_mut().leave();
+        let (program, globals, source) =
+            result.wrap_err_with(|| format!("Failed to import {}", modules::display(&file)))?;
+        let environment = Environment {
+            path: Rc::from(file.as_path()),
+            globals,
+            source: Rc::from(source),
+        };
+        Ok(self.modules.borrow_mut().insert(file, &program, environment))
+    }
+
+    /// Runs the file at `path` in an interpreter of its own, sharing this
+    /// one's builtins, output, modules and hook, and returns its program,
+    /// globals and source. The hook sees the module run as a call.
+    fn load(&mut self, path: &Path) -> Result<(Vec<S>, HashMap<String, Value>, String)> {
+        let source = fs::read_to_string(path).into_diagnostic()?;
+        let mut module = Interpreter {
+            parser: Parser::new(""),
+            current_token: None,
+            variables: HashMap::new(),
+            frames: Vec::new(),
+            builtins: self.builtins.clone(),
+            stdout: self.stdout.clone(),
+            stderr: self.stderr.clone(),
+            hook: self.hook.take(),
+            file: Some(path.to_path_buf()),
+            modules: self.modules.clone(),
+            module: Some(Rc::from(path)),
+            module_environment: None,
+            source: Some(Rc::from(source.as_str())),
+            capabilities: self.capabilities.clone(),
+            returned: None,
+        };
+
+        let name = format!("<import {}>", modules::display(path));
+        module.with_hook(|hook, interpreter| hook.enter(interpreter, &name));
+        let mut parser = Parser::new(&source);
+        let mut program = Vec::new();
+        let result = std::iter::from_fn(|| parser.parse_statement()).try_for_each(|statement| {
+            let statement = statement.map_err(|e| Report::new(ScriptError::Parse(e)))?;
+            module.execute(&statement)?;
+            program.push(statement);
+            Ok(())
+        });
+        module.with_hook(|hook, interpreter| hook.exit(interpreter, &name));
+        self.hook = module.hook.take();
+        result.map(|()| (program, module.variables, source))
+    }
+
+    fn evaluate_function_call(&mut self, name: &S, args: &[S]) -> Result<Value> {
+        let func = match name {
+            S::Atom(Token {
+                kind: TokenKind::Identifier(name_str),
+                ..
+            }) => match self.lookup(name_str) {
+                Some(func @ (Value::Function { .. } | Value::NativeFunction(_))) => func,
+                _ => return Err(miette!("'{}' is not a function", name_str)),
+            },
+            S::Cons(
+                Token {
+                    kind: TokenKind::Dot,
+                    ..
                 },
-                _ => {
-                    if name_str == "print" {
-                        let mut output = String::new();
-                        for arg in args {
-                            let value = self.evaluate(arg)?;
-                            output.push_str(&format!("{}", value));
-                        }
-                        println!("{}", output);
-                        return Ok(Value::Nil);
+                parts,
+            ) => match parts.as_slice() {
+                [object, S::Atom(Token {
+                    kind: TokenKind::Identifier(member),
+                    ..
+                })] => match self.evaluate(object)? {
+                    Value::String(s) => {
+                        return self.call_method(Value::String(s), member, name, object, args)
                     }
-                    
-                    return Err(miette!("'{}' is not a function", name_str));
-                }
-            };
+                    object @ Value::Map(_) => self.get_member(object, member)?,
+                    object => {
+                        let kind = object.type_name();
+                        let help =
+                            format!("only strings have methods; pass the {} to a function instead", kind);
+                        let error = miette!(help = help, "No method '{}' on {}", member, kind);
+                        return Err(self.locate(error, name, &[]));
+                    }
+                },
+                _ => self.evaluate(name)?,
+            },
+            callee => self.evaluate(callee)?,
+        };
 
-            // Now we can safely work with the cloned function definition
-            if let Value::Function { params, body, .. } = func {
-                if args.len() != params.len() {
-                    return Err(miette!(
-                        "Wrong number of arguments: expected {}, got {}",
-                        params.len(),
-                        args.len()
-                    ));
-                }
+        // Evaluate all arguments first
+        let mut evaluated_args = Vec::with_capacity(args.len());
+        for arg in args {
+            evaluated_args.push(self.evaluate(arg)?);
+        }
 
-                // Evaluate all arguments first
-                let mut evaluated_args = HashMap::new();
-                for (param, arg) in params.iter().zip(args) {
-                    let arg_value = self.evaluate(arg)?;
-                    evaluated_args.insert(param.clone(), arg_value);
-                }
+        match func {
+            // Errors from merc functions already come from their own code.
+            Value::NativeFunction(_) => self
+                .call_value(&func, evaluated_args)
+                .map_err(|error| self.locate(error, name, &args.iter().collect::<Vec<_>>())),
+            _ => self.call_value(&func, evaluated_args),
+        }
+    }
 
-                // Save old scope and set new one
-                let old_scope = std::mem::replace(&mut self.variables, evaluated_args);
-                let result = self.evaluate(&body);
-                // Restore old scope
-                self.variables = old_scope;
+    /// Calls `receiver.method(args)` on a string as the function of the same
+    /// name in the `string` module, with the receiver as first argument.
+    /// Errors count the arguments in parentheses from 1, as written.
+    fn call_method(
+        &mut self,
+        receiver: Value,
+        method: &str,
+        name: &S,
+        object: &S,
+        args: &[S],
+    ) -> Result<Value> {
+        let func = match self.builtins.get("string") {
+            Some(Value::Map(methods)) => methods.get(method).cloned(),
+            _ => None,
+        };
+        let Some(func) = func else {
+            let error = miette!(
+                help = "see the `string` module for the methods strings have",
+                "No method '{}' on string",
+                method
+            );
+            return Err(self.locate(error, name, &[]));
+        };
+        // The receiver isn't one of the arguments as written.
+        if let Value::NativeFunction(NativeFunction {
+            arity: Some(arity), ..
+        }) = &func
+        {
+            if args.len() + 1 != *arity {
+                let error = miette!(
+                    "Wrong number of arguments to '{}': expected {}, got {}",
+                    method,
+                    arity - 1,
+                    args.len()
+                );
+                return Err(self.locate(error, name, &args.iter().collect::<Vec<_>>()));
+            }
+        }
 
-                result
-            } else {
-                unreachable!("We already checked that this is a function")
+        let mut evaluated_args = Vec::with_capacity(args.len() + 1);
+        evaluated_args.push(receiver);
+        for arg in args {
+            evaluated_args.push(self.evaluate(arg)?);
+        }
+        let nodes: Vec<&S> = [object].into_iter().chain(args).collect();
+        self.call_value(&func, evaluated_args).map_err(|error| {
+            let error = match error.downcast::<ArgumentError>() {
+                Ok(error) => Report::new(ArgumentError {
+                    function: method.to_string(),
+                    method: true,
+                    ..error
+                }),
+                Err(error) => error,
+            };
+            self.locate(error, name, &nodes)
+        })
+    }
+
+    /// Points an error raised by a native function at the argument it
+    /// rejected, or else at the whole call.
+    fn locate(&self, error: Report, name: &S, args: &[&S]) -> Report {
+        let source = match &self.module_environment {
+            Some(environment) => Some(&environment.source),
+            None => self.source.as_ref(),
+        };
+        let argument = error
+            .downcast_ref::<ArgumentError>()
+            .and_then(|error| Some((args.get(error.position)?.span()?, error.problem.clone())));
+        let call = [name].into_iter().chain(args.iter().copied()).filter_map(S::span).reduce(|a, b| {
+            a.start.min(b.start)..a.end.max(b.end)
+        });
+        let (span, label) = match (argument, call) {
+            (Some(argument), _) => argument,
+            (None, Some(call)) => (call, "in this call".to_string()),
+            (None, None) => return error,
+        };
+        match source {
+            Some(source) if span.end <= source.len() => {
+                Report::new(Located::new(error, span, label, source))
             }
-        } else {
-            Err(miette!("Function name must be an identifier"))
+            _ => error,
         }
     }
 
@@ -328,13 +1109,7 @@ impl<'a> Interpreter<'a> {
     }
 
     fn equals(&self, left: Value, right: Value) -> Result<Value> {
-        match (left, right) {
-            (Value::Number(a), Value::Number(b)) => Ok(Value::Boolean(a == b)),
-            (Value::String(a), Value::String(b)) => Ok(Value::Boolean(a == b)),
-            (Value::Boolean(a), Value::Boolean(b)) => Ok(Value::Boolean(a == b)),
-            (Value::Nil, Value::Nil) => Ok(Value::Boolean(true)),
-            _ => Ok(Value::Boolean(false)),
-        }
+        Ok(Value::Boolean(left == right))
     }
 
     fn not_equals(&self, left: Value, right: Value) -> Result<Value> {
@@ -376,3 +1151,94 @@ impl<'a> Interpreter<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(source: &str) -> Value {
+        Interpreter::default().eval(source).expect("evaluates")
+    }
+
+    fn error(source: &str) -> String {
+        Interpreter::default().eval(source).expect_err("fails").to_string()
+    }
+
+    fn string(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+
+    #[test]
+    fn lists_and_maps_are_built_from_literals() {
+        assert_eq!(
+            eval(r#"[1, "two", [3]]"#),
+            Value::List(vec![
+                Value::Number(1.0),
+                string("two"),
+                Value::List(vec![Value::Number(3.0)]),
+            ])
+        );
+        assert_eq!(
+            eval("let m = {a: 1, \"b c\": [], }\nm"),
+            Value::Map(BTreeMap::from([
+                ("a".to_string(), Value::Number(1.0)),
+                ("b c".to_string(), Value::List(vec![])),
+            ]))
+        );
+    }
+
+    #[test]
+    fn members_and_indexes_read_lists_and_maps() {
+        let source = "let point = {x: 1, tags: [\"a\", \"b\"]}\n";
+        assert_eq!(eval(&format!("{}point.x", source)), Value::Number(1.0));
+        assert_eq!(eval(&format!("{}point[\"x\"]", source)), Value::Number(1.0));
+        assert_eq!(eval(&format!("{}point.tags[1]", source)), string("b"));
+        assert_eq!(eval("[[1, 2], [3, 4]][1][0]"), Value::Number(3.0));
+    }
+
+    #[test]
+    fn missing_members_and_indexes_are_errors() {
+        assert_eq!(error("[1, 2][2]"), "Index 2 out of bounds for list of length 2");
+        assert_eq!(error("[1, 2][0.5]"), "Index 0.5 out of bounds for list of length 2");
+        assert_eq!(error("let m = {a: 1}\nm.b"), "Map has no key 'b'");
+        assert_eq!(error("let m = {a: 1}\nm[\"b\"]"), "Map has no key 'b'");
+        assert_eq!(error("[1][\"a\"]"), "Cannot index a list with a string");
+        assert_eq!(error("let n = 3\nn.x"), "Cannot access 'x' on a value of type number");
+    }
+
+    #[test]
+    fn strings_unescape_their_escapes() {
+        assert_eq!(eval(r#""a\"b\\c\td\n\r\0""#), string("a\"b\\c\td\n\r\0"));
+        assert_eq!(eval(r#""\q""#), string("q"));
+    }
+
+    #[test]
+    fn return_leaves_the_function_at_once() {
+        let stdout = crate::output::OutputBuffer::new();
+        let mut interpreter = Interpreter::default();
+        interpreter.set_stdout(Box::new(stdout.clone()));
+        let result = interpreter.eval("func f() { return 1; print(\"after\") }\nf()");
+        assert_eq!(result.unwrap(), Value::Number(1.0));
+        assert_eq!(stdout.contents(), "");
+    }
+
+    #[test]
+    fn return_unwinds_loops_and_branches_but_only_one_call() {
+        let source = "func find(items, wanted) {
+    let i = 0
+    while i < 3 {
+        if items[i] == wanted { return i }
+        let i = i + 1
+    }
+    nil
+}
+func twice(x) { return find([0, x, 2], x) * 2 }
+[twice(1), find([5, 6, 7], 4)]";
+        assert_eq!(eval(source), Value::List(vec![Value::Number(2.0), Value::Nil]));
+    }
+
+    #[test]
+    fn return_outside_a_function_is_an_error() {
+        assert_eq!(error("return 1"), "'return' outside of a function");
+    }
+}
@@ -1,5 +1,5 @@
 
-use std::fmt;
+use std::{fmt, ops::Range};
 
 use miette::{miette, Error, LabeledSpan, Severity};
 
@@ -10,17 +10,21 @@ pub struct Token {
     pub row: usize,
     pub column: usize,
     pub index: usize,
+    /// Byte offset of the token's first character.
+    pub start: usize,
 }
 
+
+
+
 impl Token {
-    pub fn to_string(&self) -> String {
-        self.kind.to_string()
+    /// Byte range of the token in the source it was lexed from.
+    pub fn span(&self) -> Range<usize> {
+        // `index` ends up one past the byte following the token's last character.
+        self.start..self.index.saturating_sub(1)
     }
-    
 }
 
-
-
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum TokenKind {
     LeftParen,
@@ -30,6 +34,7 @@ pub enum TokenKind {
     LeftBracket,
     RightBracket,
     Comma,
+    Colon,
     Dot,
     Minus,
     Plus,
@@ -41,6 +46,13 @@ pub enum TokenKind {
     String(String),
     Number(String),
     Identifier(String),
+    /// A `//` comment, only produced by [`Lexer::with_comments`] and
+    /// [`Lexer::with_trivia`].
+    Comment(String),
+    /// Spaces and tabs, only produced by [`Lexer::with_trivia`].
+    Whitespace(String),
+    /// Input the lexer rejected, only found in concrete syntax trees.
+    Unknown(String),
     // Comparisons 
     Equal,
     Bang,
@@ -66,11 +78,75 @@ pub enum TokenKind {
     This,
     True,
     While,
+    Test,
+    Import,
+    From,
+    As,
+    Export,
     Block(Vec<String>),
 }
 impl TokenKind {
-    pub fn to_string(&self) -> String {
-        match self {
+    /// The token as it is written in source, e.g. `+`, `while` or `"hi"`.
+    pub fn lexeme(&self) -> String {
+        let lexeme = match self {
+            TokenKind::String(s) => return format!("{:?}", s),
+            TokenKind::Number(n) => n,
+            TokenKind::Identifier(name)
+            | TokenKind::Comment(name)
+            | TokenKind::Whitespace(name)
+            | TokenKind::Unknown(name) => name,
+            TokenKind::LeftParen => "(",
+            TokenKind::RightParen => ")",
+            TokenKind::LeftBrace => "{",
+            TokenKind::RightBrace => "}",
+            TokenKind::LeftBracket => "[",
+            TokenKind::RightBracket => "]",
+            TokenKind::Comma => ",",
+            TokenKind::Colon => ":",
+            TokenKind::Dot => ".",
+            TokenKind::Minus => "-",
+            TokenKind::Plus => "+",
+            TokenKind::Semicolon => ";",
+            TokenKind::Star => "*",
+            TokenKind::Slash => "/",
+            TokenKind::NewLine => "\n",
+            TokenKind::Equal => "=",
+            TokenKind::Bang => "!",
+            TokenKind::BangEqual => "!=",
+            TokenKind::EqualEqual => "==",
+            TokenKind::Greater => ">",
+            TokenKind::GreaterEqual => ">=",
+            TokenKind::Less => "<",
+            TokenKind::LessEqual => "<=",
+            TokenKind::Fun => "func",
+            TokenKind::Let => "let",
+            TokenKind::And => "and",
+            TokenKind::Class => "class",
+            TokenKind::If => "if",
+            TokenKind::Else => "else",
+            TokenKind::False => "false",
+            TokenKind::For => "for",
+            TokenKind::Nil => "nil",
+            TokenKind::Or => "or",
+            TokenKind::Return => "return",
+            TokenKind::Super => "super",
+            TokenKind::This => "this",
+            TokenKind::True => "true",
+            TokenKind::While => "while",
+            TokenKind::Test => "test",
+            TokenKind::Import => "import",
+            TokenKind::From => "from",
+            TokenKind::As => "as",
+            TokenKind::Export => "export",
+            TokenKind::EOF | TokenKind::Block(_) => "",
+        };
+        lexeme.to_string()
+    }
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
             TokenKind::LeftParen => "LeftParen".to_string(),
             TokenKind::RightParen => "RightParen".to_string(),
             TokenKind::LeftBrace => "LeftBrace".to_string(),
@@ -78,6 +154,7 @@ impl TokenKind {
             TokenKind::LeftBracket => "LeftBracket".to_string(),
             TokenKind::RightBracket => "RightBracket".to_string(),
             TokenKind::Comma => "Comma".to_string(),
+            TokenKind::Colon => "Colon".to_string(),
             TokenKind::Dot => "Dot".to_string(),
             TokenKind::Minus => "Minus".to_string(),
             TokenKind::Plus => "Plus".to_string(),
@@ -89,6 +166,9 @@ impl TokenKind {
             TokenKind::String(s) => format!("String({})", s),
             TokenKind::Number(n) => format!("Number({})", n),
             TokenKind::Identifier(s) => s.to_string(),
+            TokenKind::Comment(s) => format!("Comment({})", s),
+            TokenKind::Whitespace(s) => format!("Whitespace({:?})", s),
+            TokenKind::Unknown(s) => format!("Unknown({})", s),
             TokenKind::Equal => "Equal".to_string(),
             TokenKind::Bang => "Bang".to_string(),
             TokenKind::BangEqual => "BangEqual".to_string(),
@@ -112,17 +192,39 @@ impl TokenKind {
             TokenKind::This => "This".to_string(),
             TokenKind::True => "True".to_string(),
             TokenKind::While => "While".to_string(),
+            TokenKind::Test => "Test".to_string(),
+            TokenKind::Import => "Import".to_string(),
+            TokenKind::From => "From".to_string(),
+            TokenKind::As => "As".to_string(),
+            TokenKind::Export => "Export".to_string(),
             TokenKind::Block(s) => format!("Block({:?})", s)
-        }
+        };
+        write!(f, "{}", s)
     }
 }
 
+/// Diagnostic code of the error for a string literal still open at the end of input.
+pub const UNTERMINATED_STRING: &str = "merc::unterminated_string";
+
+/// Reserved words recognised by [`Lexer::next`].
+pub const KEYWORDS: &[&str] = &[
+    "and", "as", "class", "else", "export", "false", "func", "for", "from", "if", "import", "nil",
+    "or", "return", "super", "test", "this", "true", "let", "while",
+];
+
 pub struct Lexer<'a> {
     whole_input: &'a str,
     rest_input: &'a str,
     current_line: usize,
     current_column: usize,
     index: usize,
+    /// Byte offset of the first character of the token being lexed.
+    start: usize,
+    /// Emit comments as tokens instead of skipping them.
+    comments: bool,
+    /// Emit runs of spaces and tabs as tokens too, so that the tokens cover
+    /// every byte of the input.
+    trivia: bool,
 }
 
 impl fmt::Display for Token {
@@ -140,6 +242,53 @@ impl<'a> Lexer<'a> {
             current_line: 1,
             current_column: 1,
             index: 1,
+            start: 0,
+            comments: false,
+            trivia: false,
+        }
+    }
+
+    /// A lexer that also produces [`TokenKind::Comment`] tokens, for tools
+    /// such as the formatter that must keep comments.
+    pub fn with_comments(input: &'a str) -> Self {
+        Lexer {
+            comments: true,
+            ..Lexer::new(input)
+        }
+    }
+
+    /// A lexer that produces every piece of the input as a token, including
+    /// [`TokenKind::Whitespace`] and [`TokenKind::Comment`], for the lossless
+    /// [concrete syntax tree](crate::cst).
+    pub fn with_trivia(input: &'a str) -> Self {
+        Lexer {
+            comments: true,
+            trivia: true,
+            ..Lexer::new(input)
+        }
+    }
+
+    /// Byte offset of the first character not yet consumed.
+    pub fn offset(&self) -> usize {
+        self.index - 1
+    }
+
+    /// Reads the rest of a `//` comment whose first slash was just consumed,
+    /// leaving the line break that ends it for the next token.
+    fn comment(&mut self) -> Token {
+        let end = self
+            .rest_input
+            .find(['\n', '\r'])
+            .unwrap_or(self.rest_input.len());
+        let text = format!("/{}", &self.rest_input[..end]);
+        self.rest_input = &self.rest_input[end..];
+        self.index += end;
+        Token {
+            kind: TokenKind::Comment(text),
+            row: self.current_line,
+            column: self.current_column,
+            index: self.index,
+            start: self.start,
         }
     }
 }
@@ -148,86 +297,71 @@ impl<'a> Iterator for Lexer<'a> {
 
     fn next(&mut self) -> Option<Self::Item> {
         let c = self.rest_input.chars().next()?;
-        self.rest_input = &self.rest_input[1..];
+        self.start = self.offset();
+        self.rest_input = &self.rest_input[c.len_utf8()..];
 
-        self.index += 1;
+        self.index += c.len_utf8();
 
-        if c== ' '{
+        if c == ' ' || c == '\t' {
             self.current_column += 1;
-            return self.next();
-        }
-
-        if self.current_column == 1 && c == '/' && self.rest_input.chars().next() == Some('/') {
-            while let Some(c) = self.rest_input.chars().next() {
+            if !self.trivia {
+                return self.next();
+            }
+            let mut whitespace = String::from(c);
+            while let Some(c) = self.rest_input.chars().next().filter(|c| *c == ' ' || *c == '\t') {
                 self.rest_input = &self.rest_input[1..];
                 self.index += 1;
-                if c == '\n' {
-                    break;
-                }
+                self.current_column += 1;
+                whitespace.push(c);
             }
-            self.current_line += 1;
-            self.current_column = 1;
-            return self.next();
+            return Some(Ok(Token { kind: TokenKind::Whitespace(whitespace), row: self.current_line, column: self.current_column, index: self.index, start: self.start, }));
         }
-        
-       
 
         let token = match c {
             ' ' =>{
                 self.current_column += 1;
                 return self.next();
                 },
-            '/' =>{
-            if self.rest_input.starts_with("/") {
-                    while let Some(c) = self.rest_input.chars().next() {
-                        self.rest_input = &self.rest_input[1..];
-                        self.index += 1;
-                        if c == '\n' {
-                            break;
-                        }
-                    }
-                self.current_line += 1;
-                self.current_column = 1;
-                return self.next();
-                } else {
-                    Ok(Token { kind: TokenKind::Slash, row: self.current_line, column: self.current_column, index: self.index,})
+            '/' => {
+                if self.rest_input.starts_with('/') {
+                    let comment = self.comment();
+                    if !self.comments {
+                        return self.next();
                     }
+                    return Some(Ok(comment));
                 }
-            '(' => Ok(Token { kind: TokenKind::LeftParen, row: self.current_line, column: self.current_column, index: self.index, }),
-            ')' => Ok(Token { kind: TokenKind::RightParen, row: self.current_line, column: self.current_column, index: self.index, }),
-            '{' => Ok(Token { kind: TokenKind::LeftBrace, row: self.current_line, column: self.current_column, index: self.index, }),
-            '}' => Ok(Token { kind: TokenKind::RightBrace, row: self.current_line, column: self.current_column, index: self.index,  }),
-            '[' => Ok(Token { kind: TokenKind::LeftBracket, row: self.current_line, column: self.current_column, index: self.index,  }),
-            ']' => Ok(Token { kind: TokenKind::RightBracket, row: self.current_line, column: self.current_column, index: self.index,  }),
-            ',' => Ok(Token { kind: TokenKind::Comma, row: self.current_line, column: self.current_column, index: self.index, }),
-            '.' => Ok(Token { kind: TokenKind::Dot, row: self.current_line, column: self.current_column, index: self.index, }),
-            '-' => Ok(Token { kind: TokenKind::Minus, row: self.current_line, column: self.current_column, index: self.index, }),
-            '+' => Ok(Token { kind: TokenKind::Plus, row: self.current_line, column: self.current_column, index: self.index, }),
-            ';' => Ok(Token { kind: TokenKind::Semicolon, row: self.current_line, column: self.current_column, index: self.index,  }),
-            '*' => Ok(Token { kind: TokenKind::Star, row: self.current_line, column: self.current_column, index: self.index, }),
+                Ok(Token { kind: TokenKind::Slash, row: self.current_line, column: self.current_column, index: self.index, start: self.start,})
+            }
+            '(' => Ok(Token { kind: TokenKind::LeftParen, row: self.current_line, column: self.current_column, index: self.index, start: self.start, }),
+            ')' => Ok(Token { kind: TokenKind::RightParen, row: self.current_line, column: self.current_column, index: self.index, start: self.start, }),
+            '{' => Ok(Token { kind: TokenKind::LeftBrace, row: self.current_line, column: self.current_column, index: self.index, start: self.start, }),
+            '}' => Ok(Token { kind: TokenKind::RightBrace, row: self.current_line, column: self.current_column, index: self.index, start: self.start,  }),
+            '[' => Ok(Token { kind: TokenKind::LeftBracket, row: self.current_line, column: self.current_column, index: self.index, start: self.start,  }),
+            ']' => Ok(Token { kind: TokenKind::RightBracket, row: self.current_line, column: self.current_column, index: self.index, start: self.start,  }),
+            ',' => Ok(Token { kind: TokenKind::Comma, row: self.current_line, column: self.current_column, index: self.index, start: self.start, }),
+            ':' => Ok(Token { kind: TokenKind::Colon, row: self.current_line, column: self.current_column, index: self.index, start: self.start, }),
+            '.' => Ok(Token { kind: TokenKind::Dot, row: self.current_line, column: self.current_column, index: self.index, start: self.start, }),
+            '-' => Ok(Token { kind: TokenKind::Minus, row: self.current_line, column: self.current_column, index: self.index, start: self.start, }),
+            '+' => Ok(Token { kind: TokenKind::Plus, row: self.current_line, column: self.current_column, index: self.index, start: self.start, }),
+            ';' => Ok(Token { kind: TokenKind::Semicolon, row: self.current_line, column: self.current_column, index: self.index, start: self.start,  }),
+            '*' => Ok(Token { kind: TokenKind::Star, row: self.current_line, column: self.current_column, index: self.index, start: self.start, }),
             '\n' | '\r' => {
-                if c == '\r' && self.rest_input.chars().next() == Some('\n') {
+                if c == '\r' && self.rest_input.starts_with('\n') {
                     self.rest_input = &self.rest_input[1..];
                     self.index += 1;
                     }
                     self.current_line += 1;
                     self.current_column = 1;
-                    Ok(Token { kind: TokenKind::NewLine, row: self.current_line, column: self.current_column, index: self.index,  })
+                    Ok(Token { kind: TokenKind::NewLine, row: self.current_line, column: self.current_column, index: self.index, start: self.start,  })
                 
             },
             '=' => {
-                while c == ' ' {
-                    self.rest_input = &self.rest_input[1..];
-                    self.index += 1;
-                    self.next();
-                }
-
                 if self.rest_input.starts_with("=") {
                     self.rest_input = &self.rest_input[1..];
                     self.index += 1;
-                    Ok(Token { kind: TokenKind::EqualEqual, row: self.current_line, column: self.current_column, index: self.index,  })
+                    Ok(Token { kind: TokenKind::EqualEqual, row: self.current_line, column: self.current_column, index: self.index, start: self.start,  })
                 } else {
-                    Ok(Token { kind: TokenKind::Equal, row: self.current_line, column: self.current_column, index: self.index, })
+                    Ok(Token { kind: TokenKind::Equal, row: self.current_line, column: self.current_column, index: self.index, start: self.start, })
                 }
             },
             '!' => {
@@ -241,9 +375,9 @@ impl<'a> Iterator for Lexer<'a> {
                 if self.rest_input.starts_with("=") {
                     self.rest_input = &self.rest_input[1..];
                     self.index += 1;
-                    Ok(Token { kind: TokenKind::BangEqual, row: self.current_line, column: self.current_column, index: self.index, })
+                    Ok(Token { kind: TokenKind::BangEqual, row: self.current_line, column: self.current_column, index: self.index, start: self.start, })
                 } else {
-                    Ok(Token { kind: TokenKind::Bang, row: self.current_line, column: self.current_column, index: self.index,})
+                    Ok(Token { kind: TokenKind::Bang, row: self.current_line, column: self.current_column, index: self.index, start: self.start,})
                 }
             },
             '<' => {
@@ -255,9 +389,9 @@ impl<'a> Iterator for Lexer<'a> {
                 if self.rest_input.starts_with("=") {
                     self.rest_input = &self.rest_input[1..];
                     self.index += 1;
-                    Ok(Token { kind: TokenKind::LessEqual, row: self.current_line, column: self.current_column, index: self.index, })
+                    Ok(Token { kind: TokenKind::LessEqual, row: self.current_line, column: self.current_column, index: self.index, start: self.start, })
                 } else {
-                    Ok(Token { kind: TokenKind::Less, row: self.current_line, column: self.current_column, index: self.index,})
+                    Ok(Token { kind: TokenKind::Less, row: self.current_line, column: self.current_column, index: self.index, start: self.start,})
                 }
             },
             '>' => {
@@ -270,31 +404,48 @@ impl<'a> Iterator for Lexer<'a> {
                 if self.rest_input.starts_with("=") {
                     self.rest_input = &self.rest_input[1..];
                     self.index += 1;
-                    Ok(Token { kind: TokenKind::GreaterEqual, row: self.current_line, column: self.current_column, index: self.index,})
+                    Ok(Token { kind: TokenKind::GreaterEqual, row: self.current_line, column: self.current_column, index: self.index, start: self.start,})
                 } else {
-                    Ok(Token { kind: TokenKind::Greater, row: self.current_line, column: self.current_column, index: self.index, })
+                    Ok(Token { kind: TokenKind::Greater, row: self.current_line, column: self.current_column, index: self.index, start: self.start, })
                 }
             },
 
             '"' => {
                 let mut string:String = String::new();
+                let mut escaped = false;
                 while let Some(c) = self.rest_input.chars().next() {
-                    self.rest_input = &self.rest_input[1..];
-                    self.index += 1;
+                    self.rest_input = &self.rest_input[c.len_utf8()..];
+                    self.index += c.len_utf8();
                     self.current_column += 1;
 
-                    if c == '"' {
-                        return Some(Ok(Token { kind: TokenKind::String(string), row: self.current_line, column: self.current_column, index: self.index, }));
-                    }
                     if c == '\n' {
                         self.current_line += 1;
                         self.current_column = 1;
                     }
+                    if escaped {
+                        escaped = false;
+                        string.push(match c {
+                            'n' => '\n',
+                            't' => '\t',
+                            'r' => '\r',
+                            '0' => '\0',
+                            other => other,
+                        });
+                        continue;
+                    }
+                    if c == '\\' {
+                        escaped = true;
+                        continue;
+                    }
+                    if c == '"' {
+                        return Some(Ok(Token { kind: TokenKind::String(string), row: self.current_line, column: self.current_column, index: self.index, start: self.start, }));
+                    }
                     string.push(c);
             }
                 Err(miette! {
                     labels = vec![LabeledSpan::at(self.index-2..self.index, "Unterminated string")],
                     severity = Severity::Error,
+                    code = UNTERMINATED_STRING,
                     help = "this string is not terminated",
                     "Unterminated string: at line: {} column: {} character: {}", self.current_line, self.current_column, c
                 }.with_source_code(self.whole_input.to_string())
@@ -306,14 +457,9 @@ impl<'a> Iterator for Lexer<'a> {
 
                 while let Some(c) = self.rest_input.chars().next() {
 
-                    if c.is_numeric() { 
-                        self.rest_input = &self.rest_input[1..];
-                        self.index += 1;
-                        self.current_column += 1;
-                        number.push(c);
-                    }else if !number.contains(".") && c == '.' {
-                        self.rest_input = &self.rest_input[1..];
-                        self.index += 1;
+                    if c.is_numeric() || (!number.contains('.') && c == '.') {
+                        self.rest_input = &self.rest_input[c.len_utf8()..];
+                        self.index += c.len_utf8();
                         self.current_column += 1;
                         number.push(c);
                     
@@ -323,19 +469,23 @@ impl<'a> Iterator for Lexer<'a> {
                   
                 }
 
+                // A trailing dot is not part of the number, as in `1.foo`.
                 if number.ends_with('.') {
-                    self.rest_input= &self.whole_input[self.index-2..];
+                    number.pop();
+                    self.rest_input = &self.whole_input[self.index - 2..];
+                    self.index -= 1;
+                    self.current_column -= 1;
                 }
                 
               
-                Ok(Token { kind: TokenKind::Number(number), row: self.current_line, column: self.current_column, index: self.index, })
+                Ok(Token { kind: TokenKind::Number(number), row: self.current_line, column: self.current_column, index: self.index, start: self.start, })
             },
             'a'..='z' | 'A'..='Z' | '_' => {
                 let mut identifier = String::from(c);
                 while let Some(c) = self.rest_input.chars().next() {
                     if c.is_alphanumeric() || c == '_' {
-                        self.rest_input = &self.rest_input[1..];
-                        self.index += 1;
+                        self.rest_input = &self.rest_input[c.len_utf8()..];
+                        self.index += c.len_utf8();
                         self.current_column += 1;
                         identifier.push(c);
                     } else {
@@ -344,30 +494,35 @@ impl<'a> Iterator for Lexer<'a> {
                 }
 
                 match identifier.as_str() {
-                    "and" => return Some(Ok(Token{kind: TokenKind::And, row: self.current_line, column: self.current_column, index: self.index, })),
-                    "class" => return Some(Ok(Token{kind: TokenKind::Class, row: self.current_line, column: self.current_column, index: self.index, })),
-                    "else" => return Some(Ok(Token { kind: TokenKind::Else, row: self.current_line, column: self.current_column, index: self.index, })),
-                    "false" => return Some(Ok(Token{kind: TokenKind::False, row: self.current_line, column: self.current_column, index: self.index, })),
-                    "func" => return Some(Ok(Token{kind: TokenKind::Fun, row: self.current_line, column: self.current_column, index: self.index, })),
-                    "for" => return Some(Ok(Token{kind: TokenKind::For, row: self.current_line, column: self.current_column, index: self.index, })),
-                    "if" => return Some(Ok(Token{kind: TokenKind::If, row: self.current_line, column: self.current_column, index: self.index, })),
-                    "nil" => return Some(Ok(Token{kind: TokenKind::Nil, row: self.current_line, column: self.current_column, index: self.index, })),
-                    "or" => return Some(Ok(Token{kind: TokenKind::Or, row: self.current_line, column: self.current_column, index: self.index, })),
-                    "return" => return Some(Ok(Token{kind: TokenKind::Return, row: self.current_line, column: self.current_column, index: self.index, })),
-                    "super" => return Some(Ok(Token{kind: TokenKind::Super, row: self.current_line, column: self.current_column, index: self.index, })),
-                    "this" => return Some(Ok(Token{kind: TokenKind::This, row: self.current_line, column: self.current_column, index: self.index, })),
-                    "true" => return Some(Ok(Token{kind: TokenKind::True, row: self.current_line, column: self.current_column, index: self.index, })),
-                    "let" => return Some(Ok(Token{kind: TokenKind::Let, row: self.current_line, column: self.current_column, index: self.index, })),
-                    "while" => return Some(Ok(Token{kind: TokenKind::While, row: self.current_line, column: self.current_column, index: self.index, })),
+                    "and" => return Some(Ok(Token{kind: TokenKind::And, row: self.current_line, column: self.current_column, index: self.index, start: self.start, })),
+                    "as" => return Some(Ok(Token{kind: TokenKind::As, row: self.current_line, column: self.current_column, index: self.index, start: self.start, })),
+                    "class" => return Some(Ok(Token{kind: TokenKind::Class, row: self.current_line, column: self.current_column, index: self.index, start: self.start, })),
+                    "else" => return Some(Ok(Token { kind: TokenKind::Else, row: self.current_line, column: self.current_column, index: self.index, start: self.start, })),
+                    "export" => return Some(Ok(Token{kind: TokenKind::Export, row: self.current_line, column: self.current_column, index: self.index, start: self.start, })),
+                    "false" => return Some(Ok(Token{kind: TokenKind::False, row: self.current_line, column: self.current_column, index: self.index, start: self.start, })),
+                    "func" => return Some(Ok(Token{kind: TokenKind::Fun, row: self.current_line, column: self.current_column, index: self.index, start: self.start, })),
+                    "for" => return Some(Ok(Token{kind: TokenKind::For, row: self.current_line, column: self.current_column, index: self.index, start: self.start, })),
+                    "from" => return Some(Ok(Token{kind: TokenKind::From, row: self.current_line, column: self.current_column, index: self.index, start: self.start, })),
+                    "if" => return Some(Ok(Token{kind: TokenKind::If, row: self.current_line, column: self.current_column, index: self.index, start: self.start, })),
+                    "import" => return Some(Ok(Token{kind: TokenKind::Import, row: self.current_line, column: self.current_column, index: self.index, start: self.start, })),
+                    "nil" => return Some(Ok(Token{kind: TokenKind::Nil, row: self.current_line, column: self.current_column, index: self.index, start: self.start, })),
+                    "or" => return Some(Ok(Token{kind: TokenKind::Or, row: self.current_line, column: self.current_column, index: self.index, start: self.start, })),
+                    "return" => return Some(Ok(Token{kind: TokenKind::Return, row: self.current_line, column: self.current_column, index: self.index, start: self.start, })),
+                    "super" => return Some(Ok(Token{kind: TokenKind::Super, row: self.current_line, column: self.current_column, index: self.index, start: self.start, })),
+                    "test" => return Some(Ok(Token{kind: TokenKind::Test, row: self.current_line, column: self.current_column, index: self.index, start: self.start, })),
+                    "this" => return Some(Ok(Token{kind: TokenKind::This, row: self.current_line, column: self.current_column, index: self.index, start: self.start, })),
+                    "true" => return Some(Ok(Token{kind: TokenKind::True, row: self.current_line, column: self.current_column, index: self.index, start: self.start, })),
+                    "let" => return Some(Ok(Token{kind: TokenKind::Let, row: self.current_line, column: self.current_column, index: self.index, start: self.start, })),
+                    "while" => return Some(Ok(Token{kind: TokenKind::While, row: self.current_line, column: self.current_column, index: self.index, start: self.start, })),
                     _ => {}
                 }
 
-                Ok(Token{kind: TokenKind::Identifier(identifier), row: self.current_line, column: self.current_column, index: self.index, })
+                Ok(Token{kind: TokenKind::Identifier(identifier), row: self.current_line, column: self.current_column, index: self.index, start: self.start, })
             },
 
             _ => Err(miette! {
                 labels = vec![
-                    LabeledSpan::at(self.index-2..self.index-1, "Unexpected character" )
+                    LabeledSpan::at(self.start..self.offset(), "Unexpected character" )
                     ],
                     severity  = Severity::Error,
                     help = "Please use valid characters",
@@ -379,3 +534,39 @@ impl<'a> Iterator for Lexer<'a> {
         Some(token)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The source text under each token's span.
+    fn lexemes(source: &str) -> Vec<&str> {
+        Lexer::new(source)
+            .map(|token| &source[token.expect("lexes").span()])
+            .collect()
+    }
+
+    #[test]
+    fn spans_cover_each_token() {
+        assert_eq!(
+            lexemes("let x = foo(1.5, y) != nil\nreturn x"),
+            ["let", "x", "=", "foo", "(", "1.5", ",", "y", ")", "!=", "nil", "\n", "return", "x"]
+        );
+        assert_eq!(lexemes("a >= b and !c"), ["a", ">=", "b", "and", "!", "c"]);
+    }
+
+    #[test]
+    fn string_spans_cover_the_literal_as_written() {
+        assert_eq!(lexemes("f(\"a\tb\")"), ["f", "(", "\"a\tb\"", ")"]);
+        assert_eq!(lexemes(r#"f("a\nb")"#), ["f", "(", r#""a\nb""#, ")"]);
+        assert_eq!(lexemes(r#""say \"hi\"" "é""#), [r#""say \"hi\"""#, r#""é""#]);
+        assert_eq!(lexemes("\"two\nlines\" x"), ["\"two\nlines\"", "x"]);
+    }
+
+    #[test]
+    fn unexpected_characters_are_labelled_whole() {
+        let error = Lexer::new("x = é").find_map(Result::err).expect("an error");
+        let label = error.labels().and_then(|mut labels| labels.next()).expect("a label");
+        assert_eq!(label.offset()..label.offset() + label.len(), 4..6);
+    }
+}
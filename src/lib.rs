@@ -7,6 +7,34 @@ pub use parser::Parser;
 pub use parser::S;
 pub use parser::Op;
 
+pub mod resolver;
+pub use resolver::Resolver;
+
+pub mod highlight;
 pub mod repl;
 pub mod interpreter;
 pub use interpreter::Interpreter;
+pub use interpreter::Value;
+
+pub mod convert;
+pub use convert::{FromValue, IntoValue};
+
+pub mod error;
+pub use error::ScriptError;
+
+pub mod coverage;
+pub mod cst;
+pub mod debugger;
+pub mod format;
+pub mod lint;
+pub mod lsp;
+pub mod modules;
+pub mod output;
+pub mod pretty;
+pub mod profiler;
+pub mod serialization;
+pub mod stdlib;
+pub mod testing;
+
+pub mod engine;
+pub use engine::Engine;
@@ -0,0 +1,585 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+};
+
+use miette::{miette, LabeledSpan, Report, Severity};
+
+use crate::{lexer::Token, pretty::Printer, Lexer, TokenKind, S};
+
+/// Every lint with a short description of what it catches.
+pub const RULES: &[(&str, &str)] = &[
+    ("unused-variable", "A `let` binding, parameter or import that is never read"),
+    ("unused-function", "A function that is never called"),
+    ("shadowing", "A local or parameter hiding a global or builtin of the same name"),
+    ("unreachable-code", "Statements after a `return` in the same block"),
+    ("self-comparison", "A value compared with itself"),
+    ("constant-condition", "An `if` or `while` whose condition never changes"),
+];
+
+/// Looks for likely mistakes in a parsed program and reports them as
+/// warnings. Scoping follows [`Resolver`](crate::Resolver): function bodies
+/// get their own locals and see every global.
+///
+/// A warning is silenced by a `// merc-allow(rule)` comment on the line it
+/// points at, or alone on the line above; several rules can be listed,
+/// separated by commas.
+pub struct Linter<'a> {
+    source: &'a str,
+    builtins: HashSet<String>,
+    allowed: HashSet<String>,
+    /// Rules allowed by comments, by the line they apply to.
+    suppressed: HashMap<usize, Vec<String>>,
+    globals: HashSet<String>,
+    /// The global scope, then the scope of the function being walked.
+    scopes: Vec<Scope>,
+    diagnostics: Vec<Report>,
+}
+
+#[derive(Default)]
+struct Scope {
+    bindings: Vec<Binding>,
+    reads: HashSet<String>,
+}
+
+struct Binding {
+    name: String,
+    token: Token,
+    kind: BindingKind,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum BindingKind {
+    Variable,
+    Parameter,
+    Function,
+    Import,
+}
+
+impl<'a> Linter<'a> {
+    pub fn new(source: &'a str, builtins: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            source,
+            builtins: builtins.into_iter().collect(),
+            allowed: HashSet::new(),
+            suppressed: suppressions(source),
+            globals: HashSet::new(),
+            scopes: vec![Scope::default()],
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// Turns off `rule` for the whole program.
+    pub fn allow(mut self, rule: &str) -> Self {
+        self.allowed.insert(rule.to_string());
+        self
+    }
+
+    /// Lints `program` and returns one warning per problem, in source order.
+    pub fn lint(mut self, program: &[S]) -> Vec<Report> {
+        for statement in program {
+            self.hoist(statement);
+        }
+        self.statements(program);
+        self.finish_scope();
+
+        let start = |report: &Report| {
+            report
+                .labels()
+                .and_then(|mut labels| labels.next())
+                .map_or(0, |label| label.offset())
+        };
+        self.diagnostics.sort_by_key(start);
+        self.diagnostics
+    }
+
+    /// Collects the names defined by top-level code.
+    fn hoist(&mut self, node: &S) {
+        match node {
+            S::Cons(token, args) if token.kind == TokenKind::Let => {
+                if let Some(name) = args.first().and_then(identifier) {
+                    self.globals.insert(name.to_string());
+                }
+            }
+            S::Cons(token, args) if matches!(token.kind, TokenKind::While | TokenKind::Export) => {
+                args.iter().for_each(|s| self.hoist(s))
+            }
+            S::Cons(token, args) if matches!(token.kind, TokenKind::Import | TokenKind::From) => {
+                let names = args.iter().skip(1).filter_map(identifier);
+                self.globals.extend(names.map(str::to_string));
+            }
+            S::FunDef { name, .. } => {
+                if let Some(name) = identifier(name) {
+                    self.globals.insert(name.to_string());
+                }
+            }
+            S::Block(statements) => statements.iter().for_each(|s| self.hoist(s)),
+            S::IfExpr {
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.hoist(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.hoist(else_branch);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn statements(&mut self, statements: &[S]) {
+        let mut returned = false;
+        let mut warned = false;
+        for statement in statements {
+            // Only the first unreachable statement is reported, but the rest
+            // are still walked so the names they read count as used.
+            if returned && !warned {
+                warned = true;
+                if let Some(token) = statement.first_token() {
+                    self.warn(
+                        "unreachable-code",
+                        token.span(),
+                        "Unreachable code after `return`".to_string(),
+                        "never runs",
+                    );
+                }
+            }
+            returned |= matches!(statement, S::Cons(token, _) if token.kind == TokenKind::Return);
+            self.visit(statement);
+        }
+    }
+
+    fn visit(&mut self, node: &S) {
+        match node {
+            S::Atom(token) => {
+                if let TokenKind::Identifier(name) = &token.kind {
+                    self.read(name);
+                }
+            }
+            S::Cons(token, args) => match (&token.kind, args.as_slice()) {
+                (TokenKind::Let, [name, value]) => {
+                    self.visit(value);
+                    if let S::Atom(name) = name {
+                        self.bind(name, BindingKind::Variable);
+                    }
+                }
+                (TokenKind::While, [cond, body]) => {
+                    let looping = matches!(cond, S::Atom(Token { kind: TokenKind::True, .. }));
+                    if !looping {
+                        self.check_condition(cond);
+                    }
+                    self.visit(cond);
+                    self.visit(body);
+                }
+                (TokenKind::Import | TokenKind::From, [_path, names @ ..]) => {
+                    for name in names {
+                        if let S::Atom(name) = name {
+                            self.bind(name, BindingKind::Import);
+                        }
+                    }
+                }
+                // Exported names are used by the files importing them.
+                (TokenKind::Export, [declaration]) => {
+                    self.visit(declaration);
+                    let name = match declaration {
+                        S::Cons(_, args) => args.first(),
+                        S::FunDef { name, .. } => Some(name.as_ref()),
+                        _ => None,
+                    };
+                    if let Some(name) = name.and_then(identifier) {
+                        self.read(name);
+                    }
+                }
+                (TokenKind::Dot, [object, _member]) => self.visit(object),
+                (kind, [lhs, rhs]) if is_comparison(kind) => {
+                    self.check_self_comparison(node, lhs, rhs);
+                    self.visit(lhs);
+                    self.visit(rhs);
+                }
+                _ => args.iter().for_each(|arg| self.visit(arg)),
+            },
+            S::BinaryExpr { lhs, rhs, .. } => {
+                self.visit(lhs);
+                self.visit(rhs);
+            }
+            S::IfExpr {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                self.check_condition(cond);
+                self.visit(cond);
+                self.visit(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.visit(else_branch);
+                }
+            }
+            S::Block(statements) => self.statements(statements),
+            S::List(items) => items.iter().for_each(|item| self.visit(item)),
+            S::Map(entries) => entries.iter().for_each(|(_, value)| self.visit(value)),
+            S::FunDef { name, args, body } => {
+                if let S::Atom(name) = name.as_ref() {
+                    self.bind(name, BindingKind::Function);
+                }
+                self.scopes.push(Scope::default());
+                for arg in args {
+                    if let S::Atom(param) = arg {
+                        self.bind(param, BindingKind::Parameter);
+                    }
+                }
+                self.visit(body);
+                self.finish_scope();
+            }
+            S::FunCall { name, args } => {
+                self.visit(name);
+                args.iter().for_each(|arg| self.visit(arg));
+            }
+        }
+    }
+
+    fn bind(&mut self, token: &Token, kind: BindingKind) {
+        let TokenKind::Identifier(name) = &token.kind else {
+            return;
+        };
+        let local = self.scopes.len() > 1;
+        let scope = self.scopes.last_mut().expect("the global scope is never popped");
+        // Binding a name again in the same scope replaces it, which is how
+        // loops update variables.
+        if scope.bindings.iter().any(|binding| &binding.name == name) {
+            return;
+        }
+        scope.bindings.push(Binding {
+            name: name.clone(),
+            token: token.clone(),
+            kind,
+        });
+
+        if self.builtins.contains(name) {
+            self.warn(
+                "shadowing",
+                token.span(),
+                format!("`{}` shadows the builtin of the same name", name),
+                "shadows a builtin",
+            );
+        } else if local && self.globals.contains(name) {
+            self.warn(
+                "shadowing",
+                token.span(),
+                format!("`{}` shadows the global of the same name", name),
+                "shadows a global",
+            );
+        }
+    }
+
+    fn read(&mut self, name: &str) {
+        let scope = match self.scopes.last_mut() {
+            Some(scope) if scope.bindings.iter().any(|binding| binding.name == name) => scope,
+            _ => &mut self.scopes[0],
+        };
+        scope.reads.insert(name.to_string());
+    }
+
+    /// Reports the bindings of the innermost scope that were never read.
+    fn finish_scope(&mut self) {
+        let scope = self.scopes.pop().expect("a scope to finish");
+        for binding in &scope.bindings {
+            if scope.reads.contains(&binding.name) || binding.name.starts_with('_') {
+                continue;
+            }
+            let (rule, message) = match binding.kind {
+                BindingKind::Variable => ("unused-variable", "Variable"),
+                BindingKind::Parameter => ("unused-variable", "Parameter"),
+                BindingKind::Function => ("unused-function", "Function"),
+                BindingKind::Import => ("unused-variable", "Import"),
+            };
+            self.warn(
+                rule,
+                binding.token.span(),
+                format!("{} `{}` is never used", message, binding.name),
+                "never used",
+            );
+        }
+    }
+
+    fn check_condition(&mut self, cond: &S) {
+        if is_constant(cond) {
+            if let Some(span) = cond.span() {
+                let span = balance(self.source, span);
+                self.warn(
+                    "constant-condition",
+                    span,
+                    "This condition is always the same".to_string(),
+                    "constant condition",
+                );
+            }
+        }
+    }
+
+    fn check_self_comparison(&mut self, comparison: &S, lhs: &S, rhs: &S) {
+        let printer = Printer::new();
+        if has_call(lhs) || printer.sexpr(lhs) != printer.sexpr(rhs) {
+            return;
+        }
+        // Not "always true": `x == x` is false when `x` is NaN.
+        if let Some(span) = comparison.span() {
+            let span = balance(self.source, span);
+            self.warn(
+                "self-comparison",
+                span,
+                format!("Comparing `{}` with itself is redundant", printer.sexpr(lhs)),
+                "compares a value with itself",
+            );
+        }
+    }
+
+    fn warn(&mut self, rule: &str, span: Range<usize>, message: String, label: &str) {
+        if self.allowed.contains(rule) {
+            return;
+        }
+        let line = self.source[..span.start.min(self.source.len())].matches('\n').count();
+        if self
+            .suppressed
+            .get(&line)
+            .is_some_and(|rules| rules.iter().any(|allowed| allowed == rule))
+        {
+            return;
+        }
+
+        self.diagnostics.push(
+            miette!(
+                labels = vec![LabeledSpan::at(span, label)],
+                severity = Severity::Warning,
+                code = rule.to_string(),
+                help = format!("add `// merc-allow({})` to allow this", rule),
+                "{}",
+                message
+            )
+            .with_source_code(self.source.to_string()),
+        );
+    }
+}
+
+/// Reads `// merc-allow(rule, ...)` comments. A comment after code applies
+/// to its own line, and one alone on a line to the line below.
+fn suppressions(source: &str) -> HashMap<usize, Vec<String>> {
+    let mut suppressed: HashMap<usize, Vec<String>> = HashMap::new();
+    for token in Lexer::with_comments(source).flatten() {
+        let TokenKind::Comment(text) = &token.kind else {
+            continue;
+        };
+        let Some(rules) = text
+            .split_once("merc-allow(")
+            .and_then(|(_, rest)| rest.split_once(')'))
+            .map(|(rules, _)| rules)
+        else {
+            continue;
+        };
+
+        let start = token.start;
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line = source[..start].matches('\n').count();
+        let alone = source[line_start..start].trim().is_empty();
+        let target = if alone { line + 1 } else { line };
+        suppressed
+            .entry(target)
+            .or_default()
+            .extend(rules.split(',').map(|rule| rule.trim().to_string()));
+    }
+    suppressed
+}
+
+/// `span` widened to take in the brackets the node starts or ends inside
+/// of, which [`S::span`] leaves out, e.g. the parentheses of `(x) < (y)`.
+fn balance(source: &str, span: Range<usize>) -> Range<usize> {
+    let (mut unclosed, mut unopened) = (0, 0);
+    for c in source[span.clone()].chars() {
+        match c {
+            '(' | '[' | '{' => unclosed += 1,
+            ')' | ']' | '}' if unclosed > 0 => unclosed -= 1,
+            ')' | ']' | '}' => unopened += 1,
+            _ => {}
+        }
+    }
+
+    let mut start = span.start;
+    while unopened > 0 {
+        let Some(open) = source[..start].rfind(['(', '[', '{']) else {
+            break;
+        };
+        start = open;
+        unopened -= 1;
+    }
+    let mut end = span.end;
+    while unclosed > 0 {
+        let Some(close) = source[end..].find([')', ']', '}']) else {
+            break;
+        };
+        end += close + 1;
+        unclosed -= 1;
+    }
+    start..end
+}
+
+fn identifier(node: &S) -> Option<&str> {
+    match node {
+        S::Atom(Token {
+            kind: TokenKind::Identifier(name),
+            ..
+        }) => Some(name),
+        _ => None,
+    }
+}
+
+fn is_comparison(kind: &TokenKind) -> bool {
+    matches!(
+        kind,
+        TokenKind::EqualEqual
+            | TokenKind::BangEqual
+            | TokenKind::Less
+            | TokenKind::LessEqual
+            | TokenKind::Greater
+            | TokenKind::GreaterEqual
+    )
+}
+
+/// Whether `node` is built from literals alone.
+fn is_constant(node: &S) -> bool {
+    match node {
+        S::Atom(token) => !matches!(token.kind, TokenKind::Identifier(_)),
+        S::Cons(token, args) => {
+            !matches!(
+                token.kind,
+                TokenKind::Let | TokenKind::Return | TokenKind::While | TokenKind::Dot
+            ) && args.iter().all(is_constant)
+        }
+        S::BinaryExpr { lhs, rhs, .. } => is_constant(lhs) && is_constant(rhs),
+        S::List(items) => items.iter().all(is_constant),
+        S::Map(entries) => entries.iter().all(|(_, value)| is_constant(value)),
+        _ => false,
+    }
+}
+
+/// Whether evaluating `node` calls a function, which may give a different
+/// value each time.
+fn has_call(node: &S) -> bool {
+    match node {
+        S::Atom(_) => false,
+        S::FunCall { .. } => true,
+        S::Cons(_, args) | S::List(args) | S::Block(args) => args.iter().any(has_call),
+        S::BinaryExpr { lhs, rhs, .. } => has_call(lhs) || has_call(rhs),
+        S::Map(entries) => entries.iter().any(|(_, value)| has_call(value)),
+        S::IfExpr { .. } | S::FunDef { .. } => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Parser;
+
+    /// The rule, message and labelled text of each warning for `source`,
+    /// with `print` as the only builtin.
+    fn lint(source: &str) -> Vec<(String, String, String)> {
+        lint_with(Linter::new(source, ["print".to_string()]), source)
+    }
+
+    fn lint_with(linter: Linter, source: &str) -> Vec<(String, String, String)> {
+        let mut parser = Parser::new(source);
+        let program: Vec<S> = std::iter::from_fn(|| parser.parse_statement())
+            .map(|statement| statement.unwrap())
+            .collect();
+        linter
+            .lint(&program)
+            .iter()
+            .map(|report| {
+                let label = report.labels().unwrap().next().unwrap();
+                let text = &source[label.offset()..label.offset() + label.len()];
+                (report.code().unwrap().to_string(), report.to_string(), text.to_string())
+            })
+            .collect()
+    }
+
+    fn warning(rule: &str, message: &str, text: &str) -> (String, String, String) {
+        (rule.to_string(), message.to_string(), text.to_string())
+    }
+
+    #[test]
+    fn unused_variable() {
+        assert_eq!(
+            lint("let x = 1\nfunc f(a) { return a }\nprint(f(2))"),
+            [warning("unused-variable", "Variable `x` is never used", "x")],
+        );
+        assert_eq!(
+            lint("func f(a, _b) { return 1 }\nprint(f(1, 2))"),
+            [warning("unused-variable", "Parameter `a` is never used", "a")],
+        );
+        assert_eq!(
+            lint("import \"lib.mc\" as lib"),
+            [warning("unused-variable", "Import `lib` is never used", "lib")],
+        );
+        assert!(lint("export let x = 1").is_empty());
+    }
+
+    #[test]
+    fn unused_function() {
+        assert_eq!(
+            lint("func f() { return 1 }"),
+            [warning("unused-function", "Function `f` is never used", "f")],
+        );
+    }
+
+    #[test]
+    fn shadowing() {
+        assert_eq!(
+            lint("let x = 1\nfunc f() { let x = 2\nreturn x }\nprint(f(), x)"),
+            [warning("shadowing", "`x` shadows the global of the same name", "x")],
+        );
+        assert_eq!(
+            lint("let print = 1\nprint"),
+            [warning("shadowing", "`print` shadows the builtin of the same name", "print")],
+        );
+    }
+
+    #[test]
+    fn unreachable_code() {
+        assert_eq!(
+            lint("func f() { return 1\nprint(2)\nprint(3) }\nprint(f())"),
+            [warning("unreachable-code", "Unreachable code after `return`", "print")],
+        );
+    }
+
+    #[test]
+    fn self_comparison() {
+        assert_eq!(
+            lint("let x = 1\nprint((x + 1) <= (x + 1))"),
+            [warning(
+                "self-comparison",
+                "Comparing `(+ x 1)` with itself is redundant",
+                "(x + 1) <= (x + 1)"
+            )],
+        );
+        // Calls may return something different each time.
+        assert!(lint("func f() { return 1 }\nprint(f() == f())").is_empty());
+    }
+
+    #[test]
+    fn constant_condition() {
+        assert_eq!(
+            lint("if 1 < 2 { print(1) }"),
+            [warning("constant-condition", "This condition is always the same", "1 < 2")],
+        );
+        assert!(lint("while true { print(1) }").is_empty());
+    }
+
+    #[test]
+    fn rules_can_be_allowed() {
+        let source = "let x = 1\n// merc-allow(unused-variable)\nlet y = 2\nlet z = 3 // merc-allow(unused-variable)";
+        assert_eq!(
+            lint(source),
+            [warning("unused-variable", "Variable `x` is never used", "x")],
+        );
+        let linter = Linter::new(source, []).allow("unused-variable");
+        assert!(lint_with(linter, source).is_empty());
+    }
+}
@@ -0,0 +1,312 @@
+use std::{collections::HashMap, ops::Range};
+
+use miette::{miette, LabeledSpan, Report, Severity};
+
+use crate::{
+    cst::{self, Parse, SyntaxKind, SyntaxNode, SyntaxToken},
+    lint::Linter,
+    Resolver, TokenKind,
+};
+
+/// What a name is bound to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefinitionKind {
+    Variable,
+    Function,
+    Parameter,
+    /// A module bound by `import "path" as name`.
+    Module,
+}
+
+/// A `let`, `func`, function parameter or import introducing a name.
+#[derive(Debug, Clone)]
+pub struct Definition {
+    pub name: String,
+    pub kind: DefinitionKind,
+    /// Range of the name itself.
+    pub range: Range<usize>,
+    /// Range of the whole statement, or of the name for parameters.
+    pub full_range: Range<usize>,
+    /// The function whose body the name is local to, `None` for globals.
+    pub scope: Option<usize>,
+    /// A short description shown on hover, e.g. `func add(a, b)`.
+    pub detail: String,
+}
+
+/// A use of a name, including the names in definitions themselves.
+#[derive(Debug, Clone)]
+pub struct Reference {
+    pub name: String,
+    pub range: Range<usize>,
+    /// Index into [`Analysis::definitions`], `None` for builtins and
+    /// undefined names.
+    pub definition: Option<usize>,
+}
+
+/// Everything the language server knows about one document.
+pub struct Analysis {
+    pub source: String,
+    parse: Parse,
+    pub definitions: Vec<Definition>,
+    pub references: Vec<Reference>,
+}
+
+impl Analysis {
+    pub fn new(source: &str) -> Self {
+        let parse = cst::parse(source);
+        let mut binder = Binder::default();
+        binder.node(&parse.syntax());
+        binder.resolve_deferred();
+
+        Self {
+            source: source.to_string(),
+            parse,
+            definitions: binder.definitions,
+            references: binder.references,
+        }
+    }
+
+    /// Syntax errors, or the resolver's and linter's findings when the
+    /// document parses.
+    pub fn diagnostics(&self, builtins: &[String]) -> Vec<Report> {
+        if !self.parse.errors().is_empty() {
+            return self
+                .parse
+                .errors()
+                .iter()
+                .map(|error| {
+                    miette!(
+                        labels = vec![LabeledSpan::at(error.range.clone(), error.message.clone())],
+                        severity = Severity::Error,
+                        "{}",
+                        error.message
+                    )
+                })
+                .collect();
+        }
+        match self.parse.to_ast() {
+            Ok(program) => {
+                let mut diagnostics =
+                    Resolver::new(&self.source, builtins.iter().cloned()).resolve(&program);
+                diagnostics.extend(Linter::new(&self.source, builtins.iter().cloned()).lint(&program));
+                diagnostics
+            }
+            Err(report) => vec![report],
+        }
+    }
+
+    /// The reference covering `offset`, counting a cursor just after a name
+    /// as on it.
+    pub fn reference_at(&self, offset: usize) -> Option<&Reference> {
+        self.references
+            .iter()
+            .find(|reference| reference.range.start <= offset && offset <= reference.range.end)
+    }
+
+    /// The definition the name at `offset` refers to.
+    pub fn definition_at(&self, offset: usize) -> Option<&Definition> {
+        self.reference_at(offset)
+            .and_then(|reference| reference.definition)
+            .map(|index| &self.definitions[index])
+    }
+
+    /// Every reference to the same definition as the name at `offset`.
+    pub fn references_at(&self, offset: usize) -> Vec<&Reference> {
+        let Some(definition) = self.reference_at(offset).and_then(|r| r.definition) else {
+            return Vec::new();
+        };
+        self.references
+            .iter()
+            .filter(|reference| reference.definition == Some(definition))
+            .collect()
+    }
+
+    /// The definitions whose names can be used at `offset`: the globals plus
+    /// the locals of the innermost function containing it.
+    pub fn visible_at(&self, offset: usize) -> Vec<&Definition> {
+        let function = self
+            .definitions
+            .iter()
+            .enumerate()
+            .filter(|(_, definition)| {
+                definition.kind == DefinitionKind::Function
+                    && definition.full_range.start < offset
+                    && offset < definition.full_range.end
+            })
+            .max_by_key(|(_, definition)| definition.full_range.start)
+            .map(|(index, _)| index);
+
+        self.definitions
+            .iter()
+            .filter(|definition| definition.scope.is_none() || definition.scope == function)
+            .collect()
+    }
+
+    /// Children of a definition for an outline: the locals of a function.
+    pub fn members(&self, definition: usize) -> impl Iterator<Item = (usize, &Definition)> {
+        self.definitions
+            .iter()
+            .enumerate()
+            .filter(move |(_, child)| child.scope == Some(definition))
+    }
+}
+
+/// Walks the tree binding names to definitions, following the
+/// interpreter's scoping: function bodies see their own locals and every
+/// global, while top-level code only sees the globals defined above it.
+#[derive(Default)]
+struct Binder {
+    definitions: Vec<Definition>,
+    references: Vec<Reference>,
+    globals: HashMap<String, usize>,
+    /// The function being walked and its locals.
+    locals: Option<(usize, HashMap<String, usize>)>,
+    /// References inside functions to names not yet defined, resolved once
+    /// every global is known.
+    deferred: Vec<usize>,
+}
+
+impl Binder {
+    fn node(&mut self, node: &SyntaxNode) {
+        let children = node.children();
+        match node.kind() {
+            SyntaxKind::Let => {
+                if let Some(value) = children.get(1) {
+                    self.node(value);
+                }
+                if let Some(name) = children.first().and_then(name_token) {
+                    let detail = match children.get(1).map(|value| value.text()) {
+                        Some(value) if value.trim().len() <= 40 && !value.trim().contains('\n') => {
+                            format!("let {} = {}", name.text(), value.trim())
+                        }
+                        _ => format!("let {}", name.text()),
+                    };
+                    self.define(&name, DefinitionKind::Variable, trimmed_range(node), detail);
+                }
+            }
+            SyntaxKind::Func => {
+                let Some(name) = children.first().and_then(name_token) else {
+                    return;
+                };
+                let params: Vec<SyntaxToken> = children
+                    .get(1)
+                    .map(|params| params.children().iter().filter_map(name_token).collect())
+                    .unwrap_or_default();
+                let names: Vec<&str> = params.iter().map(|param| param.text()).collect();
+                let detail = format!("func {}({})", name.text(), names.join(", "));
+                let function = self.define(&name, DefinitionKind::Function, trimmed_range(node), detail);
+
+                let outer = self.locals.replace((function, HashMap::new()));
+                for param in &params {
+                    let detail = format!("(parameter) {}", param.text());
+                    self.define(param, DefinitionKind::Parameter, param.text_range(), detail);
+                }
+                if let Some(body) = children.get(2) {
+                    self.node(body);
+                }
+                self.locals = outer;
+            }
+            SyntaxKind::Import => {
+                let path = children.first().map(|path| path.text()).unwrap_or_default();
+                let from = node.tokens().iter().any(|token| token.kind() == &TokenKind::From);
+                for name in children.iter().filter_map(name_token) {
+                    let (kind, detail) = match from {
+                        true => (DefinitionKind::Variable, format!("from {} import", path.trim())),
+                        false => (DefinitionKind::Module, format!("import {} as", path.trim())),
+                    };
+                    let detail = format!("{} {}", detail, name.text());
+                    self.define(&name, kind, trimmed_range(node), detail);
+                }
+            }
+            SyntaxKind::Name => {
+                if let Some(token) = name_token(node) {
+                    self.reference(&token);
+                }
+            }
+            // Member names and map keys are not variables.
+            SyntaxKind::Member => {
+                if let Some(object) = children.first() {
+                    self.node(object);
+                }
+            }
+            SyntaxKind::Entry => {
+                if let Some(value) = children.get(1) {
+                    self.node(value);
+                }
+            }
+            _ => children.iter().for_each(|child| self.node(child)),
+        }
+    }
+
+    fn define(
+        &mut self,
+        name: &SyntaxToken,
+        kind: DefinitionKind,
+        full_range: Range<usize>,
+        detail: String,
+    ) -> usize {
+        let index = self.definitions.len();
+        self.definitions.push(Definition {
+            name: name.text().to_string(),
+            kind,
+            range: name.text_range(),
+            full_range,
+            scope: self.locals.as_ref().map(|(function, _)| *function),
+            detail,
+        });
+        match &mut self.locals {
+            Some((_, locals)) => locals.insert(name.text().to_string(), index),
+            None => self.globals.insert(name.text().to_string(), index),
+        };
+        self.references.push(Reference {
+            name: name.text().to_string(),
+            range: name.text_range(),
+            definition: Some(index),
+        });
+        index
+    }
+
+    fn reference(&mut self, token: &SyntaxToken) {
+        let name = token.text();
+        let definition = match &self.locals {
+            Some((_, locals)) => locals.get(name).or_else(|| self.globals.get(name)),
+            None => self.globals.get(name),
+        }
+        .copied();
+        if definition.is_none() && self.locals.is_some() {
+            self.deferred.push(self.references.len());
+        }
+        self.references.push(Reference {
+            name: name.to_string(),
+            range: token.text_range(),
+            definition,
+        });
+    }
+
+    fn resolve_deferred(&mut self) {
+        for index in std::mem::take(&mut self.deferred) {
+            let reference = &mut self.references[index];
+            reference.definition = self.globals.get(&reference.name).copied();
+        }
+    }
+}
+
+/// The identifier of a [`SyntaxKind::Name`] node.
+fn name_token(node: &SyntaxNode) -> Option<SyntaxToken> {
+    if node.kind() != SyntaxKind::Name {
+        return None;
+    }
+    node.tokens()
+        .into_iter()
+        .find(|token| matches!(token.kind(), TokenKind::Identifier(_)))
+}
+
+/// The node's range without its leading trivia.
+fn trimmed_range(node: &SyntaxNode) -> Range<usize> {
+    let start = node
+        .tokens()
+        .iter()
+        .find(|token| !token.is_trivia() && token.kind() != &TokenKind::NewLine)
+        .map_or(node.text_range().start, |token| token.text_range().start);
+    start..node.text_range().end
+}
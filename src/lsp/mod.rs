@@ -0,0 +1,511 @@
+//! A language server for merc, speaking the Language Server Protocol over
+//! stdin and stdout.
+//!
+//! Documents are re-analysed in full on every change: they are small, and
+//! the lossless syntax tree keeps working on code that doesn't parse yet.
+
+use std::collections::{BTreeMap, HashMap};
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+        Notification as _, PublishDiagnostics,
+    },
+    request::{
+        Completion, DocumentSymbolRequest, Formatting, GotoDefinition, HoverRequest, References,
+        Request as _,
+    },
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse,
+    Diagnostic, DiagnosticSeverity, DocumentFormattingParams, DocumentSymbol,
+    DocumentSymbolParams, DocumentSymbolResponse, GotoDefinitionParams, GotoDefinitionResponse,
+    Hover, HoverContents, HoverParams, HoverProviderCapability, Location, MarkupContent,
+    MarkupKind, NumberOrString, OneOf, Position, PublishDiagnosticsParams, ReferenceParams,
+    ServerCapabilities, SymbolKind, TextDocumentPositionParams, TextDocumentSyncCapability,
+    TextDocumentSyncKind, TextEdit, Url,
+};
+use miette::{IntoDiagnostic, Report, Result};
+
+use crate::{format::format, lexer::KEYWORDS, Interpreter, Value};
+
+mod analysis;
+
+pub use analysis::{Analysis, Definition, DefinitionKind, Reference};
+
+/// Runs the server until the client asks it to exit.
+pub fn run() -> Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        completion_provider: Some(CompletionOptions::default()),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        ..Default::default()
+    };
+    connection
+        .initialize(serde_json::to_value(capabilities).into_diagnostic()?)
+        .into_diagnostic()?;
+
+    Server::new(connection).main_loop()?;
+    io_threads.join().into_diagnostic()?;
+    Ok(())
+}
+
+struct Server {
+    connection: Connection,
+    documents: HashMap<Url, Analysis>,
+    /// Builtin names with a short description of each and how to complete them.
+    builtins: BTreeMap<String, (String, CompletionItemKind)>,
+}
+
+impl Server {
+    fn new(connection: Connection) -> Self {
+        let interpreter = Interpreter::default();
+        let mut builtins: BTreeMap<_, _> = interpreter
+            .builtin_names()
+            .filter_map(|name| {
+                let builtin = match interpreter.lookup(name)? {
+                    Value::Map(_) => ("module".to_string(), CompletionItemKind::MODULE),
+                    Value::NativeFunction(native) => (
+                        Value::NativeFunction(native).describe(),
+                        CompletionItemKind::FUNCTION,
+                    ),
+                    other => (other.describe(), CompletionItemKind::VARIABLE),
+                };
+                Some((name.clone(), builtin))
+            })
+            .collect();
+        builtins.insert(
+            "args".to_string(),
+            ("list of command-line arguments".to_string(), CompletionItemKind::VARIABLE),
+        );
+
+        Self {
+            connection,
+            documents: HashMap::new(),
+            builtins,
+        }
+    }
+
+    fn main_loop(&mut self) -> Result<()> {
+        while let Ok(message) = self.connection.receiver.recv() {
+            match message {
+                Message::Request(request) => {
+                    if self.connection.handle_shutdown(&request).into_diagnostic()? {
+                        return Ok(());
+                    }
+                    let response = self.request(request);
+                    self.send(Message::Response(response))?;
+                }
+                Message::Notification(notification) => self.notification(notification)?,
+                Message::Response(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn request(&self, request: Request) -> Response {
+        match request.method.as_str() {
+            GotoDefinition::METHOD => self.reply::<GotoDefinition>(request, Self::definition),
+            References::METHOD => self.reply::<References>(request, Self::references),
+            HoverRequest::METHOD => self.reply::<HoverRequest>(request, Self::hover),
+            DocumentSymbolRequest::METHOD => {
+                self.reply::<DocumentSymbolRequest>(request, Self::symbols)
+            }
+            Completion::METHOD => self.reply::<Completion>(request, Self::completion),
+            Formatting::METHOD => self.reply::<Formatting>(request, Self::formatting),
+            _ => Response::new_err(
+                request.id,
+                ErrorCode::MethodNotFound as i32,
+                format!("Unhandled request: {}", request.method),
+            ),
+        }
+    }
+
+    fn reply<R: lsp_types::request::Request>(
+        &self,
+        request: Request,
+        handler: fn(&Self, R::Params) -> R::Result,
+    ) -> Response {
+        match serde_json::from_value(request.params) {
+            Ok(params) => Response::new_ok(request.id, handler(self, params)),
+            Err(err) => Response::new_err(request.id, ErrorCode::InvalidParams as i32, err.to_string()),
+        }
+    }
+
+    fn notification(&mut self, notification: Notification) -> Result<()> {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                self.handle::<DidOpenTextDocument>(notification, Self::did_open)
+            }
+            DidChangeTextDocument::METHOD => {
+                self.handle::<DidChangeTextDocument>(notification, Self::did_change)
+            }
+            DidCloseTextDocument::METHOD => {
+                self.handle::<DidCloseTextDocument>(notification, Self::did_close)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Runs `handler` on the notification's parameters. Notifications get no
+    /// response to report malformed parameters in, so those are logged to
+    /// stderr and the notification is ignored.
+    fn handle<N: lsp_types::notification::Notification>(
+        &mut self,
+        notification: Notification,
+        handler: fn(&mut Self, N::Params) -> Result<()>,
+    ) -> Result<()> {
+        match serde_json::from_value(notification.params) {
+            Ok(params) => handler(self, params),
+            Err(err) => {
+                eprintln!("Ignoring malformed {} notification: {}", notification.method, err);
+                Ok(())
+            }
+        }
+    }
+
+    fn did_open(&mut self, params: lsp_types::DidOpenTextDocumentParams) -> Result<()> {
+        self.update(params.text_document.uri, &params.text_document.text)
+    }
+
+    fn did_change(&mut self, params: lsp_types::DidChangeTextDocumentParams) -> Result<()> {
+        // Only full syncs are requested, so the last change is the whole document.
+        match params.content_changes.last() {
+            Some(change) => self.update(params.text_document.uri, &change.text),
+            None => Ok(()),
+        }
+    }
+
+    fn did_close(&mut self, params: lsp_types::DidCloseTextDocumentParams) -> Result<()> {
+        self.documents.remove(&params.text_document.uri);
+        self.publish(params.text_document.uri, Vec::new())
+    }
+
+    fn update(&mut self, uri: Url, text: &str) -> Result<()> {
+        let analysis = Analysis::new(text);
+        let builtins: Vec<String> = self.builtins.keys().cloned().collect();
+        let lines = LineIndex::new(text);
+        let diagnostics = analysis
+            .diagnostics(&builtins)
+            .iter()
+            .map(|report| diagnostic(report, &lines))
+            .collect();
+        self.documents.insert(uri.clone(), analysis);
+        self.publish(uri, diagnostics)
+    }
+
+    fn publish(&self, uri: Url, diagnostics: Vec<Diagnostic>) -> Result<()> {
+        let params = PublishDiagnosticsParams::new(uri, diagnostics, None);
+        self.send(Message::Notification(Notification::new(
+            PublishDiagnostics::METHOD.to_string(),
+            params,
+        )))
+    }
+
+    fn send(&self, message: Message) -> Result<()> {
+        self.connection.sender.send(message).into_diagnostic()
+    }
+
+    /// The document and byte offset a position refers to.
+    fn locate(&self, position: &TextDocumentPositionParams) -> Option<(&Analysis, LineIndex<'_>, usize)> {
+        let analysis = self.documents.get(&position.text_document.uri)?;
+        let lines = LineIndex::new(&analysis.source);
+        let offset = lines.offset(position.position);
+        Some((analysis, lines, offset))
+    }
+
+    fn definition(&self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let position = params.text_document_position_params;
+        let (analysis, lines, offset) = self.locate(&position)?;
+        let definition = analysis.definition_at(offset)?;
+        Some(GotoDefinitionResponse::Scalar(Location::new(
+            position.text_document.uri,
+            lines.range(&definition.range),
+        )))
+    }
+
+    fn references(&self, params: ReferenceParams) -> Option<Vec<Location>> {
+        let position = params.text_document_position;
+        let (analysis, lines, offset) = self.locate(&position)?;
+        let include_declaration = params.context.include_declaration;
+        let locations = analysis
+            .references_at(offset)
+            .into_iter()
+            .filter(|reference| {
+                include_declaration
+                    || reference
+                        .definition
+                        .is_none_or(|index| analysis.definitions[index].range != reference.range)
+            })
+            .map(|reference| Location::new(position.text_document.uri.clone(), lines.range(&reference.range)))
+            .collect();
+        Some(locations)
+    }
+
+    fn hover(&self, params: HoverParams) -> Option<Hover> {
+        let (analysis, lines, offset) = self.locate(&params.text_document_position_params)?;
+        let reference = analysis.reference_at(offset)?;
+        let value = match reference.definition {
+            Some(index) => format!("```merc\n{}\n```", analysis.definitions[index].detail),
+            None => {
+                let (description, _) = self.builtins.get(&reference.name)?;
+                format!("`{}`: builtin {}", reference.name, description)
+            }
+        };
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value,
+            }),
+            range: Some(lines.range(&reference.range)),
+        })
+    }
+
+    fn symbols(&self, params: DocumentSymbolParams) -> Option<DocumentSymbolResponse> {
+        let analysis = self.documents.get(&params.text_document.uri)?;
+        let lines = LineIndex::new(&analysis.source);
+        let symbols = analysis
+            .definitions
+            .iter()
+            .enumerate()
+            .filter(|(_, definition)| definition.scope.is_none())
+            .map(|(index, definition)| symbol(analysis, index, definition, &lines))
+            .collect();
+        Some(DocumentSymbolResponse::Nested(symbols))
+    }
+
+    fn completion(&self, params: CompletionParams) -> Option<CompletionResponse> {
+        let (analysis, _, offset) = self.locate(&params.text_document_position)?;
+        let mut items: BTreeMap<String, CompletionItem> = BTreeMap::new();
+        for keyword in KEYWORDS {
+            items.insert(keyword.to_string(), item(keyword, CompletionItemKind::KEYWORD, None));
+        }
+        for (name, (description, kind)) in &self.builtins {
+            items.insert(name.clone(), item(name, *kind, Some(description)));
+        }
+        for definition in analysis.visible_at(offset) {
+            let kind = match definition.kind {
+                DefinitionKind::Function => CompletionItemKind::FUNCTION,
+                DefinitionKind::Variable | DefinitionKind::Parameter => CompletionItemKind::VARIABLE,
+                DefinitionKind::Module => CompletionItemKind::MODULE,
+            };
+            items.insert(definition.name.clone(), item(&definition.name, kind, Some(&definition.detail)));
+        }
+        Some(CompletionResponse::Array(items.into_values().collect()))
+    }
+
+    fn formatting(&self, params: DocumentFormattingParams) -> Option<Vec<TextEdit>> {
+        let analysis = self.documents.get(&params.text_document.uri)?;
+        // Code that doesn't parse is left alone; its diagnostics say why.
+        let formatted = format(&analysis.source).ok()?;
+        let lines = LineIndex::new(&analysis.source);
+        Some(vec![TextEdit::new(lines.range(&(0..analysis.source.len())), formatted)])
+    }
+}
+
+fn item(label: &str, kind: CompletionItemKind, detail: Option<&str>) -> CompletionItem {
+    CompletionItem {
+        label: label.to_string(),
+        kind: Some(kind),
+        detail: detail.map(str::to_string),
+        ..Default::default()
+    }
+}
+
+fn symbol(analysis: &Analysis, index: usize, definition: &Definition, lines: &LineIndex) -> DocumentSymbol {
+    let (kind, children) = match definition.kind {
+        DefinitionKind::Function => {
+            let children = analysis
+                .members(index)
+                .filter(|(_, member)| member.kind != DefinitionKind::Parameter)
+                .map(|(index, member)| symbol(analysis, index, member, lines))
+                .collect();
+            (SymbolKind::FUNCTION, Some(children))
+        }
+        DefinitionKind::Variable => (SymbolKind::VARIABLE, None),
+        DefinitionKind::Parameter => (SymbolKind::VARIABLE, None),
+        DefinitionKind::Module => (SymbolKind::MODULE, None),
+    };
+    #[allow(deprecated)]
+    DocumentSymbol {
+        name: definition.name.clone(),
+        detail: Some(definition.detail.clone()),
+        kind,
+        tags: None,
+        deprecated: None,
+        range: lines.range(&definition.full_range),
+        selection_range: lines.range(&definition.range),
+        children,
+    }
+}
+
+/// Converts a report to a diagnostic placed at its first label.
+fn diagnostic(report: &Report, lines: &LineIndex) -> Diagnostic {
+    let range = report
+        .labels()
+        .and_then(|mut labels| labels.next())
+        .map_or(0..0, |label| label.offset()..label.offset() + label.len());
+    let severity = match report.severity() {
+        Some(miette::Severity::Warning) => DiagnosticSeverity::WARNING,
+        Some(miette::Severity::Advice) => DiagnosticSeverity::HINT,
+        _ => DiagnosticSeverity::ERROR,
+    };
+    Diagnostic {
+        range: lines.range(&range),
+        severity: Some(severity),
+        code: report.code().map(|code| NumberOrString::String(code.to_string())),
+        source: Some("merc".to_string()),
+        message: report.to_string(),
+        ..Default::default()
+    }
+}
+
+/// Converts between byte offsets and LSP positions, whose columns count
+/// UTF-16 code units.
+struct LineIndex<'a> {
+    text: &'a str,
+    /// Byte offset of the start of every line.
+    starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    fn new(text: &'a str) -> Self {
+        let mut starts = vec![0];
+        starts.extend(text.match_indices('\n').map(|(i, _)| i + 1));
+        Self { text, starts }
+    }
+
+    fn position(&self, offset: usize) -> Position {
+        let offset = offset.min(self.text.len());
+        let line = self.starts.partition_point(|start| *start <= offset) - 1;
+        let column = self.text[self.starts[line]..offset].encode_utf16().count();
+        Position::new(line as u32, column as u32)
+    }
+
+    fn offset(&self, position: Position) -> usize {
+        let Some(&start) = self.starts.get(position.line as usize) else {
+            return self.text.len();
+        };
+        let mut column = 0;
+        for (i, c) in self.text[start..].char_indices() {
+            if column >= position.character as usize || c == '\n' {
+                return start + i;
+            }
+            column += c.len_utf16();
+        }
+        self.text.len()
+    }
+
+    fn range(&self, range: &std::ops::Range<usize>) -> lsp_types::Range {
+        lsp_types::Range::new(self.position(range.start), self.position(range.end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::{
+        DidOpenTextDocumentParams, FormattingOptions, TextDocumentIdentifier, TextDocumentItem,
+    };
+
+    use super::*;
+
+    #[test]
+    fn line_index_counts_columns_in_utf16() {
+        let text = "let s = \"é😀\"\nprint(s)";
+        let lines = LineIndex::new(text);
+        // `é` is one UTF-16 unit in two bytes, `😀` two units in four bytes.
+        let quote = text.rfind('"').unwrap();
+        assert_eq!(lines.position(quote), Position::new(0, 12));
+        assert_eq!(lines.offset(Position::new(0, 12)), quote);
+        assert_eq!(lines.position(text.find("print").unwrap()), Position::new(1, 0));
+        assert_eq!(lines.offset(Position::new(1, 6)), text.rfind('s').unwrap());
+    }
+
+    #[test]
+    fn line_index_clamps_to_the_end_of_the_text() {
+        let lines = LineIndex::new("a\nbc\n");
+        assert_eq!(lines.position(5), Position::new(2, 0));
+        assert_eq!(lines.position(50), Position::new(2, 0));
+        assert_eq!(lines.offset(Position::new(1, 99)), 4);
+        assert_eq!(lines.offset(Position::new(2, 0)), 5);
+        assert_eq!(lines.offset(Position::new(7, 3)), 5);
+    }
+
+    fn uri() -> Url {
+        Url::parse("file:///test.mc").unwrap()
+    }
+
+    /// A server with `text` open, and the client end of its connection.
+    fn open(text: &str) -> (Server, Connection) {
+        let (server, client) = Connection::memory();
+        let mut server = Server::new(server);
+        let params = DidOpenTextDocumentParams {
+            text_document: TextDocumentItem::new(uri(), "merc".to_string(), 1, text.to_string()),
+        };
+        server
+            .notification(Notification::new(DidOpenTextDocument::METHOD.to_string(), params))
+            .unwrap();
+        (server, client)
+    }
+
+    fn published(client: &Connection) -> PublishDiagnosticsParams {
+        match client.receiver.try_recv() {
+            Ok(Message::Notification(notification)) => {
+                assert_eq!(notification.method, PublishDiagnostics::METHOD);
+                serde_json::from_value(notification.params).unwrap()
+            }
+            other => panic!("expected diagnostics, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn opening_a_document_publishes_its_diagnostics() {
+        let (_server, client) = open("let s = \"😀\"\nprint(s, y)");
+        let params = published(&client);
+        assert_eq!(params.uri, uri());
+        let [diagnostic] = params.diagnostics.as_slice() else {
+            panic!("expected one diagnostic, got {:?}", params.diagnostics);
+        };
+        assert_eq!(diagnostic.message, "Undefined variable: y");
+        assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::ERROR));
+        let y = lsp_types::Range::new(Position::new(1, 9), Position::new(1, 10));
+        assert_eq!(diagnostic.range, y);
+
+        let (_server, client) = open("let = 1");
+        let diagnostics = published(&client).diagnostics;
+        assert!(!diagnostics.is_empty());
+        assert_eq!(diagnostics[0].range.start, Position::new(0, 4), "{:?}", diagnostics);
+        let (_server, client) = open("print(1)");
+        assert!(published(&client).diagnostics.is_empty());
+    }
+
+    fn format_request(server: &Server) -> Response {
+        let params = DocumentFormattingParams {
+            text_document: TextDocumentIdentifier::new(uri()),
+            options: FormattingOptions::default(),
+            work_done_progress_params: Default::default(),
+        };
+        server.request(Request::new(1.into(), Formatting::METHOD.to_string(), params))
+    }
+
+    #[test]
+    fn formatting_replaces_the_whole_document() {
+        let (server, _client) = open("let x=1\nprint( x )");
+        let result = format_request(&server).result.expect("a result");
+        let edits: Vec<TextEdit> = serde_json::from_value(result).unwrap();
+        assert_eq!(
+            edits,
+            [TextEdit::new(
+                lsp_types::Range::new(Position::new(0, 0), Position::new(1, 10)),
+                "let x = 1\nprint(x)\n".to_string()
+            )]
+        );
+
+        let (server, _client) = open("let = 1");
+        assert_eq!(format_request(&server).result, Some(serde_json::Value::Null));
+    }
+}
@@ -1,91 +1,553 @@
-use clap::Parser;
+use clap::{
+    builder::{PossibleValue, PossibleValuesParser},
+    Args, Parser, Subcommand, ValueEnum,
+};
 use colored::*;
-use merc::repl;
-use miette::{IntoDiagnostic, WrapErr};
-use std::{fs, path::PathBuf};
+use indicatif::{ProgressBar, ProgressStyle};
+use merc::{
+    coverage::Coverage,
+    cst,
+    debugger::{Debugger, Terminal},
+    highlight::Highlighter,
+    lint::Linter,
+    pretty::Printer,
+    profiler::Profiler,
+    repl, stdlib::Capabilities, testing, Interpreter, Resolver, ScriptError, Value,
+};
+use miette::{miette, IntoDiagnostic, Report, Result, WrapErr};
+use std::{
+    env, fs,
+    io::{self, IsTerminal, Read},
+    path::{Path, PathBuf},
+    process,
+};
+
+/// Exit status for scripts that fail to parse or resolve (`EX_DATAERR`).
+const EXIT_PARSE_ERROR: i32 = 65;
+/// Exit status for scripts that fail while running (`EX_SOFTWARE`).
+const EXIT_RUNTIME_ERROR: i32 = 70;
 
 #[derive(Parser, Debug)]
 #[command(version, about)]
-struct Args {
-    #[clap(short, long)]
-    filename: Option<PathBuf>,
+struct Cli {
+    /// When to use colours and syntax highlighting
+    #[arg(long, global = true, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
 
-    #[clap(short, long)]
-    tokenize: Option<bool>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
 
-    #[clap(short, long)]
-    parser: Option<bool>,
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ColorChoice {
+    /// Colour output when stdout is a terminal and `NO_COLOR` is unset
+    Auto,
+    Always,
+    Never,
+}
 
-    #[clap(short, long)]
-    interpret: Option<bool>,
+impl ColorChoice {
+    fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Auto => io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
 }
 
-fn main() -> miette::Result<()> {
-    let args = Args::parse();
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run a script
+    Run(RunArgs),
+    /// Print the tokens of a script
+    Tokens(SourceArgs),
+    /// Print the syntax tree of a script
+    Ast(AstArgs),
+    /// Lex, parse and resolve a script without running it
+    Check(SourceArgs),
+    /// Warn about likely mistakes such as unused variables
+    Lint(LintArgs),
+    /// Run the tests in the `*_test.mc` files under the given paths
+    Test(TestArgs),
+    /// Format scripts in place, or stdin to stdout
+    Fmt(FmtArgs),
+    /// Step through a script with breakpoints
+    Debug(DebugArgs),
+    /// Start a debug adapter for editors, speaking DAP over stdio
+    Dap,
+    /// Start a language server for editors, speaking LSP over stdio
+    Lsp,
+    /// Start the interactive interpreter (the default)
+    Repl,
+}
 
-    match (args.tokenize, args.parser, args.interpret) {
-        (Some(true), _, _) => {
-            match args.filename {
-                Some(filename) => {
-                    let contents = fs::read_to_string(&filename)
-                        .into_diagnostic()
-                        .wrap_err_with(|| format!("Failed to read file: {}", filename.display()))?;
+#[derive(Args, Debug)]
+struct SourceArgs {
+    /// Script to read, or `-` for stdin
+    #[arg(required_unless_present = "eval", conflicts_with = "eval")]
+    file: Option<String>,
 
-                    let lexer = merc::Lexer::new(&contents);
+    /// Use CODE instead of reading a file
+    #[arg(short, long, value_name = "CODE")]
+    eval: Option<String>,
+}
 
-                    for token in lexer {
-                        let token = token?;
-                        println!("{}", token);
-                    }
-                }
+#[derive(Args, Debug)]
+struct FmtArgs {
+    /// Scripts to format; reads stdin when none are given or for `-`
+    files: Vec<String>,
+
+    /// List files that are not formatted and exit with status 1 instead of
+    /// writing them
+    #[arg(long)]
+    check: bool,
+}
+
+#[derive(Args, Debug)]
+struct TestArgs {
+    /// Test files, or directories to search for `*_test.mc` files
+    #[arg(default_value = ".")]
+    paths: Vec<PathBuf>,
+
+    /// Record which lines and branches the tests run, printing a summary and
+    /// writing an lcov report
+    #[arg(long)]
+    coverage: bool,
+
+    /// Where to write the lcov report
+    #[arg(long, value_name = "FILE", default_value = "lcov.info", requires = "coverage")]
+    lcov: PathBuf,
+
+    /// Let tests read and write files inside DIR with the `fs` module;
+    /// can be given more than once
+    #[arg(long, value_name = "DIR")]
+    allow_fs: Vec<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct DebugArgs {
+    /// Script to debug
+    file: String,
+
+    /// Pause at LINE; can be given more than once. Without breakpoints the
+    /// debugger pauses before the first statement
+    #[arg(short, long = "break", value_name = "LINE")]
+    breakpoint: Vec<usize>,
+
+    /// Let the script read and write files inside DIR with the `fs` module;
+    /// can be given more than once
+    #[arg(long, value_name = "DIR")]
+    allow_fs: Vec<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct LintArgs {
+    #[command(flatten)]
+    source: SourceArgs,
+
+    /// Turn off a lint; can be given more than once
+    #[arg(short = 'A', long = "allow", value_name = "RULE", value_parser = lint_rules())]
+    allow: Vec<String>,
+}
+
+fn lint_rules() -> PossibleValuesParser {
+    PossibleValuesParser::new(
+        merc::lint::RULES
+            .iter()
+            .map(|(name, description)| PossibleValue::new(*name).help(*description)),
+    )
+}
+
+#[derive(Args, Debug)]
+struct AstArgs {
+    #[command(flatten)]
+    source: SourceArgs,
+
+    /// How to print each statement
+    #[arg(long, value_enum, default_value_t = AstFormat::Tree)]
+    format: AstFormat,
+
+    /// Annotate nodes with the byte range they cover
+    #[arg(long)]
+    spans: bool,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum AstFormat {
+    /// The source of each statement followed by an indented tree
+    Tree,
+    /// One S-expression per statement, e.g. `(+ 1 (* 2 3))`
+    Sexpr,
+    /// The raw `Debug` output of the parser
+    Debug,
+    /// The lossless concrete syntax tree of the whole file, whitespace and
+    /// comments included
+    Cst,
+}
 
-                None => {}
-            };
+#[derive(Args, Debug)]
+struct RunArgs {
+    /// Run CODE instead of reading a file
+    #[arg(short, long, value_name = "CODE")]
+    eval: Option<String>,
+
+    /// Time every function call and count line hits, printing a report to
+    /// stderr when the script ends
+    #[arg(long)]
+    profile: bool,
+
+    /// With `--profile`, also write the call stacks in the folded format
+    /// read by flame graph tools to FILE
+    #[arg(long, value_name = "FILE", requires = "profile")]
+    folded: Option<String>,
+
+    /// Let the script read and write files inside DIR with the `fs` module;
+    /// can be given more than once
+    #[arg(long, value_name = "DIR")]
+    allow_fs: Vec<PathBuf>,
+
+    /// Script to run (`-` for stdin) followed by the arguments passed to it as
+    /// the `args` global; with `--eval` every value is a script argument
+    #[arg(
+        value_name = "FILE [ARGS]",
+        required_unless_present = "eval",
+        trailing_var_arg = true,
+        allow_hyphen_values = true
+    )]
+    input: Vec<String>,
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let color = cli.color.enabled();
+    colored::control::set_override(color);
+    miette::set_hook(Box::new(move |_| {
+        Box::new(miette::MietteHandlerOpts::new().color(color).build())
+    }))?;
+
+    match cli.command {
+        Some(Command::Run(args)) => run(args),
+        Some(Command::Tokens(args)) => tokens(args),
+        Some(Command::Ast(args)) => ast(args),
+        Some(Command::Check(args)) => check(args),
+        Some(Command::Lint(args)) => lint(args),
+        Some(Command::Test(args)) => test(args),
+        Some(Command::Fmt(args)) => fmt(args),
+        Some(Command::Debug(args)) => debug(args),
+        Some(Command::Dap) => merc::debugger::dap::run(),
+        Some(Command::Lsp) => merc::lsp::run(),
+        Some(Command::Repl) | None => {
+            repl::repl();
             Ok(())
         }
-        (_, Some(true), _) => {
-            match args.filename {
-                Some(filename) => {
-                    let contents = fs::read_to_string(&filename)
-                        .into_diagnostic()
-                        .wrap_err_with(|| format!("Failed to read file: {}", filename.display()))?;
-
-                    let mut parser = merc::Parser::new(&contents);
-
-                    while let Some(ast) = parser.parse_statement() {
-                        match ast {
-                            Ok(ast) => println!("{:?}", ast),
-                            Err(err) => {
-                                eprintln!("{:?}", err);
-                                break;
-                            }
-                        }
-                    }
+    }
+}
+
+fn run(args: RunArgs) -> Result<()> {
+    let (file, source, script_args) = match args.eval {
+        Some(code) => (None, code, args.input),
+        None => {
+            let mut input = args.input.into_iter();
+            let file = input.next().expect("clap requires a file or --eval");
+            let source = read_source(&file)?;
+            (Some(file), source, input.collect())
+        }
+    };
+
+    let mut interpreter = Interpreter::new(merc::Parser::new(&source));
+    if let Some(file) = file.filter(|file| file != "-") {
+        interpreter.set_file(Path::new(&file));
+    }
+    interpreter.set_capabilities(allow_fs(args.allow_fs));
+    interpreter.variables.insert(
+        "args".to_string(),
+        Value::List(script_args.into_iter().map(Value::String).collect()),
+    );
+
+    let profiler = args.profile.then(|| {
+        let profiler = Profiler::new();
+        interpreter.set_hook(profiler.clone());
+        profiler
+    });
+    let result = interpreter.run();
+
+    if let Some(profiler) = profiler {
+        eprint!("{}", profiler.report());
+        if let Some(file) = args.folded {
+            fs::write(&file, profiler.folded())
+                .into_diagnostic()
+                .wrap_err_with(|| format!("Failed to write file: {}", file))?;
+        }
+    }
+    if let Err(report) = result {
+        fail(report);
+    }
+    Ok(())
+}
+
+fn tokens(args: SourceArgs) -> Result<()> {
+    let source = args.read()?;
+
+    for token in merc::Lexer::new(&source) {
+        match token {
+            Ok(token) => println!("{}", token),
+            Err(err) => fail(Report::new(ScriptError::Parse(err))),
+        }
+    }
+    Ok(())
+}
+
+fn ast(args: AstArgs) -> Result<()> {
+    let source = args.source.read()?;
+    if let AstFormat::Cst = args.format {
+        let parse = cst::parse(&source);
+        print!("{}", parse.syntax().debug_tree());
+        if let Err(err) = parse.to_ast() {
+            fail(err);
+        }
+        return Ok(());
+    }
+
+    let mut parser = merc::Parser::new(&source);
+    let printer = Printer::new().spans(args.spans);
+    let highlighter = Highlighter::global();
+
+    while let Some(ast) = parser.parse_statement() {
+        let ast = match ast {
+            Ok(ast) => ast,
+            Err(err) => fail(Report::new(ScriptError::Parse(err))),
+        };
+        match args.format {
+            AstFormat::Tree => {
+                if let Some(span) = parser.statement_span() {
+                    println!("{}", highlighter.highlight(&source[span]));
                 }
+                print!("{}", printer.tree(&ast));
+            }
+            AstFormat::Sexpr => println!("{}", printer.sexpr(&ast)),
+            AstFormat::Debug => println!("{:?}", ast),
+            AstFormat::Cst => unreachable!("handled above"),
+        }
+    }
+    Ok(())
+}
 
-                None => {}
-            };
-            Ok(())
+fn check(args: SourceArgs) -> Result<()> {
+    let source = args.read()?;
+    let program = parse_program(&source);
+
+    let diagnostics = Resolver::new(&source, builtins()).resolve(&program);
+    if diagnostics.is_empty() {
+        return Ok(());
+    }
+    for diagnostic in &diagnostics {
+        eprintln!("{:?}", diagnostic);
+    }
+    process::exit(EXIT_PARSE_ERROR);
+}
+
+fn lint(args: LintArgs) -> Result<()> {
+    let source = args.source.read()?;
+    let program = parse_program(&source);
+
+    let linter = args
+        .allow
+        .iter()
+        .fold(Linter::new(&source, builtins()), |linter, rule| linter.allow(rule));
+    let warnings = linter.lint(&program);
+    if warnings.is_empty() {
+        return Ok(());
+    }
+    for warning in &warnings {
+        eprintln!("{:?}", warning);
+    }
+    process::exit(1);
+}
+
+fn test(args: TestArgs) -> Result<()> {
+    let files = testing::discover(&args.paths)?;
+    if files.is_empty() {
+        return Err(miette!("No test files found"));
+    }
+
+    // Files that fail to load count as one failed test each.
+    let mut failures: Vec<(String, Report, String)> = Vec::new();
+    let mut suites = Vec::new();
+    for file in &files {
+        match testing::Suite::load(file) {
+            Ok(suite) => suites.push(suite),
+            Err(report) => failures.push((file.display().to_string(), report, String::new())),
         }
-        (_, _, Some(true)) => match args.filename {
-            Some(filename) => {
-                let contents = fs::read_to_string(&filename)
-                    .into_diagnostic()
-                    .wrap_err_with(|| format!("Failed to read file: {}", filename.display()))?;
+    }
+
+    let total: usize = suites.iter().map(|suite| suite.count()).sum::<usize>() + failures.len();
+    let progress = ProgressBar::new(total as u64).with_style(
+        ProgressStyle::with_template("{bar:30.cyan/blue} {pos}/{len} {wide_msg}")
+            .expect("valid template")
+            .progress_chars("=> "),
+    );
+    progress.inc(failures.len() as u64);
 
-                let parser = merc::Parser::new(&contents);
+    let capabilities = allow_fs(args.allow_fs);
+    // One recorder for every suite, so a module imported by several test
+    // files gets a single record.
+    let recorder = args.coverage.then(Coverage::new);
+    for suite in &suites {
+        for index in 0..suite.count() {
+            progress.set_message(suite.name(index));
+            let outcome = suite.run(index, recorder.as_ref(), &capabilities);
+            if let Err(report) = outcome.result {
+                progress.suspend(|| println!("{} {}", "FAIL".bright_red(), outcome.name));
+                failures.push((outcome.name, report, outcome.output));
+            }
+            progress.inc(1);
+        }
+    }
+    progress.finish_and_clear();
 
-                let mut a = merc::Interpreter::new(parser);
-                let _ = a.run();
+    for (name, report, output) in &failures {
+        println!("\n{} {}", "----".bright_red(), name.bold());
+        if !output.is_empty() {
+            print!("{}", output);
+        }
+        eprintln!("{:?}", report);
+    }
+    println!(
+        "\n{} passed, {} failed",
+        (total - failures.len()).to_string().bright_green(),
+        failures.len().to_string().bright_red()
+    );
+    if let Some(recorder) = recorder {
+        let coverage = recorder.files();
+        print!("\n{}", merc::coverage::summary(&coverage));
+        fs::write(&args.lcov, merc::coverage::lcov(&coverage))
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Failed to write file: {}", args.lcov.display()))?;
+    }
+    if !failures.is_empty() {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+/// The default capabilities, plus access to the `--allow-fs` directories.
+fn allow_fs(dirs: Vec<PathBuf>) -> Capabilities {
+    Capabilities {
+        fs: dirs,
+        ..Capabilities::default()
+    }
+}
 
-                Ok(())
+fn debug(args: DebugArgs) -> Result<()> {
+    let source = read_source(&args.file)?;
+    let terminal = Terminal::new(&args.file)?;
+    let debugger = args
+        .breakpoint
+        .iter()
+        .fold(Debugger::new(terminal), |debugger, line| debugger.breakpoint(*line))
+        .stop_on_entry(args.breakpoint.is_empty());
+
+    let mut interpreter = Interpreter::default();
+    interpreter.set_file(Path::new(&args.file));
+    interpreter.set_capabilities(allow_fs(args.allow_fs));
+    if let Err(report) = debugger.run_in(interpreter, &source) {
+        fail(report);
+    }
+    Ok(())
+}
+
+/// Parses every statement of `source`, exiting on the first parse error.
+fn parse_program(source: &str) -> Vec<merc::S> {
+    let mut parser = merc::Parser::new(source);
+    let mut program = Vec::new();
+    while let Some(statement) = parser.parse_statement() {
+        match statement {
+            Ok(statement) => program.push(statement),
+            Err(err) => fail(Report::new(ScriptError::Parse(err))),
+        }
+    }
+    program
+}
+
+/// Every name a script can use without defining it.
+fn builtins() -> Vec<String> {
+    let mut builtins: Vec<String> = Interpreter::default().builtin_names().cloned().collect();
+    builtins.push("args".to_string());
+    builtins
+}
+
+fn fmt(args: FmtArgs) -> Result<()> {
+    let files = if args.files.is_empty() {
+        vec!["-".to_string()]
+    } else {
+        args.files
+    };
+
+    let mut unformatted = false;
+    for file in &files {
+        let source = read_source(file)?;
+        let formatted = match merc::format::format(&source) {
+            Ok(formatted) => formatted,
+            Err(report) => fail(report.wrap_err(format!("Failed to format {}", file))),
+        };
+
+        if args.check {
+            if formatted != source {
+                println!("{}", if file == "-" { "<stdin>" } else { file });
+                unformatted = true;
             }
-            None => Ok(()),
-        },
-        (None, None, None) => {
-            repl::repl();
-            Ok(())
+        } else if file == "-" {
+            print!("{}", formatted);
+        } else if formatted != source {
+            fs::write(file, formatted)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("Failed to write file: {}", file))?;
         }
-        (_, _, _) => Ok(()),
+    }
+
+    if unformatted {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+impl SourceArgs {
+    fn read(&self) -> Result<String> {
+        match (&self.eval, &self.file) {
+            (Some(code), _) => Ok(code.clone()),
+            (None, Some(file)) => read_source(file),
+            (None, None) => unreachable!("clap requires a file or --eval"),
+        }
+    }
+}
+
+/// Reads a script from `file`, or from stdin when it is `-`.
+fn read_source(file: &str) -> Result<String> {
+    if file == "-" {
+        let mut source = String::new();
+        io::stdin()
+            .read_to_string(&mut source)
+            .into_diagnostic()
+            .wrap_err("Failed to read script from stdin")?;
+        return Ok(source);
+    }
+
+    fs::read_to_string(file)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Failed to read file: {}", file))
+}
+
+/// Prints `report` and exits with the status matching the kind of failure.
+fn fail(report: Report) -> ! {
+    eprintln!("{:?}", report);
+    process::exit(exit_code(&report));
+}
+
+fn exit_code(report: &Report) -> i32 {
+    match report.downcast_ref::<ScriptError>() {
+        Some(ScriptError::Parse(_)) => EXIT_PARSE_ERROR,
+        Some(ScriptError::Runtime(_)) => EXIT_RUNTIME_ERROR,
+        None => 1,
     }
 }
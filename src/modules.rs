@@ -0,0 +1,139 @@
+//! The registry of script modules loaded with `import`.
+//!
+//! Each file is evaluated once, in its own interpreter, the first time it is
+//! imported; later imports of the same file, from anywhere in the program,
+//! share its environment. Files are identified by their canonical path.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    env,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use miette::{miette, Result};
+
+use crate::{interpreter::Value, TokenKind, S};
+
+/// What the functions a module defines run against.
+#[derive(Debug)]
+pub struct Environment {
+    /// The module's file.
+    pub path: Rc<Path>,
+    pub globals: HashMap<String, Value>,
+    /// The module's source, for errors raised by its code.
+    pub source: Rc<str>,
+}
+
+#[derive(Debug, Default)]
+pub struct Modules {
+    environments: HashMap<PathBuf, Rc<Environment>>,
+    /// The names each module exposes to the files importing it.
+    exports: HashMap<PathBuf, BTreeMap<String, Value>>,
+    /// The files being evaluated, outermost first, to report import cycles.
+    loading: Vec<PathBuf>,
+}
+
+impl Modules {
+    /// Starts the chain of loading files at the program's own file.
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            loading: vec![root],
+            ..Self::default()
+        }
+    }
+
+    pub fn environment(&self, path: &Path) -> Option<Rc<Environment>> {
+        self.environments.get(path).cloned()
+    }
+
+    pub fn exports(&self, path: &Path) -> Option<BTreeMap<String, Value>> {
+        self.exports.get(path).cloned()
+    }
+
+    /// Marks `path` as being evaluated, failing if that is already the case
+    /// further up the chain of imports.
+    pub fn enter(&mut self, path: &Path) -> Result<()> {
+        if let Some(start) = self.loading.iter().position(|loading| loading == path) {
+            let cycle: Vec<String> = self.loading[start..]
+                .iter()
+                .chain([&path.to_path_buf()])
+                .map(|path| display(path))
+                .collect();
+            let steps: Vec<String> = cycle
+                .windows(2)
+                .map(|pair| format!("{} imports {}", pair[0], pair[1]))
+                .collect();
+            return Err(miette!(
+                help = format!(
+                    "{}\nmove the code both files need into a module of its own",
+                    steps.join("\n")
+                ),
+                "Import cycle: {}",
+                cycle.join(" -> ")
+            ));
+        }
+        self.loading.push(path.to_path_buf());
+        Ok(())
+    }
+
+    pub fn leave(&mut self) {
+        self.loading.pop();
+    }
+
+    /// Records the environment of the module at `path` once it has run, exposing
+    /// the names `program` exports, or all names not starting with `_` if it
+    /// exports none.
+    pub fn insert(
+        &mut self,
+        path: PathBuf,
+        program: &[S],
+        environment: Environment,
+    ) -> BTreeMap<String, Value> {
+        let exported: Vec<&str> = program.iter().filter_map(exported_name).collect();
+        let exports: BTreeMap<String, Value> = environment
+            .globals
+            .iter()
+            .filter(|(name, _)| match exported.is_empty() {
+                true => !name.starts_with('_'),
+                false => exported.contains(&name.as_str()),
+            })
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        self.environments.insert(path.clone(), Rc::new(environment));
+        self.exports.insert(path, exports.clone());
+        exports
+    }
+}
+
+/// The name declared by an `export let` or `export func` statement.
+fn exported_name(statement: &S) -> Option<&str> {
+    let S::Cons(token, args) = statement else {
+        return None;
+    };
+    if token.kind != TokenKind::Export {
+        return None;
+    }
+    let name = match args.first()? {
+        S::Cons(_, args) => args.first()?,
+        S::FunDef { name, .. } => name,
+        _ => return None,
+    };
+    match name {
+        S::Atom(token) => match &token.kind {
+            TokenKind::Identifier(name) => Some(name),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// `path` relative to the working directory when it is inside it.
+pub fn display(path: &Path) -> String {
+    env::current_dir()
+        .ok()
+        .and_then(|cwd| path.strip_prefix(cwd).ok().map(Path::to_path_buf))
+        .unwrap_or_else(|| path.to_path_buf())
+        .display()
+        .to_string()
+}
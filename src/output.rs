@@ -0,0 +1,129 @@
+use std::{
+    cell::RefCell,
+    io::{self, Write},
+    rc::Rc,
+};
+
+/// A shared, swappable destination for script output.
+///
+/// Native functions capture a clone of the handle when they are registered,
+/// so redirecting it with [`Output::redirect`] also affects them.
+#[derive(Clone)]
+pub struct Output(Rc<RefCell<Box<dyn Write>>>);
+
+impl Output {
+    pub fn new(writer: Box<dyn Write>) -> Self {
+        Self(Rc::new(RefCell::new(writer)))
+    }
+
+    pub fn stdout() -> Self {
+        Self::new(Box::new(io::stdout()))
+    }
+
+    pub fn stderr() -> Self {
+        Self::new(Box::new(io::stderr()))
+    }
+
+    /// Replaces the underlying writer, returning the previous one.
+    pub fn redirect(&self, writer: Box<dyn Write>) -> Box<dyn Write> {
+        std::mem::replace(&mut *self.0.borrow_mut(), writer)
+    }
+}
+
+impl Write for &Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.borrow_mut().flush()
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (&*self).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (&*self).flush()
+    }
+}
+
+/// An in-memory writer whose contents stay readable after it is handed to
+/// an interpreter, for capturing output in tests and services.
+#[derive(Clone, Default)]
+pub struct OutputBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl OutputBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+
+    /// Returns the captured output and empties the buffer.
+    pub fn take(&self) -> String {
+        let bytes = std::mem::take(&mut *self.0.borrow_mut());
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+}
+
+impl Write for OutputBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Interpreter;
+
+    use super::*;
+
+    #[test]
+    fn redirecting_reaches_every_clone() {
+        let output = Output::new(Box::new(OutputBuffer::new()));
+        let clone = output.clone();
+        let buffer = OutputBuffer::new();
+        output.redirect(Box::new(buffer.clone()));
+        write!(&clone, "to the new writer").unwrap();
+        assert_eq!(buffer.contents(), "to the new writer");
+    }
+
+    #[test]
+    fn take_empties_the_buffer() {
+        let mut buffer = OutputBuffer::new();
+        writeln!(buffer, "first").unwrap();
+        assert_eq!(buffer.take(), "first\n");
+        assert_eq!(buffer.contents(), "");
+        writeln!(buffer, "second").unwrap();
+        assert_eq!(buffer.contents(), "second\n");
+    }
+
+    #[test]
+    fn interpreters_write_to_their_redirected_outputs() {
+        let (stdout, stderr) = (OutputBuffer::new(), OutputBuffer::new());
+        let mut interpreter = Interpreter::default();
+        interpreter.set_stdout(Box::new(stdout.clone()));
+        interpreter.set_stderr(Box::new(stderr.clone()));
+
+        interpreter.eval("print(\"one\")\nprint(1 + 1)").unwrap();
+        assert_eq!(stdout.take(), "one\n2\n");
+
+        // Errors are returned to the host rather than written out.
+        let error = interpreter.eval("print(\"before\")\nprint(missing)\nprint(\"after\")");
+        assert_eq!(error.unwrap_err().to_string(), "Undefined variable: missing");
+        assert_eq!(stdout.contents(), "before\n");
+
+        writeln!(interpreter.stderr(), "reported").unwrap();
+        assert_eq!(stderr.contents(), "reported\n");
+    }
+}
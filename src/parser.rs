@@ -1,7 +1,8 @@
-use std::iter::Peekable;
+use std::{iter::Peekable, ops::Range};
 
 use crate::{
-    lexer::{Token, TokenKind},
+    lexer::{Token, TokenKind, UNTERMINATED_STRING},
+    pretty::Printer,
     Lexer,
 };
 use miette::{miette, Error, LabeledSpan, Severity};
@@ -38,33 +39,99 @@ pub enum S {
         name: Box<S>,
         args: Vec<S>,
     },
+    List(Vec<S>),
+    Map(Vec<(S, S)>),
 }
 
 impl S {
-    pub fn to_string(&self) -> String {
+    /// Whether this statement is an expression evaluated for its value,
+    /// as opposed to a declaration or control flow.
+    pub fn is_expression(&self) -> bool {
         match self {
-            S::Atom(token) => token.kind.to_string(),
+            S::Cons(token, _) => !matches!(
+                token.kind,
+                TokenKind::Let
+                    | TokenKind::Return
+                    | TokenKind::While
+                    | TokenKind::Test
+                    | TokenKind::Import
+                    | TokenKind::From
+                    | TokenKind::Export
+            ),
+            S::FunDef { .. } | S::IfExpr { .. } | S::Block(_) => false,
+            _ => true,
+        }
+    }
+
+    /// The leftmost token of the node, e.g. to find the line it starts on.
+    pub fn first_token(&self) -> Option<&Token> {
+        match self {
+            S::Atom(token) => Some(token),
+            S::Cons(token, args) => args
+                .first()
+                .and_then(S::first_token)
+                .filter(|first| first.index < token.index)
+                .or(Some(token)),
+            S::BinaryExpr { lhs, .. } => lhs.first_token(),
+            S::IfExpr { cond, .. } => cond.first_token(),
+            S::Block(statements) | S::List(statements) => {
+                statements.first().and_then(S::first_token)
+            }
+            S::FunDef { name, .. } | S::FunCall { name, .. } => name.first_token(),
+            S::Map(entries) => entries.first().and_then(|(key, _)| key.first_token()),
+        }
+    }
+
+    /// The byte range covered by the node's tokens, e.g. to tell nodes apart
+    /// across clones of a function body.
+    pub fn span(&self) -> Option<Range<usize>> {
+        let children: Vec<&S> = match self {
+            S::Atom(token) => return Some(token.span()),
             S::Cons(token, args) => {
-                let args = args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
-                format!("{:?} {:?}", token, args)
+                let span = token.span();
+                return Some(args.iter().filter_map(S::span).fold(span, union));
             }
-            S::BinaryExpr { op, lhs, rhs } => format!("{:?} {:?} {:?}", op, lhs, rhs),
+            S::BinaryExpr { lhs, rhs, .. } => vec![lhs, rhs],
             S::IfExpr {
                 cond,
                 then_branch,
                 else_branch,
-            } => format!("if {:?} {:?} {:?}", cond, then_branch, else_branch),
-            S::Block(block) => format!("{:?}", block),
-            S::FunDef { name, args, body } => format!("def {:?} {:?} {:?}", name, args, body),
-            S::FunCall { name, args } => format!("call {:?} {:?}", name, args),
-        }
+            } => [cond, then_branch].into_iter().chain(else_branch).map(|b| &**b).collect(),
+            S::Block(items) | S::List(items) => items.iter().collect(),
+            S::FunDef { name, args, body } => {
+                [&**name].into_iter().chain(args).chain([&**body]).collect()
+            }
+            S::FunCall { name, args } => [&**name].into_iter().chain(args).collect(),
+            S::Map(entries) => entries.iter().flat_map(|(key, value)| [key, value]).collect(),
+        };
+        children.into_iter().filter_map(S::span).reduce(union)
     }
 }
 
+fn union(a: Range<usize>, b: Range<usize>) -> Range<usize> {
+    a.start.min(b.start)..a.end.max(b.end)
+}
+
+/// Formats as an S-expression such as `(+ 1 (* 2 3))`, or as an indented
+/// tree with `{:#}`; see [`Printer`] for spans.
+impl std::fmt::Display for S {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if f.alternate() {
+            write!(f, "{}", Printer::new().tree(self).trim_end())
+        } else {
+            write!(f, "{}", Printer::new().sexpr(self))
+        }
+    }
+}
 
 pub struct Parser<'a> {
     lexer: Peekable<Lexer<'a>>,
     _whole_input: &'a str,
+    /// Start of the first token and end of the last token consumed by the
+    /// statement being parsed, ignoring newlines.
+    statement_span: Option<Range<usize>>,
+    /// Set when parsing failed because the input ended mid-construct.
+    incomplete: bool,
 }
 
 impl<'a> Parser<'a> {
@@ -72,181 +139,297 @@ impl<'a> Parser<'a> {
         Self {
             lexer: Lexer::new(input).peekable(),
             _whole_input: input,
+            statement_span: None,
+            incomplete: false,
         }
     }
 
-    pub fn parse_statement(&mut self) -> Option<Result<S, Error>> {
-        if let Some(Ok(token)) = self.eat_token() {
-            match token {
-                Token {
-                    kind: TokenKind::EOF,
-                    ..
-                } => return None,
-                Token {
-                    kind: TokenKind::NewLine,
-                    ..
-                } => {
-                    return self.parse_statement();
-                }
-                Token {
-                    kind: TokenKind::Let,
-                    row,
-                    column,
-                    index,
-                } => {
-                    let name_match =
-                        self.match_tokens(TokenKind::Identifier("variable_name".to_string()));
-
-                    if let Err(e) = name_match {
-                        return Some(Err(miette!{
-                            severity = Severity::Error,
-                            labels = vec![LabeledSpan::at(e.index-2..e.index, format!("Expected Identifier, got {:?}", e.kind))],
-                            help = format!("use"),
-                            "Expected Identifier, got {:?}", e.kind,
-                        }.with_source_code(self._whole_input.to_string())));
-                    }
+    /// The text being parsed.
+    pub fn source(&self) -> &'a str {
+        self._whole_input
+    }
 
-                    let name = self.eat_token().unwrap().unwrap();
-                    let equal = self.match_tokens(TokenKind::Equal);
+    /// Whether the last error was caused by the input ending in the middle of
+    /// a statement, such as an unclosed brace or string or a trailing
+    /// operator, so that more input could complete it.
+    pub fn is_incomplete(&self) -> bool {
+        self.incomplete
+    }
 
-                    if let Err(equal) = equal {
-                        return Some(Err(miette!{
-                            severity = Severity::Error,
-                            labels = vec![LabeledSpan::at(equal.index-2..equal.index, format!("Expected Equal, got {:?}", equal.kind))],
-                            help = format!("use"),
-                            "Expected Equal, got {:?}", equal.kind,
-                        }.with_source_code(self._whole_input.to_string())));
-                    }
+    /// Byte range of the source text of the statement most recently returned
+    /// by [`Parser::parse_statement`].
+    pub fn statement_span(&self) -> Option<Range<usize>> {
+        self.statement_span.clone()
+    }
 
-                    self.eat_token();
-                    match self.peek_token() {
-                        Some(Ok(Token {
-                            kind: TokenKind::LeftBrace,
-                            ..
-                        })) => {
-                            let block = self.parse_block().unwrap();
-                            return Some(Ok(S::Cons(
-                                Token {
-                                    kind: TokenKind::Let,
-                                    row,
-                                    column,
-                                    index,
-                                },
-                                vec![S::Atom(name), S::Block(block)],
-                            )));
-                        }
+    pub fn parse_statement(&mut self) -> Option<Result<S, Error>> {
+        self.statement_span = None;
+        self.incomplete = false;
+        self.statement()
+    }
 
-                        _token => {
-                            let expr = self.parse_expression(0).unwrap().unwrap();
-                            return Some(Ok(S::Cons(
-                                Token {
-                                    kind: TokenKind::Let,
-                                    row,
-                                    column,
-                                    index,
-                                },
-                                vec![S::Atom(name), expr],
-                            )));
-                        }
-                    }
-                }
-                Token {
-                    kind: TokenKind::LeftBrace,
-                    row: _,
-                    column: _,
-                    index: _,
-                } => {
-                    let block = self.parse_block().unwrap();
-                    return Some(Ok(S::Block(block)));
-                }
+    fn statement(&mut self) -> Option<Result<S, Error>> {
+        if self.at_expression() {
+            return Some(self.parse_operand().map_err(|err| miette!(err)));
+        }
 
-                Token {
-                    kind: TokenKind::Fun,
-                    row: _,
-                    column: _,
-                    index: _,
-                } => {
-                    return self.parse_function_definition();
+        let token = match self.eat_token()? {
+            Ok(token) => token,
+            Err(err) => return Some(Err(err)),
+        };
+        match token {
+            Token {
+                kind: TokenKind::EOF,
+                ..
+            } => None,
+            Token {
+                kind: TokenKind::NewLine | TokenKind::Semicolon,
+                ..
+            } => {
+                self.statement()
+            }
+            Token {
+                kind: TokenKind::Let,
+                row,
+                column,
+                index,
+                start,
+            } => {
+                let name_match =
+                    self.match_tokens(TokenKind::Identifier("variable_name".to_string()));
+
+                if let Err(e) = name_match {
+                    return Some(Err(miette!{
+                        severity = Severity::Error,
+                        labels = vec![LabeledSpan::at(e.index.saturating_sub(2)..e.index, format!("Expected Identifier, got {:?}", e.kind))],
+                        help = format!("use"),
+                        "Expected Identifier, got {:?}", e.kind,
+                    }.with_source_code(self._whole_input.to_string())));
                 }
 
-                Token {
-                    kind: TokenKind::If,
-                    row: _,
-                    column: _,
-                    index: _,
-                } => {
-                    return self.parse_if_expression();
+                let name = self.eat_token().unwrap().unwrap();
+                let equal = self.match_tokens(TokenKind::Equal);
+
+                if let Err(equal) = equal {
+                    return Some(Err(miette!{
+                        severity = Severity::Error,
+                        labels = vec![LabeledSpan::at(equal.index.saturating_sub(2)..equal.index, format!("Expected Equal, got {:?}", equal.kind))],
+                        help = format!("use"),
+                        "Expected Equal, got {:?}", equal.kind,
+                    }.with_source_code(self._whole_input.to_string())));
                 }
 
-                Token {
-                    kind: TokenKind::Return,
-                    row,
-                    column,
-                    index,
-                } => {
-                    let expr = self.parse_expression(0).unwrap().unwrap();
-                    return Some(Ok(S::Cons(
-                        Token {
-                            kind: TokenKind::Return,
-                            row,
-                            column,
-                            index,
-                        },
-                        vec![expr],
-                    )));
+                self.eat_token();
+                match self.peek_token() {
+                    Some(Ok(Token {
+                        kind: TokenKind::LeftBrace,
+                        ..
+                    })) => {
+                        self.eat_token();
+                        self.skip_newlines();
+                        let value = if self.at_map_body() {
+                            match self.parse_map_body() {
+                                Ok(map) => map,
+                                Err(err) => return Some(Err(miette!(err))),
+                            }
+                        } else {
+                            match self.parse_block_body() {
+                                Ok(block) => S::Block(block),
+                                Err(err) => return Some(Err(miette!(err))),
+                            }
+                        };
+                        Some(Ok(S::Cons(
+                            Token {
+                                kind: TokenKind::Let,
+                                row,
+                                column,
+                                index,
+                                start,
+                            },
+                            vec![S::Atom(name), value],
+                        )))
+                    }
+
+                    _token => {
+                        let expr = match self.parse_operand() {
+                            Ok(expr) => expr,
+                            Err(err) => return Some(Err(miette!(err))),
+                        };
+                        Some(Ok(S::Cons(
+                            Token {
+                                kind: TokenKind::Let,
+                                row,
+                                column,
+                                index,
+                                start,
+                            },
+                            vec![S::Atom(name), expr],
+                        )))
+                    }
                 }
+            }
+            Token {
+                kind: TokenKind::LeftBrace,
+                row: _,
+                column: _,
+                index: _,
+                start: _,
+            } => {
+                Some(
+                    self.parse_block_body()
+                        .map(S::Block)
+                        .map_err(|err| miette!(err)),
+                )
+            }
 
-                Token {
-                    kind: TokenKind::Identifier(t),
-                    row,
-                    column,
-                    index,
-                } => {
-                    let name = Token {
-                        kind: TokenKind::Identifier(t),
+            Token {
+                kind: TokenKind::Fun,
+                row: _,
+                column: _,
+                index: _,
+                start: _,
+            } => {
+                self.parse_function_definition()
+            }
+
+            Token {
+                kind: TokenKind::If,
+                row: _,
+                column: _,
+                index: _,
+                start: _,
+            } => {
+                self.parse_if_expression()
+            }
+
+            Token {
+                kind: TokenKind::Return,
+                row,
+                column,
+                index,
+                start,
+            } => {
+                let expr = match self.parse_operand() {
+                    Ok(expr) => expr,
+                    Err(err) => return Some(Err(miette!(err))),
+                };
+                Some(Ok(S::Cons(
+                    Token {
+                        kind: TokenKind::Return,
                         row,
                         column,
                         index,
-                    };
-
-                    let same = self.match_tokens(TokenKind::LeftParen);
+                        start,
+                    },
+                    vec![expr],
+                )))
+            }
 
-                    if same.is_ok() {
-                        let args = self.parse_arguments().unwrap();
-                        return Some(Ok(S::FunCall {
-                            name: Box::new(S::Atom(name)),
-                            args,
-                        }));
-                    }
+            Token {
+                kind: TokenKind::While,
+                row,
+                column,
+                index,
+                start,
+            } => {
+                self.parse_while_expression(row, column, index, start)
+            }
 
-                    return Some(Ok(S::Atom(name)));
-                }
+            Token {
+                kind: TokenKind::Test,
+                row,
+                column,
+                index,
+                start,
+            } => {
+                self.parse_test_block(row, column, index, start)
+            }
 
-                Token {
-                    kind: TokenKind::While,
+            Token {
+                kind: kind @ (TokenKind::Import | TokenKind::From),
+                row,
+                column,
+                index,
+                start,
+            } => {
+                let token = Token {
+                    kind,
                     row,
                     column,
                     index,
-                } => {
-                    return self.parse_while_expression(row, column, index);
-                }
+                    start,
+                };
+                Some(self.parse_import(token))
+            }
 
-                token => {
-                    return Some(Err(miette!(
-                        labels = vec![LabeledSpan::at(
-                            token.index - 2..token.index,
-                            format!("Unexpected token: {:?}", token.kind)
-                        )],
-                        severity = Severity::Error,
-                        help = format!("use {:?}", token.kind),
-                        "Unexpected token: {:?}",
-                        token.kind
-                    )
-                    .with_source_code(self._whole_input.to_string())));
-                }
+            Token {
+                kind: TokenKind::Export,
+                row,
+                column,
+                index,
+                start,
+            } => {
+                self.parse_export(row, column, index, start)
+            }
+
+            token => {
+                Some(Err(miette!(
+                    labels = vec![LabeledSpan::at(
+                        token.index.saturating_sub(2)..token.index,
+                        format!("Unexpected token: {:?}", token.kind)
+                    )],
+                    severity = Severity::Error,
+                    help = format!("use {:?}", token.kind),
+                    "Unexpected token: {:?}",
+                    token.kind
+                )
+                .with_source_code(self._whole_input.to_string())))
             }
+        }
+    }
+
+    /// Whether the next token starts an expression statement such as
+    /// `f(x)`, `1 + 2` or `[1, 2]`.
+    /// Whether the `{` just consumed opens a map rather than a block: it is
+    /// empty, or its first entry starts with a string or `name:`.
+    fn at_map_body(&mut self) -> bool {
+        let key_end = match self.peek_token() {
+            Some(Ok(Token {
+                kind: TokenKind::String(_) | TokenKind::RightBrace,
+                ..
+            })) => return true,
+            Some(Ok(
+                token @ Token {
+                    kind: TokenKind::Identifier(_),
+                    ..
+                },
+            )) => token.span().end,
+            _ => return false,
         };
-        None
+        // Only one token can be peeked, so look for the colon in the source.
+        self._whole_input[key_end..]
+            .trim_start_matches([' ', '\t'])
+            .starts_with(':')
+    }
+
+    fn at_expression(&mut self) -> bool {
+        matches!(
+            self.peek_token(),
+            Some(Ok(Token {
+                kind: TokenKind::Identifier(_)
+                    | TokenKind::Number(_)
+                    | TokenKind::String(_)
+                    | TokenKind::True
+                    | TokenKind::False
+                    | TokenKind::Nil
+                    | TokenKind::LeftParen
+                    | TokenKind::LeftBracket
+                    | TokenKind::Minus
+                    | TokenKind::Plus
+                    | TokenKind::Bang,
+                ..
+            }))
+        )
     }
 
     fn parse_expression(&mut self, min_bp: u8) -> Option<Result<S, String>> {
@@ -275,7 +458,11 @@ impl<'a> Parser<'a> {
                 } => {
                     let token = self.eat_token().unwrap().unwrap();
                     let (_, r_bp) = get_prefix_binding_power(&token);
-                    let right = self.parse_equality(r_bp).unwrap().unwrap();
+                    let right = match self.parse_equality(r_bp) {
+                        Some(Ok(right)) => right,
+                        Some(Err(err)) => return Some(Err(err)),
+                        None => return Some(Err(self.unexpected_eof())),
+                    };
                     S::Cons(token, vec![right])
                 }
 
@@ -293,34 +480,43 @@ impl<'a> Parser<'a> {
 
                     self.eat_token();
 
-                    expr.unwrap()
+                    match expr.and_then(|expr| self.parse_postfix(expr)) {
+                        Ok(expr) => expr,
+                        Err(err) => return Some(Err(err)),
+                    }
+                }
+
+                Token {
+                    kind: TokenKind::LeftBracket,
+                    ..
+                } => {
+                    self.eat_token();
+                    match self.parse_list_body().and_then(|list| self.parse_postfix(list)) {
+                        Ok(list) => list,
+                        Err(err) => return Some(Err(err)),
+                    }
+                }
+
+                Token {
+                    kind: TokenKind::LeftBrace,
+                    ..
+                } => {
+                    self.eat_token();
+                    match self.parse_map_body().and_then(|map| self.parse_postfix(map)) {
+                        Ok(map) => map,
+                        Err(err) => return Some(Err(err)),
+                    }
                 }
                 _token => {
                     let token = self.eat_token().unwrap().unwrap();
-                    if let Some(Ok(Token {
-                        kind: TokenKind::LeftParen,
-                        ..
-                    })) = self.peek_token()
-                    {
-                        let args = self.parse_arguments().unwrap();
-
-                        return Some(Ok(S::FunCall {
-                            name: Box::new(S::Atom(token)),
-                            args,
-                        }));
+                    match self.parse_postfix(S::Atom(token)) {
+                        Ok(expr) => expr,
+                        Err(err) => return Some(Err(err)),
                     }
-                    S::Atom(token)
                 }
             },
             Some(Err(err)) => return Some(Err(err.to_string())),
-            None => {
-                return Some(Ok(S::Atom(Token {
-                    kind: TokenKind::EOF,
-                    row: 0,
-                    column: 0,
-                    index: 0,
-                })))
-            }
+            None => return Some(Err(self.unexpected_eof())),
         };
 
         loop {
@@ -349,7 +545,11 @@ impl<'a> Parser<'a> {
                 }
                 self.eat_token();
 
-                let right = self.parse_expression(r_bp).unwrap().unwrap();
+                let right = match self.parse_expression(r_bp) {
+                    Some(Ok(right)) => right,
+                    Some(Err(err)) => return Some(Err(err)),
+                    None => return Some(Err(self.unexpected_eof())),
+                };
                 left = S::Cons(operator, vec![left, right]);
                 continue;
             }
@@ -364,60 +564,216 @@ impl<'a> Parser<'a> {
         self.parse_expression(min_bp)
     }
 
-    fn parse_block(&mut self) -> Result<Vec<S>, String> {
-        self.eat_token();
-        let mut block = Vec::new();
-        match self.peek_token() {
-            Some(Ok(Token {
-                kind: TokenKind::RightBrace,
-                ..
-            })) => {
+    /// Parses calls, member accesses and indexing applied to `left`.
+    fn parse_postfix(&mut self, mut left: S) -> Result<S, String> {
+        loop {
+            match self.peek_token() {
+                Some(Ok(Token {
+                    kind: TokenKind::LeftParen,
+                    ..
+                })) => {
+                    let args = self.parse_arguments()?;
+                    left = S::FunCall {
+                        name: Box::new(left),
+                        args,
+                    };
+                }
+                Some(Ok(Token {
+                    kind: TokenKind::Dot,
+                    ..
+                })) => {
+                    let dot = self.eat_token().unwrap().unwrap();
+                    match self.eat_token() {
+                        Some(Ok(
+                            name @ Token {
+                                kind: TokenKind::Identifier(_),
+                                ..
+                            },
+                        )) => left = S::Cons(dot, vec![left, S::Atom(name)]),
+                        Some(Ok(token)) => {
+                            return Err(format!("Expected Identifier after '.', got {:?}", token.kind))
+                        }
+                        Some(Err(err)) => return Err(err.to_string()),
+                        None => return Err("Expected Identifier after '.'".to_string()),
+                    }
+                }
+                Some(Ok(Token {
+                    kind: TokenKind::LeftBracket,
+                    ..
+                })) => {
+                    let bracket = self.eat_token().unwrap().unwrap();
+                    let index = self.parse_operand()?;
+                    if self.match_tokens(TokenKind::RightBracket).is_err() {
+                        return Err("Expected RightBracket".to_string());
+                    }
+                    self.eat_token();
+                    left = S::Cons(bracket, vec![left, index]);
+                }
+                _ => return Ok(left),
+            }
+        }
+    }
+
+    /// Parses an expression that must be present, e.g. a list item or an index.
+    fn parse_operand(&mut self) -> Result<S, String> {
+        match self.parse_expression(0) {
+            Some(expr) => expr,
+            None => Err(self.unexpected_eof()),
+        }
+    }
+
+    /// Parses the items of a list literal; the opening `[` is already consumed.
+    fn parse_list_body(&mut self) -> Result<S, String> {
+        let mut items = Vec::new();
+        loop {
+            self.skip_newlines();
+            if self.match_tokens(TokenKind::RightBracket).is_ok() {
                 self.eat_token();
-                return Ok(block);
+                return Ok(S::List(items));
+            }
+
+            items.push(self.parse_operand()?);
+            self.skip_newlines();
+
+            match self.eat_token() {
+                Some(Ok(Token {
+                    kind: TokenKind::Comma,
+                    ..
+                })) => continue,
+                Some(Ok(Token {
+                    kind: TokenKind::RightBracket,
+                    ..
+                })) => return Ok(S::List(items)),
+                Some(Ok(token)) => return Err(format!("Expected ',' or ']', Got: {:?}", token.kind)),
+                Some(Err(err)) => return Err(err.to_string()),
+                None => {
+                    self.incomplete = true;
+                    return Err("Expected ',' or ']', Got: EOF".to_string());
+                }
+            }
+        }
+    }
+
+    /// Parses the entries of a map literal; the opening `{` is already consumed.
+    fn parse_map_body(&mut self) -> Result<S, String> {
+        let mut entries = Vec::new();
+        loop {
+            self.skip_newlines();
+            let key = match self.eat_token() {
+                Some(Ok(Token {
+                    kind: TokenKind::RightBrace,
+                    ..
+                })) => return Ok(S::Map(entries)),
+                Some(Ok(
+                    key @ Token {
+                        kind: TokenKind::String(_) | TokenKind::Identifier(_),
+                        ..
+                    },
+                )) => S::Atom(key),
+                Some(Ok(token)) => return Err(format!("Expected map key, got {:?}", token.kind)),
+                Some(Err(err)) => return Err(err.to_string()),
+                None => {
+                    self.incomplete = true;
+                    return Err("Expected map key, got EOF".to_string());
+                }
+            };
+
+            if self.match_tokens(TokenKind::Colon).is_err() {
+                return Err("Expected Colon after map key".to_string());
+            }
+            self.eat_token();
+
+            let value = self.parse_operand()?;
+            entries.push((key, value));
+            self.skip_newlines();
+
+            match self.eat_token() {
+                Some(Ok(Token {
+                    kind: TokenKind::Comma,
+                    ..
+                })) => continue,
+                Some(Ok(Token {
+                    kind: TokenKind::RightBrace,
+                    ..
+                })) => return Ok(S::Map(entries)),
+                Some(Ok(token)) => return Err(format!("Expected ',' or '}}', Got: {:?}", token.kind)),
+                Some(Err(err)) => return Err(err.to_string()),
+                None => {
+                    self.incomplete = true;
+                    return Err("Expected ',' or '}', Got: EOF".to_string());
+                }
             }
+        }
+    }
+
+    fn unexpected_eof(&mut self) -> String {
+        self.incomplete = true;
+        "Unexpected end of input".to_string()
+    }
+
+    fn skip_newlines(&mut self) {
+        while let Some(Ok(Token {
+            kind: TokenKind::NewLine,
+            ..
+        })) = self.peek_token()
+        {
+            self.eat_token();
+        }
+    }
+
+    fn parse_block(&mut self) -> Result<Vec<S>, String> {
+        self.skip_newlines();
+        match self.eat_token() {
             Some(Ok(Token {
-                kind: TokenKind::EOF,
+                kind: TokenKind::LeftBrace,
                 ..
-            })) => {
-                return Ok(block);
-            }
+            })) => self.parse_block_body(),
+            Some(Ok(token)) => Err(format!("Expected LeftBrace, got {:?}", token.kind)),
+            Some(Err(err)) => Err(err.to_string()),
+            None => Err(self.unexpected_eof()),
+        }
+    }
 
-            _ => {
-                while let Some(Ok(token)) = self.peek_token() {
-                    match token {
-                        Token {
-                            kind: TokenKind::NewLine,
-                            ..
-                        } => {
-                            self.eat_token();
-                        }
-                        Token {
-                            kind: TokenKind::EOF,
-                            ..
-                        } => {
-                            return Ok(block);
-                        }
+    /// Parses the statements of a block; the opening `{` is already consumed.
+    fn parse_block_body(&mut self) -> Result<Vec<S>, String> {
+        let mut block = Vec::new();
+        loop {
+            let token = match self.peek_token() {
+                Some(Ok(token)) => token,
+                Some(Err(_)) => return Err(self.eat_token().unwrap().unwrap_err().to_string()),
+                None => return Err(self.unexpected_eof()),
+            };
+            match token {
+                Token {
+                    kind: TokenKind::NewLine | TokenKind::Semicolon,
+                    ..
+                } => {
+                    self.eat_token();
+                }
+                Token {
+                    kind: TokenKind::EOF,
+                    ..
+                } => {
+                    return Ok(block);
+                }
 
-                        Token {
-                            kind: TokenKind::RightBrace,
-                            ..
-                        } => {
-                            self.eat_token();
-                            return Ok(block);
-                        }
-                        _ => {
-                            if let Some(expr) = self.parse_statement() {
-                                match expr {
-                                    Ok(expr) => block.push(expr),
-                                    Err(err) => return Err(miette!(err).to_string()),
-                                }
-                            }
+                Token {
+                    kind: TokenKind::RightBrace,
+                    ..
+                } => {
+                    self.eat_token();
+                    return Ok(block);
+                }
+                _ => {
+                    if let Some(expr) = self.statement() {
+                        match expr {
+                            Ok(expr) => block.push(expr),
+                            Err(err) => return Err(err.to_string()),
                         }
                     }
                 }
             }
         }
-        Ok(block)
     }
 
     fn parse_arguments(&mut self) -> Result<Vec<S>, String> {
@@ -435,8 +791,10 @@ impl<'a> Parser<'a> {
         }
 
         loop {
-            let arg = self.parse_expression(0).unwrap().unwrap();
+            self.skip_newlines();
+            let arg = self.parse_operand()?;
             args.push(arg);
+            self.skip_newlines();
 
             match self.eat_token() {
                 Some(Ok(Token {
@@ -449,10 +807,9 @@ impl<'a> Parser<'a> {
                 })) => {
                     break;
                 }
-                Some(token) => return Err(format!("Expected ',' or ')', Got: {:?}", token).into()),
-                None => {
-                    break;
-                }
+                Some(Ok(token)) => return Err(format!("Expected ',' or ')', Got: {:?}", token.kind)),
+                Some(Err(err)) => return Err(err.to_string()),
+                None => return Err(self.unexpected_eof()),
             }
         }
 
@@ -460,8 +817,12 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_if_expression(&mut self) -> Option<Result<S, Error>> {
-        let cond = self.parse_expression(0).unwrap().unwrap();
-        let then_branch = self.parse_block().unwrap();
+        Some(self.parse_if_parts().map_err(|err| miette!(err)))
+    }
+
+    fn parse_if_parts(&mut self) -> Result<S, String> {
+        let cond = self.parse_operand()?;
+        let then_branch = self.parse_block()?;
 
         while let Some(Ok(Token {
             kind: TokenKind::NewLine,
@@ -478,10 +839,10 @@ impl<'a> Parser<'a> {
         })) = self.peek_token()
         {
             self.eat_token();
-            else_branch = self.parse_block().unwrap();
+            else_branch = self.parse_block()?;
         }
 
-        return Some(Ok(S::IfExpr {
+        Ok(S::IfExpr {
             cond: Box::new(cond),
             then_branch: Box::new(S::Block(then_branch)),
             else_branch: if else_branch.is_empty() {
@@ -489,7 +850,7 @@ impl<'a> Parser<'a> {
             } else {
                 Some(Box::new(S::Block(else_branch)))
             },
-        }));
+        })
     }
 
     fn parse_while_expression(
@@ -497,18 +858,131 @@ impl<'a> Parser<'a> {
         row: usize,
         column: usize,
         index: usize,
+        start: usize,
     ) -> Option<Result<S, Error>> {
-        let cond = self.parse_expression(0).unwrap().unwrap();
-        let block = self.parse_block().unwrap();
-        return Some(Ok(S::Cons(
+        let parts = self
+            .parse_operand()
+            .and_then(|cond| Ok((cond, self.parse_block()?)));
+        let (cond, block) = match parts {
+            Ok(parts) => parts,
+            Err(err) => return Some(Err(miette!(err))),
+        };
+        Some(Ok(S::Cons(
             Token {
                 kind: TokenKind::While,
                 row,
                 column,
                 index,
+                start,
             },
             vec![cond, S::Block(block)],
-        )));
+        )))
+    }
+
+    /// Parses `test "name" { ... }` into `(test "name" (block ...))`.
+    fn parse_test_block(
+        &mut self,
+        row: usize,
+        column: usize,
+        index: usize,
+        start: usize,
+    ) -> Option<Result<S, Error>> {
+        let name = match self.match_tokens(TokenKind::String(String::new())) {
+            Ok(_) => self.eat_token().unwrap().unwrap(),
+            Err(e) => {
+                return Some(Err(miette! {
+                    severity = Severity::Error,
+                    labels = vec![LabeledSpan::at(e.index.saturating_sub(2)..e.index, format!("Expected the test's name, got {:?}", e.kind))],
+                    help = "name the test with a string, e.g. `test \"adds numbers\" { ... }`",
+                    "Expected String, got {:?}", e.kind,
+                }
+                .with_source_code(self._whole_input.to_string())));
+            }
+        };
+        let block = match self.parse_block() {
+            Ok(block) => block,
+            Err(err) => return Some(Err(miette!(err))),
+        };
+        Some(Ok(S::Cons(
+            Token {
+                kind: TokenKind::Test,
+                row,
+                column,
+                index,
+                start,
+            },
+            vec![S::Atom(name), S::Block(block)],
+        )))
+    }
+
+    /// Parses the rest of `import "path" as name`, or of `from "path" import
+    /// a, b` when `token` is `from`, into the path followed by the names.
+    fn parse_import(&mut self, token: Token) -> Result<S, Error> {
+        let help = "e.g. `import \"lib.mc\" as lib` or `from \"lib.mc\" import a, b`";
+        let path = self.expect(TokenKind::String(String::new()), "the module's path", help)?;
+        let mut names = vec![S::Atom(path)];
+        if token.kind == TokenKind::Import {
+            self.expect(TokenKind::As, "`as`", help)?;
+            names.push(S::Atom(self.expect(TokenKind::Identifier(String::new()), "a name", help)?));
+        } else {
+            self.expect(TokenKind::Import, "`import`", help)?;
+            loop {
+                names.push(S::Atom(self.expect(TokenKind::Identifier(String::new()), "a name", help)?));
+                if self.match_tokens(TokenKind::Comma).is_err() {
+                    break;
+                }
+                self.eat_token();
+            }
+        }
+        Ok(S::Cons(token, names))
+    }
+
+    fn parse_export(
+        &mut self,
+        row: usize,
+        column: usize,
+        index: usize,
+        start: usize,
+    ) -> Option<Result<S, Error>> {
+        let token = Token {
+            kind: TokenKind::Export,
+            row,
+            column,
+            index,
+            start,
+        };
+        let declaration = match self.statement()? {
+            Ok(statement @ (S::FunDef { .. } | S::Cons(Token { kind: TokenKind::Let, .. }, _))) => {
+                statement
+            }
+            Ok(_) => {
+                return Some(Err(miette! {
+                    severity = Severity::Error,
+                    labels = vec![LabeledSpan::at(token.span(), "exported here")],
+                    help = "export a declaration, e.g. `export func area(r) { ... }`",
+                    "Only `let` and `func` declarations can be exported",
+                }
+                .with_source_code(self._whole_input.to_string())))
+            }
+            Err(err) => return Some(Err(err)),
+        };
+        Some(Ok(S::Cons(token, vec![declaration])))
+    }
+
+    /// Consumes the next token if it is of the `expected` kind, described as
+    /// `what` in the error otherwise.
+    fn expect(&mut self, expected: TokenKind, what: &str, help: &str) -> Result<Token, Error> {
+        match self.match_tokens(expected) {
+            Ok(_) => Ok(self.eat_token().unwrap().unwrap()),
+            Err(e) if e.kind == TokenKind::EOF => Err(miette!(self.unexpected_eof())),
+            Err(e) => Err(miette! {
+                severity = Severity::Error,
+                labels = vec![LabeledSpan::at(e.span(), format!("Expected {}", what))],
+                help = help.to_string(),
+                "Expected {}, got {:?}", what, e.kind,
+            }
+            .with_source_code(self._whole_input.to_string())),
+        }
     }
 
     fn parse_function_definition(&mut self) -> Option<Result<S, Error>> {
@@ -517,7 +991,7 @@ impl<'a> Parser<'a> {
         if let Err(e) = name_match {
             return Some(Err(miette!{
             severity = Severity::Error,
-            labels = vec![LabeledSpan::at(e.index-2..e.index, format!("Expected Identifier, got {:?}", e.kind))],
+            labels = vec![LabeledSpan::at(e.index.saturating_sub(2)..e.index, format!("Expected Identifier, got {:?}", e.kind))],
             help = format!("use"),
             "Expected Identifier, got {:?}", e.kind,
         }.with_source_code(self._whole_input.to_string())));
@@ -525,20 +999,45 @@ impl<'a> Parser<'a> {
 
         let name = self.eat_token().unwrap().unwrap();
 
-        let args = self.parse_arguments().unwrap();
-        let body = self.parse_block().unwrap();
-        return Some(Ok(S::FunDef {
+        if self.match_tokens(TokenKind::LeftParen).is_err() {
+            return Some(Err(miette!("Expected LeftParen after function name")));
+        }
+        let parts = self
+            .parse_arguments()
+            .and_then(|args| Ok((args, self.parse_block()?)));
+        let (args, body) = match parts {
+            Ok(parts) => parts,
+            Err(err) => return Some(Err(miette!(err))),
+        };
+        Some(Ok(S::FunDef {
             name: Box::new(S::Atom(name)),
             args,
             body: Box::new(S::Block(body)),
-        }));
+        }))
     }
 
     fn eat_token(&mut self) -> Option<Result<Token, Error>> {
-        self.lexer.next().map(|res| res.map_err(|err| err))
+        let token = self.lexer.next();
+        match &token {
+            Some(Ok(token)) if token.kind != TokenKind::NewLine => {
+                let span = token.span();
+                self.statement_span = Some(match self.statement_span.take() {
+                    Some(statement) => statement.start..span.end,
+                    None => span,
+                });
+            }
+            Some(Err(err)) if is_unterminated(err) => self.incomplete = true,
+            _ => {}
+        }
+        token
     }
 
     fn peek_token(&mut self) -> Option<&Result<Token, Error>> {
+        if let Some(Err(err)) = self.lexer.peek() {
+            if is_unterminated(err) {
+                self.incomplete = true;
+            }
+        }
         self.lexer.peek()
     }
 
@@ -552,6 +1051,7 @@ impl<'a> Parser<'a> {
                     row: token.row,
                     column: token.column,
                     index: token.index,
+                    start: token.start,
                 })
             }
 
@@ -560,6 +1060,7 @@ impl<'a> Parser<'a> {
                 row: token.row,
                 column: token.column,
                 index: token.index,
+                start: token.start,
             }),
 
             Some(Err(_err)) => Err(Token {
@@ -567,18 +1068,29 @@ impl<'a> Parser<'a> {
                 row: 0,
                 column: 0,
                 index: 0,
+                start: 0,
             }),
 
-            None => Err(Token {
-                kind: TokenKind::EOF,
-                row: 0,
-                column: 0,
-                index: 0,
-            }),
+            None => {
+                self.incomplete = true;
+                Err(Token {
+                    kind: TokenKind::EOF,
+                    row: 0,
+                    column: 0,
+                    index: 0,
+                    start: 0,
+                })
+            }
         }
     }
 }
 
+/// Whether a lexer error means a literal was still open at the end of input.
+fn is_unterminated(err: &Error) -> bool {
+    err.code()
+        .is_some_and(|code| code.to_string() == UNTERMINATED_STRING)
+}
+
 fn get_prefix_binding_power(token: &Token) -> ((), u8) {
     match token {
         Token {
@@ -656,22 +1168,63 @@ fn get_postfix_binding_power(token: &Token) -> Option<(u8, ())> {
         Token {
             kind: TokenKind::Bang,
             ..
-        }
-        | Token {
-            kind: TokenKind::LeftBracket,
-            ..
         } => Some((6, ())),
-        Token {
-            kind: TokenKind::LeftParen,
-            ..
-        } => Some((8, ())),
         _ => None,
     }
 }
 
 fn match_token_kind(token: &TokenKind, expected: &TokenKind) -> bool {
-    match (token, expected) {
-        (TokenKind::Identifier(_), TokenKind::Identifier(_)) => true,
-        _ => false,
+    matches!(
+        (token, expected),
+        (TokenKind::Identifier(_), TokenKind::Identifier(_))
+            | (TokenKind::String(_), TokenKind::String(_))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The S-expressions of the statements in `source`.
+    fn parse(source: &str) -> Vec<String> {
+        let mut parser = Parser::new(source);
+        std::iter::from_fn(|| parser.parse_statement())
+            .map(|statement| statement.expect("parses").to_string())
+            .collect()
+    }
+
+    #[test]
+    fn let_binds_maps_with_identifier_keys() {
+        assert_eq!(parse("let m = {a: 1}"), ["(let m (map (entry a 1)))"]);
+        assert_eq!(
+            parse(r#"let m = {a : 1, "b": 2}"#),
+            [r#"(let m (map (entry a 1) (entry "b" 2)))"#]
+        );
+        assert_eq!(parse("let m = {\n    a: 1\n}"), ["(let m (map (entry a 1)))"]);
+        assert_eq!(parse("let m = {}"), ["(let m (map))"]);
+    }
+
+    #[test]
+    fn let_binds_blocks_starting_with_an_identifier() {
+        assert_eq!(parse("let b = { a + 1 }"), ["(let b (block (+ a 1)))"]);
+        assert_eq!(parse("let b = {\n    a\n}"), ["(let b (block a))"]);
+    }
+
+    #[test]
+    fn lists_maps_members_and_indexes_nest() {
+        assert_eq!(parse("[1, [2], []]"), ["(list 1 (list 2) (list))"]);
+        assert_eq!(parse("[\n    1,\n    2,\n]"), ["(list 1 2)"]);
+        assert_eq!(parse("print({a: [1]})"), ["(call print (map (entry a (list 1))))"]);
+        assert_eq!(parse("a.b.c"), ["(. (. a b) c)"]);
+        assert_eq!(parse("a[0][1] + 1"), ["(+ (index (index a 0) 1) 1)"]);
+        assert_eq!(parse("a.f(1)[2]"), ["(index (call (. a f) 1) 2)"]);
+    }
+
+    #[test]
+    fn malformed_collections_are_errors() {
+        let error = |source: &str| Parser::new(source).parse_statement().unwrap().unwrap_err();
+        assert!(error("[1 2]").to_string().contains("Expected ',' or ']'"));
+        assert!(error("print({a 1})").to_string().contains("Expected Colon after map key"));
+        assert!(error("a.1").to_string().contains("Expected Identifier after '.'"));
     }
 }
@@ -0,0 +1,173 @@
+use std::ops::Range;
+
+use crate::{
+    lexer::{Token, TokenKind},
+    Op, S,
+};
+
+/// Renders syntax trees as indented trees or canonical S-expressions, e.g.
+/// `(+ 1 (* 2 3))`, optionally annotated with the byte range each node
+/// covers in the source.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Printer {
+    spans: bool,
+}
+
+impl Printer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends `@start..end` to every node.
+    pub fn spans(mut self, spans: bool) -> Self {
+        self.spans = spans;
+        self
+    }
+
+    /// Renders `node` on one line as an S-expression.
+    pub fn sexpr(&self, node: &S) -> String {
+        let mut out = String::new();
+        self.write_sexpr(&Node::from(node), &mut out);
+        out
+    }
+
+    /// Renders `node` as a tree with one node per line.
+    pub fn tree(&self, node: &S) -> String {
+        let node = Node::from(node);
+        let mut out = self.label(&node);
+        out.push('\n');
+        self.write_children(&node, "", &mut out);
+        out
+    }
+
+    fn write_sexpr(&self, node: &Node, out: &mut String) {
+        match &node.children {
+            None => out.push_str(&self.label(node)),
+            Some(children) => {
+                out.push('(');
+                out.push_str(&node.label);
+                for child in children {
+                    out.push(' ');
+                    self.write_sexpr(child, out);
+                }
+                out.push(')');
+                if let Some(span) = node.span.as_ref().filter(|_| self.spans) {
+                    out.push_str(&format!("@{}..{}", span.start, span.end));
+                }
+            }
+        }
+    }
+
+    fn write_children(&self, node: &Node, indent: &str, out: &mut String) {
+        let children = node.children.as_deref().unwrap_or_default();
+        for (i, child) in children.iter().enumerate() {
+            let last = i + 1 == children.len();
+            out.push_str(indent);
+            out.push_str(if last { "└── " } else { "├── " });
+            out.push_str(&self.label(child));
+            out.push('\n');
+            let indent = format!("{}{}", indent, if last { "    " } else { "│   " });
+            self.write_children(child, &indent, out);
+        }
+    }
+
+    fn label(&self, node: &Node) -> String {
+        match node.span.as_ref().filter(|_| self.spans) {
+            Some(span) => format!("{}@{}..{}", node.label, span.start, span.end),
+            None => node.label.clone(),
+        }
+    }
+}
+
+/// A syntax tree node reduced to what the printer shows: atoms have no
+/// children, every other node prints as a parenthesised list.
+struct Node {
+    label: String,
+    span: Option<Range<usize>>,
+    children: Option<Vec<Node>>,
+}
+
+impl Node {
+    fn list(label: &str, children: Vec<Node>) -> Self {
+        let span = children
+            .iter()
+            .filter_map(|child| child.span.clone())
+            .reduce(|a, b| a.start.min(b.start)..a.end.max(b.end));
+        Self {
+            label: label.to_string(),
+            span,
+            children: Some(children),
+        }
+    }
+
+    /// A list headed by `token`, whose span also covers the token.
+    fn headed(token: &Token, label: &str, children: Vec<Node>) -> Self {
+        let mut node = Self::list(label, children);
+        let head = token.span();
+        node.span = Some(match node.span {
+            Some(span) => span.start.min(head.start)..span.end.max(head.end),
+            None => head,
+        });
+        node
+    }
+}
+
+impl From<&S> for Node {
+    fn from(node: &S) -> Self {
+        let nodes = |items: &[S]| items.iter().map(Node::from).collect::<Vec<_>>();
+        match node {
+            S::Atom(token) => Node {
+                label: token.kind.lexeme(),
+                span: Some(token.span()),
+                children: None,
+            },
+            S::Cons(token, args) => {
+                let label = match &token.kind {
+                    TokenKind::LeftBracket => "index".to_string(),
+                    kind => kind.lexeme(),
+                };
+                Node::headed(token, &label, nodes(args))
+            }
+            S::BinaryExpr { op, lhs, rhs } => {
+                let op = match op {
+                    Op::Plus => "+",
+                    Op::Minus => "-",
+                    Op::Star => "*",
+                    Op::Slash => "/",
+                };
+                Node::list(op, vec![Node::from(lhs.as_ref()), Node::from(rhs.as_ref())])
+            }
+            S::IfExpr {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                let mut children = vec![Node::from(cond.as_ref()), Node::from(then_branch.as_ref())];
+                children.extend(else_branch.as_deref().map(Node::from));
+                Node::list("if", children)
+            }
+            S::Block(statements) => Node::list("block", nodes(statements)),
+            S::FunDef { name, args, body } => Node::list(
+                "func",
+                vec![
+                    Node::from(name.as_ref()),
+                    Node::list("params", nodes(args)),
+                    Node::from(body.as_ref()),
+                ],
+            ),
+            S::FunCall { name, args } => {
+                let mut children = vec![Node::from(name.as_ref())];
+                children.extend(nodes(args));
+                Node::list("call", children)
+            }
+            S::List(items) => Node::list("list", nodes(items)),
+            S::Map(entries) => Node::list(
+                "map",
+                entries
+                    .iter()
+                    .map(|(key, value)| Node::list("entry", vec![Node::from(key), Node::from(value)]))
+                    .collect(),
+            ),
+        }
+    }
+}
@@ -0,0 +1,356 @@
+//! An instrumenting profiler built on the interpreter's [`Hook`].
+//!
+//! Every user-defined function call is timed, giving call counts and
+//! inclusive and exclusive times per function, and every statement counts as
+//! a hit on its line, in the program's file or the module it was imported
+//! from. Time spent in native functions is part of the function that called
+//! them.
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use miette::Result;
+
+use crate::{
+    interpreter::{Hook, Interpreter},
+    modules, S,
+};
+
+/// The name of the frame for top-level code.
+const SCRIPT: &str = "<script>";
+
+/// How many of the most executed lines the report lists.
+const HOT_LINES: usize = 10;
+
+/// Timings of one function, summed over its calls.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FunctionProfile {
+    pub calls: u64,
+    /// Time from entering to leaving the function, counted once for
+    /// recursive calls.
+    pub inclusive: Duration,
+    /// Time spent in the function's own statements, not in the functions it
+    /// calls.
+    pub exclusive: Duration,
+}
+
+#[derive(Debug)]
+struct Call {
+    function: String,
+    started: Instant,
+    /// Time spent in the functions this call made.
+    children: Duration,
+}
+
+/// A line of the program's file, or of a module it imported.
+pub type Line = (Option<PathBuf>, usize);
+
+#[derive(Debug)]
+struct Profile {
+    functions: HashMap<String, FunctionProfile>,
+    lines: BTreeMap<Line, u64>,
+    /// The source of every file that code ran from, to show the hot lines.
+    sources: HashMap<Option<PathBuf>, Rc<str>>,
+    /// Exclusive time per call stack, keyed by names joined with `;`.
+    stacks: HashMap<String, Duration>,
+    calls: Vec<Call>,
+    elapsed: Option<Duration>,
+}
+
+/// Records where a program spends its time. Clones share the same
+/// recordings, so one can be handed to the interpreter and the other kept for
+/// the report.
+#[derive(Debug, Clone)]
+pub struct Profiler(Rc<RefCell<Profile>>);
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Profiler {
+    /// Starts timing the top-level code of a program.
+    pub fn new() -> Self {
+        Self(Rc::new(RefCell::new(Profile {
+            functions: HashMap::new(),
+            lines: BTreeMap::new(),
+            sources: HashMap::new(),
+            stacks: HashMap::new(),
+            calls: vec![Call {
+                function: SCRIPT.to_string(),
+                started: Instant::now(),
+                children: Duration::ZERO,
+            }],
+            elapsed: None,
+        })))
+    }
+
+    /// Stops timing the top-level code. Called by the reports; later calls
+    /// have no effect.
+    pub fn finish(&self) {
+        let mut profile = self.0.borrow_mut();
+        if profile.elapsed.is_some() {
+            return;
+        }
+        // Frames of functions that failed are already closed by `exit`, so
+        // only the script frame is left.
+        while !profile.calls.is_empty() {
+            profile.leave();
+        }
+        let elapsed = profile.functions.get(SCRIPT).map_or(Duration::ZERO, |f| f.inclusive);
+        profile.elapsed = Some(elapsed);
+    }
+
+    /// The timings of every function, the top-level code included as `<script>`.
+    pub fn functions(&self) -> Vec<(String, FunctionProfile)> {
+        self.finish();
+        let mut functions: Vec<_> = self
+            .0
+            .borrow()
+            .functions
+            .iter()
+            .map(|(name, profile)| (name.clone(), *profile))
+            .collect();
+        functions.sort_by(|a, b| b.1.exclusive.cmp(&a.1.exclusive).then(a.0.cmp(&b.0)));
+        functions
+    }
+
+    /// How many statements ran on each line.
+    pub fn lines(&self) -> BTreeMap<Line, u64> {
+        self.0.borrow().lines.clone()
+    }
+
+    /// The call stacks in the folded format read by flame graph tools: one
+    /// `<script>;caller;callee microseconds` line per stack.
+    pub fn folded(&self) -> String {
+        self.finish();
+        let profile = self.0.borrow();
+        let mut stacks: Vec<_> = profile.stacks.iter().collect();
+        stacks.sort();
+        stacks
+            .into_iter()
+            .map(|(stack, time)| format!("{} {}\n", stack, time.as_micros()))
+            .collect()
+    }
+
+    /// A table of the functions by exclusive time, followed by the most
+    /// executed lines, prefixed with their file when code ran from several.
+    pub fn report(&self) -> String {
+        let functions = self.functions();
+        let total = self.0.borrow().elapsed.unwrap_or_default();
+        let width = functions
+            .iter()
+            .map(|(name, _)| name.len())
+            .chain(["Function".len()])
+            .max()
+            .unwrap_or(0);
+
+        let mut report = String::new();
+        let _ = writeln!(
+            report,
+            "{:<width$}  {:>8}  {:>12}  {:>12}  {:>6}",
+            "Function", "Calls", "Inclusive", "Exclusive", "%"
+        );
+        for (name, profile) in &functions {
+            let _ = writeln!(
+                report,
+                "{:<width$}  {:>8}  {:>12}  {:>12}  {:>5.1}%",
+                name,
+                profile.calls,
+                millis(profile.inclusive),
+                millis(profile.exclusive),
+                percent(profile.exclusive, total),
+            );
+        }
+
+        let mut lines: Vec<_> = self.lines().into_iter().collect();
+        lines.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        if !lines.is_empty() {
+            let profile = self.0.borrow();
+            let name = |(file, row): &Line| match file {
+                Some(file) if profile.sources.len() > 1 => {
+                    format!("{}:{}", modules::display(file), row)
+                }
+                _ => row.to_string(),
+            };
+            let lines: Vec<_> = lines.into_iter().take(HOT_LINES).collect();
+            let width = lines.iter().map(|(line, _)| name(line).len()).chain([6]).max().unwrap_or(6);
+            let _ = writeln!(report, "\n{:>width$}  {:>10}  Source", "Line", "Hits");
+            for (line, hits) in &lines {
+                let text = profile
+                    .sources
+                    .get(&line.0)
+                    .and_then(|source| source.lines().nth(line.1 - 1))
+                    .map_or("", str::trim);
+                let _ = writeln!(report, "{:>width$}  {:>10}  {}", name(line), hits, text);
+            }
+        }
+        report
+    }
+}
+
+impl Profile {
+    fn enter(&mut self, function: &str) {
+        self.calls.push(Call {
+            function: function.to_string(),
+            started: Instant::now(),
+            children: Duration::ZERO,
+        });
+    }
+
+    fn leave(&mut self) {
+        let stack = self
+            .calls
+            .iter()
+            .map(|call| call.function.as_str())
+            .collect::<Vec<_>>()
+            .join(";");
+        let Some(call) = self.calls.pop() else {
+            return;
+        };
+        let elapsed = call.started.elapsed();
+        let exclusive = elapsed.saturating_sub(call.children);
+        // A recursive call's time is already inside the outermost call's.
+        let recursive = self.calls.iter().any(|outer| outer.function == call.function);
+
+        let function = self.functions.entry(call.function).or_default();
+        function.calls += 1;
+        function.exclusive += exclusive;
+        if !recursive {
+            function.inclusive += elapsed;
+        }
+        *self.stacks.entry(stack).or_default() += exclusive;
+        if let Some(caller) = self.calls.last_mut() {
+            caller.children += elapsed;
+        }
+    }
+}
+
+impl Hook for Profiler {
+    fn statement(&mut self, interpreter: &mut Interpreter<'_>, statement: &S) -> Result<()> {
+        if let Some(token) = statement.first_token() {
+            let file = interpreter.current_file().map(Path::to_path_buf);
+            let mut profile = self.0.borrow_mut();
+            if !profile.sources.contains_key(&file) {
+                let source = interpreter.current_source().unwrap_or_default();
+                profile.sources.insert(file.clone(), Rc::from(source));
+            }
+            *profile.lines.entry((file, token.row)).or_default() += 1;
+        }
+        Ok(())
+    }
+
+    fn enter(&mut self, _interpreter: &mut Interpreter<'_>, name: &str) {
+        self.0.borrow_mut().enter(name);
+    }
+
+    fn exit(&mut self, _interpreter: &mut Interpreter<'_>, _name: &str) {
+        self.0.borrow_mut().leave();
+    }
+}
+
+fn millis(duration: Duration) -> String {
+    format!("{:.3}ms", duration.as_secs_f64() * 1000.0)
+}
+
+fn percent(part: Duration, total: Duration) -> f64 {
+    if total.is_zero() {
+        return 0.0;
+    }
+    part.as_secs_f64() / total.as_secs_f64() * 100.0
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{output::OutputBuffer, Parser};
+
+    use super::*;
+
+    fn profile(source: &str) -> Profiler {
+        let mut interpreter = Interpreter::new(Parser::new(source));
+        interpreter.set_stdout(Box::new(OutputBuffer::new()));
+        let profiler = Profiler::new();
+        interpreter.set_hook(profiler.clone());
+        interpreter.run().unwrap();
+        profiler
+    }
+
+    const PROGRAM: &str = "func leaf(n) {
+    return n + 1
+}
+func branch(n) {
+    return leaf(n) + leaf(n)
+}
+let a = branch(1)
+let b = branch(2)
+print(leaf(a + b))";
+
+    #[test]
+    fn calls_are_counted_per_function() {
+        let calls: HashMap<String, u64> = profile(PROGRAM)
+            .functions()
+            .into_iter()
+            .map(|(name, profile)| (name, profile.calls))
+            .collect();
+        let expected = [("<script>", 1), ("branch", 2), ("leaf", 5)];
+        assert_eq!(calls, expected.map(|(name, calls)| (name.to_string(), calls)).into());
+    }
+
+    #[test]
+    fn folded_stacks_have_a_line_per_call_path() {
+        let folded = profile(PROGRAM).folded();
+        let stacks: Vec<&str> = folded
+            .lines()
+            .map(|line| {
+                let (stack, micros) = line.rsplit_once(' ').unwrap();
+                assert!(micros.parse::<u128>().is_ok(), "{}", line);
+                stack
+            })
+            .collect();
+        assert_eq!(
+            stacks,
+            ["<script>", "<script>;branch", "<script>;branch;leaf", "<script>;leaf"]
+        );
+    }
+
+    #[test]
+    fn recursive_calls_are_nested_in_the_stacks() {
+        let profiler = profile(
+            "func down(n) {
+    if n > 0 {
+        down(n - 1)
+    }
+}
+down(2)",
+        );
+        let folded = profiler.folded();
+        let stacks: Vec<&str> = folded
+            .lines()
+            .filter_map(|line| line.rsplit_once(' '))
+            .map(|(stack, _)| stack)
+            .collect();
+        assert_eq!(
+            stacks,
+            ["<script>", "<script>;down", "<script>;down;down", "<script>;down;down;down"]
+        );
+        let down = profiler.functions().into_iter().find(|(name, _)| name == "down").unwrap().1;
+        assert_eq!(down.calls, 3);
+        assert!(down.inclusive >= down.exclusive);
+    }
+
+    #[test]
+    fn statements_count_as_hits_on_their_line() {
+        let lines = profile(PROGRAM).lines();
+        assert_eq!(lines[&(None, 2)], 5);
+        assert_eq!(lines[&(None, 5)], 2);
+        assert_eq!(lines[&(None, 7)], 1);
+        assert_eq!(lines[&(None, 9)], 1);
+    }
+}
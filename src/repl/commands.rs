@@ -0,0 +1,162 @@
+use std::{fs, path::PathBuf, time::Instant};
+
+use colored::*;
+use miette::{miette, IntoDiagnostic, Report, Result, WrapErr};
+
+use super::Session;
+use crate::{
+    highlight::Highlighter, interpreter::Value, lexer::KEYWORDS, pretty::Printer, Lexer, Parser,
+    ScriptError,
+};
+
+/// Colon commands with the argument they take and a short description.
+pub const COMMANDS: &[(&str, &str, &str)] = &[
+    ("help", "", "Show this help message"),
+    ("load", "<file>", "Run a script in this session"),
+    ("reload", "", "Run the last loaded script again"),
+    ("tokens", "<code>", "Show the tokens of some code"),
+    ("ast", "<code>", "Show the syntax tree of some code"),
+    ("type", "<expr>", "Show the type of an expression's value"),
+    ("time", "<expr>", "Evaluate an expression and show how long it took"),
+    ("doc", "<name>", "Describe a variable, function or module"),
+    ("env", "", "Show all defined variables"),
+    ("save", "<file>", "Write the inputs run so far to a file"),
+    ("reset", "", "Forget all definitions and results"),
+    ("clear", "", "Clear the screen"),
+    ("exit", "", "Exit the interpreter"),
+];
+
+/// What the REPL should do after running a command.
+pub enum Flow {
+    Continue,
+    Exit,
+}
+
+/// Runs a `:command`, with `input` being the whole line including the colon.
+pub fn run(session: &mut Session, input: &str) -> Result<Flow> {
+    let input = input.strip_prefix(':').unwrap_or(input);
+    let (name, arg) = match input.split_once(char::is_whitespace) {
+        Some((name, arg)) => (name, arg.trim()),
+        None => (input, ""),
+    };
+
+    match name {
+        "help" => super::print_help(),
+        "load" => load(session, PathBuf::from(required(name, arg)?))?,
+        "reload" => {
+            let path = session
+                .loaded
+                .clone()
+                .ok_or_else(|| miette!("No script has been loaded yet"))?;
+            load(session, path)?;
+        }
+        "tokens" => {
+            for token in Lexer::new(required(name, arg)?) {
+                let token = token.map_err(|e| Report::new(ScriptError::Parse(e)))?;
+                println!("{}", token);
+            }
+        }
+        "ast" => {
+            let mut parser = Parser::new(required(name, arg)?);
+            while let Some(statement) = parser.parse_statement() {
+                let statement = statement.map_err(|e| Report::new(ScriptError::Parse(e)))?;
+                print!("{}", Printer::new().tree(&statement));
+            }
+        }
+        "type" => {
+            let value = session.interpreter.eval(required(name, arg)?)?;
+            println!("{}", value.type_name().bright_yellow());
+        }
+        "time" => {
+            let start = Instant::now();
+            let value = session.interpreter.eval(required(name, arg)?)?;
+            let elapsed = start.elapsed();
+            println!("{}", value);
+            println!("{}", format!("took {:?}", elapsed).dimmed());
+        }
+        "doc" => doc(session, required(name, arg)?)?,
+        "env" => super::print_environment(&session.interpreter.variables),
+        "save" => {
+            let path = required(name, arg)?;
+            let mut source = session.transcript.join("\n");
+            source.push('\n');
+            fs::write(path, source)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("Failed to write file: {}", path))?;
+            println!("{} {}", "Saved session to".bright_green(), path);
+        }
+        "reset" => {
+            // Keep the loaded script so `:reload` can start the session over.
+            let loaded = session.loaded.take();
+            *session = Session::new();
+            session.loaded = loaded;
+            println!("{}", "Session reset".bright_green());
+        }
+        "clear" => {
+            print!("{esc}[2J{esc}[1;1H", esc = 27 as char);
+            super::print_logo();
+        }
+        "exit" | "quit" => return Ok(Flow::Exit),
+        _ => {
+            return Err(miette!(
+                "Unknown command ':{}', type :help to list the commands",
+                name
+            ))
+        }
+    }
+    Ok(Flow::Continue)
+}
+
+fn required<'a>(command: &str, arg: &'a str) -> Result<&'a str> {
+    if arg.is_empty() {
+        let usage = COMMANDS
+            .iter()
+            .find(|(name, ..)| *name == command)
+            .map_or("", |(_, usage, _)| usage);
+        return Err(miette!("Usage: :{} {}", command, usage));
+    }
+    Ok(arg)
+}
+
+/// Runs the script at `path` as if typed in, so that files written by
+/// `:save` can use the `_` and `_1`, `_2`, … results again, with its imports
+/// resolved relative to the script.
+fn load(session: &mut Session, path: PathBuf) -> Result<()> {
+    let source = fs::read_to_string(&path)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Failed to read file: {}", path.display()))?;
+    session.loaded = Some(path.clone());
+    session.interpreter.set_file(&path);
+    session.evaluate(&source)?;
+    println!("{} {}", "Loaded".bright_green(), path.display());
+    Ok(())
+}
+
+fn doc(session: &Session, name: &str) -> Result<()> {
+    if KEYWORDS.contains(&name) {
+        println!("{} is a keyword", name.bright_blue());
+        return Ok(());
+    }
+
+    let value = session
+        .interpreter
+        .lookup(name)
+        .ok_or_else(|| miette!("Undefined variable: {}", name))?;
+    match &value {
+        Value::Function { name, params, .. } => {
+            let signature = format!("func {}({})", name, params.join(", "));
+            println!("{}", Highlighter::global().highlight(&signature));
+        }
+        Value::NativeFunction(native) => {
+            println!("{} {}", native.name.bright_blue(), value.describe().dimmed());
+        }
+        Value::Map(members) if session.interpreter.builtin_names().any(|n| n == name) => {
+            println!("{} {}", "module".dimmed(), name.bright_blue());
+            for (member, value) in members {
+                println!("  {} {}", member.bright_blue(), value.describe().dimmed());
+            }
+        }
+        other => println!("{} = {}", other.describe().dimmed(), other),
+    }
+    Ok(())
+}
@@ -0,0 +1,104 @@
+use std::borrow::Cow;
+
+use rustyline::{
+    completion::{Completer, FilenameCompleter, Pair},
+    highlight::Highlighter,
+    hint::Hinter,
+    validate::Validator,
+    Context, Helper,
+};
+
+use super::commands::COMMANDS;
+use crate::{highlight, lexer::KEYWORDS};
+
+/// Commands whose argument is a path, completed from the file system.
+const PATH_COMMANDS: &[&str] = &[":load ", ":save "];
+
+/// Line editor helper providing completion of commands, keywords, builtins
+/// and the names currently bound in the REPL session, and highlighting of the
+/// input as it is typed.
+#[derive(Default)]
+pub struct ReplHelper {
+    names: Vec<String>,
+    files: FilenameCompleter,
+}
+
+impl ReplHelper {
+    /// Replaces the session names offered for completion.
+    pub fn set_names(&mut self, names: impl IntoIterator<Item = String>) {
+        self.names = names.into_iter().collect();
+        self.names.sort();
+        self.names.dedup();
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        if PATH_COMMANDS.iter().any(|command| line.starts_with(command)) {
+            return self.files.complete(line, pos, ctx);
+        }
+        let command = line[..pos].strip_prefix(':');
+        if let Some(prefix) = command.filter(|prefix| !prefix.contains(char::is_whitespace)) {
+            let candidates = COMMANDS
+                .iter()
+                .filter(|(name, ..)| name.starts_with(prefix))
+                .map(|(name, ..)| Pair {
+                    display: name.to_string(),
+                    replacement: name.to_string(),
+                })
+                .collect();
+            return Ok((1, candidates));
+        }
+
+        let start = line[..pos]
+            .rfind(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .map_or(0, |i| i + 1);
+        let prefix = &line[start..pos];
+        if prefix.is_empty() {
+            return Ok((start, Vec::new()));
+        }
+
+        let mut candidates: Vec<Pair> = KEYWORDS
+            .iter()
+            .map(|word| word.to_string())
+            .chain(self.names.iter().cloned())
+            .filter(|word| word.starts_with(prefix))
+            .map(|word| Pair {
+                display: word.clone(),
+                replacement: word,
+            })
+            .collect();
+        candidates.sort_by(|a, b| a.display.cmp(&b.display));
+        candidates.dedup_by(|a, b| a.display == b.display);
+
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        if !colored::control::SHOULD_COLORIZE.should_colorize() {
+            return Cow::Borrowed(line);
+        }
+        Cow::Owned(highlight::Highlighter::global().highlight(line))
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize, _forced: bool) -> bool {
+        colored::control::SHOULD_COLORIZE.should_colorize()
+    }
+}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
@@ -0,0 +1,249 @@
+use colored::*;
+use rustyline::{error::ReadlineError, history::FileHistory, Config, Editor};
+use std::{collections::HashMap, io::Write, path::PathBuf};
+
+use miette::{Report, Result};
+
+use crate::{interpreter::Value, Interpreter, Parser, ScriptError};
+
+mod commands;
+mod helper;
+pub use helper::ReplHelper;
+
+use commands::Flow;
+
+/// State carried from one input to the next in a REPL session.
+struct Session {
+    /// One interpreter for the whole session, so definitions carry over between inputs.
+    interpreter: Interpreter<'static>,
+    /// How many results have been saved as `_1`, `_2`, …
+    results: usize,
+    /// Source of every input that ran successfully, written out by `:save`.
+    transcript: Vec<String>,
+    /// The script last run by `:load`, run again by `:reload`.
+    loaded: Option<PathBuf>,
+}
+
+impl Session {
+    fn new() -> Self {
+        Self {
+            interpreter: Interpreter::default(),
+            results: 0,
+            transcript: Vec::new(),
+            loaded: None,
+        }
+    }
+
+    /// Runs each statement of `input`, echoing the value of expression
+    /// statements and saving it as `_` and `_1`, `_2`, … in order.
+    fn evaluate(&mut self, input: &str) -> Result<()> {
+        let mut parser = Parser::new(input);
+        self.interpreter.set_source(input);
+        while let Some(statement) = parser.parse_statement() {
+            let statement = statement.map_err(|e| Report::new(ScriptError::Parse(e)))?;
+            let value = self.interpreter.execute(&statement)?;
+            if !statement.is_expression() || value == Value::Nil {
+                continue;
+            }
+
+            self.results += 1;
+            let name = format!("_{}", self.results);
+            println!("{} {}", format!("{} =", name).dimmed(), value);
+            self.interpreter.variables.insert(name, value.clone());
+            self.interpreter.variables.insert("_".to_string(), value);
+        }
+        self.transcript.push(input.to_string());
+        Ok(())
+    }
+
+    /// Prints an error from an input or command to the session's stderr.
+    fn report(&self, error: &Report) {
+        let _ = writeln!(self.interpreter.stderr(), "{} {}", "Error:".bright_red(), error);
+    }
+}
+
+pub fn repl() {
+    print_logo();
+    println!("Type {} for a list of commands.", ":help".bright_blue());
+
+    let config = Config::builder().auto_add_history(true).build();
+    let mut editor: Editor<ReplHelper, FileHistory> = match Editor::with_config(config) {
+        Ok(editor) => editor,
+        Err(err) => {
+            eprintln!("{} {}", "Error:".bright_red(), err);
+            return;
+        }
+    };
+    editor.set_helper(Some(ReplHelper::default()));
+
+    let history = history_path();
+    if let Some(history) = &history {
+        // A missing history file just means this is the first session.
+        let _ = editor.load_history(history);
+    }
+
+    let mut session = Session::new();
+    // Lines of a statement that is still being typed.
+    let mut pending = String::new();
+
+    loop {
+        if let Some(helper) = editor.helper_mut() {
+            let interpreter = &session.interpreter;
+            helper.set_names(
+                interpreter
+                    .variables
+                    .keys()
+                    .chain(interpreter.builtin_names())
+                    .cloned(),
+            );
+        }
+
+        let prompt = if pending.is_empty() { ">>" } else { ".." };
+        let line = match editor.readline(&format!("{} ", prompt.bright_blue())) {
+            Ok(line) => line,
+            // Ctrl-C abandons the current input, Ctrl-D ends the session.
+            Err(ReadlineError::Interrupted) => {
+                pending.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(err) => {
+                eprintln!("{} {}", "Error:".bright_red(), err);
+                break;
+            }
+        };
+        if !pending.is_empty() {
+            pending.push('\n');
+            pending.push_str(&line);
+            if is_incomplete(&pending) {
+                continue;
+            }
+        }
+        let input = if pending.is_empty() {
+            line.trim().to_string()
+        } else {
+            std::mem::take(&mut pending)
+        };
+
+        match input.as_str() {
+            "" => continue,
+            _ if input.starts_with(':') => match commands::run(&mut session, &input) {
+                Ok(Flow::Continue) => {}
+                Ok(Flow::Exit) => break,
+                Err(e) => session.report(&e),
+            },
+            _ if is_incomplete(&input) => pending = input,
+            _ => {
+                if let Err(e) = session.evaluate(&input) {
+                    session.report(&e);
+                }
+            }
+        }
+    }
+
+    if let Some(history) = &history {
+        if let Some(dir) = history.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        if let Err(err) = editor.save_history(history) {
+            eprintln!("{} could not save history: {}", "Warning:".bright_yellow(), err);
+        }
+    }
+
+    println!("{}", "Goodbye!".bright_green());
+}
+
+/// Whether `input` stops partway through a statement, so the REPL should
+/// read more lines before running it.
+fn is_incomplete(input: &str) -> bool {
+    let mut parser = Parser::new(input);
+    while let Some(statement) = parser.parse_statement() {
+        if statement.is_err() {
+            return parser.is_incomplete();
+        }
+    }
+    false
+}
+
+/// Where REPL history is kept between sessions, e.g. `~/.local/share/merc/history`.
+fn history_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("merc").join("history"))
+}
+
+fn print_environment(variables: &HashMap<String, Value>) {
+    if variables.is_empty() {
+        println!("{}", "No variables defined".bright_yellow());
+        return;
+    }
+
+    println!("{}", "Current environment:".bright_green());
+    let mut variables: Vec<_> = variables.iter().collect();
+    variables.sort_by(|a, b| a.0.cmp(b.0));
+    for (name, value) in variables {
+        println!(
+            "  {} = {}",
+            name.bright_blue(),
+            format!("{}", value).bright_yellow()
+        );
+    }
+}
+
+fn print_help() {
+    println!("{}", "Available commands:".bright_green());
+    for (name, arg, description) in commands::COMMANDS {
+        let usage = format!(":{} {}", name, arg);
+        println!("  {:<16} - {}", usage.trim_end(), description);
+    }
+    println!("\nLanguage features:");
+    println!("  let x = <expression>  - Define a variable");
+    println!("  func name(args) {{ }}  - Define a function");
+    println!("  if <cond> {{ }} else {{ }}  - Conditional");
+    println!("  while <cond> {{ }}  - Loop");
+    println!("  1 + 2 * 3  - Arithmetic; results are saved as _ and _1, _2, ...");
+    println!("  \"hello\" + \" world\"  - String concatenation");
+    println!("  true && false  - Boolean operations");
+}
+
+fn print_logo() {
+    println!(
+        "{}",
+        r#"
+ ███▄ ▄███▓▓█████  ██▀███   ▄████▄  
+▓██▒▀█▀ ██▒▓█   ▀ ▓██ ▒ ██▒▒██▀ ▀█  
+▓██    ▓██░▒███   ▓██ ░▄█ ▒▒▓█    ▄ 
+▒██    ▒██ ▒▓█  ▄ ▒██▀▀█▄  ▒▓▓▄ ▄██▒
+▒██▒   ░██▒░▒████▒░██▓ ▒██▒▒ ▓███▀ ░
+░ ▒░   ░  ░░░ ▒░ ░░ ▒▓ ░▒▓░░ ░▒ ▒  ░
+░  ░      ░ ░ ░  ░  ░▒ ░ ▒░  ░  ▒   
+░      ░      ░     ░░   ░ ░        
+       ░      ░  ░   ░     ░ ░      
+                           ░        
+"#
+        .bright_cyan()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::output::OutputBuffer;
+
+    use super::*;
+
+    #[test]
+    fn errors_are_reported_on_stderr() {
+        let (stdout, stderr) = (OutputBuffer::new(), OutputBuffer::new());
+        let mut session = Session::new();
+        session.interpreter.set_stdout(Box::new(stdout.clone()));
+        session.interpreter.set_stderr(Box::new(stderr.clone()));
+
+        for input in ["print(missing)", "let = 1"] {
+            let error = session.evaluate(input).expect_err("fails");
+            session.report(&error);
+        }
+        assert_eq!(stdout.contents(), "");
+        let reported = stderr.contents();
+        assert!(reported.contains("Undefined variable: missing"), "{}", reported);
+        assert!(reported.contains("Expected Identifier, got Equal"), "{}", reported);
+        assert_eq!(reported.lines().count(), 2, "{}", reported);
+    }
+}
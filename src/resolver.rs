@@ -0,0 +1,295 @@
+use std::collections::{HashMap, HashSet};
+
+use miette::{miette, LabeledSpan, Report, Severity};
+
+use crate::{lexer::Token, TokenKind, S};
+
+/// Statically checks a parsed program for names that are used before they
+/// are defined and for calls with the wrong number of arguments, mirroring
+/// the interpreter's scoping rules: top-level statements see the globals
+/// defined above them, while function bodies see their own locals plus every
+/// global and builtin.
+pub struct Resolver<'a> {
+    source: &'a str,
+    builtins: HashSet<String>,
+    /// Every global the program defines, with the arity of those defined by `func`.
+    hoisted: HashMap<String, Option<usize>>,
+    defined: HashSet<String>,
+    locals: Option<HashSet<String>>,
+    diagnostics: Vec<Report>,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(source: &'a str, builtins: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            source,
+            builtins: builtins.into_iter().collect(),
+            hoisted: HashMap::new(),
+            defined: HashSet::new(),
+            locals: None,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// Resolves `program` and returns one diagnostic per problem found.
+    pub fn resolve(mut self, program: &[S]) -> Vec<Report> {
+        for statement in program {
+            self.hoist(statement);
+        }
+        for statement in program {
+            self.visit(statement);
+        }
+        self.diagnostics
+    }
+
+    fn hoist(&mut self, node: &S) {
+        match node {
+            S::Cons(
+                Token {
+                    kind: TokenKind::Let,
+                    ..
+                },
+                args,
+            ) => {
+                if let Some(name) = args.first().and_then(identifier) {
+                    self.hoisted.entry(name.to_string()).or_insert(None);
+                }
+            }
+            S::FunDef { name, args, .. } => {
+                if let Some(name) = identifier(name) {
+                    self.hoisted.insert(name.to_string(), Some(args.len()));
+                }
+            }
+            S::Block(statements) => statements.iter().for_each(|s| self.hoist(s)),
+            S::IfExpr {
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.hoist(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.hoist(else_branch);
+                }
+            }
+            S::Cons(
+                Token {
+                    kind: TokenKind::While | TokenKind::Export,
+                    ..
+                },
+                args,
+            ) => args.iter().for_each(|s| self.hoist(s)),
+            S::Cons(
+                Token {
+                    kind: TokenKind::Import | TokenKind::From,
+                    ..
+                },
+                args,
+            ) => {
+                for name in args.iter().skip(1).filter_map(identifier) {
+                    self.hoisted.entry(name.to_string()).or_insert(None);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn visit(&mut self, node: &S) {
+        match node {
+            S::Atom(token) => {
+                if let TokenKind::Identifier(name) = &token.kind {
+                    if !self.is_defined(name) {
+                        self.error(token, format!("Undefined variable: {}", name), "not defined");
+                    }
+                }
+            }
+            S::Cons(token, args) => match (&token.kind, args.as_slice()) {
+                (TokenKind::Let, [name, value]) => {
+                    self.visit(value);
+                    if let Some(name) = identifier(name) {
+                        self.define(name);
+                    }
+                }
+                (TokenKind::Import | TokenKind::From, [_path, names @ ..]) => {
+                    names.iter().filter_map(identifier).for_each(|name| self.define(name))
+                }
+                (TokenKind::Dot, [object, _member]) => self.visit(object),
+                _ => args.iter().for_each(|arg| self.visit(arg)),
+            },
+            S::BinaryExpr { lhs, rhs, .. } => {
+                self.visit(lhs);
+                self.visit(rhs);
+            }
+            S::IfExpr {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                self.visit(cond);
+                self.visit(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.visit(else_branch);
+                }
+            }
+            S::Block(statements) | S::List(statements) => {
+                statements.iter().for_each(|s| self.visit(s))
+            }
+            S::Map(entries) => entries.iter().for_each(|(_, value)| self.visit(value)),
+            S::FunDef { name, args, body } => {
+                if let Some(name) = identifier(name) {
+                    self.define(name);
+                }
+                let params = args.iter().filter_map(identifier).map(str::to_string);
+                let outer = self.locals.replace(params.collect());
+                self.visit(body);
+                self.locals = outer;
+            }
+            S::FunCall { name, args } => {
+                self.visit(name);
+                if let S::Atom(token @ Token {
+                    kind: TokenKind::Identifier(callee),
+                    ..
+                }) = name.as_ref()
+                {
+                    self.check_arity(token, callee, args.len());
+                }
+                args.iter().for_each(|arg| self.visit(arg));
+            }
+        }
+    }
+
+    fn check_arity(&mut self, token: &Token, callee: &str, given: usize) {
+        let shadowed = self
+            .locals
+            .as_ref()
+            .is_some_and(|locals| locals.contains(callee));
+        if shadowed {
+            return;
+        }
+        if let Some(Some(expected)) = self.hoisted.get(callee).copied() {
+            if expected != given {
+                self.error(
+                    token,
+                    format!(
+                        "Wrong number of arguments to '{}': expected {}, got {}",
+                        callee, expected, given
+                    ),
+                    "called here",
+                );
+            }
+        }
+    }
+
+    fn is_defined(&self, name: &str) -> bool {
+        let in_scope = match &self.locals {
+            Some(locals) => locals.contains(name) || self.hoisted.contains_key(name),
+            None => self.defined.contains(name),
+        };
+        in_scope || self.builtins.contains(name)
+    }
+
+    fn define(&mut self, name: &str) {
+        match &mut self.locals {
+            Some(locals) => locals.insert(name.to_string()),
+            None => self.defined.insert(name.to_string()),
+        };
+    }
+
+    fn error(&mut self, token: &Token, message: String, label: &str) {
+        self.diagnostics.push(
+            miette!(
+                labels = vec![LabeledSpan::at(token.span(), label)],
+                severity = Severity::Error,
+                "{}",
+                message
+            )
+            .with_source_code(self.source.to_string()),
+        );
+    }
+}
+
+fn identifier(node: &S) -> Option<&str> {
+    match node {
+        S::Atom(Token {
+            kind: TokenKind::Identifier(name),
+            ..
+        }) => Some(name),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Parser;
+
+    use super::*;
+
+    /// The message of each problem found in `source` and the code it points at.
+    fn check(source: &str) -> Vec<(String, String)> {
+        let mut parser = Parser::new(source);
+        let program: Vec<S> = std::iter::from_fn(|| parser.parse_statement())
+            .map(|statement| statement.expect("parses"))
+            .collect();
+        Resolver::new(source, ["print".to_string()])
+            .resolve(&program)
+            .iter()
+            .map(|report| {
+                let label = report.labels().and_then(|mut labels| labels.next()).expect("a label");
+                let span = label.offset()..label.offset() + label.len();
+                (report.to_string(), source[span].to_string())
+            })
+            .collect()
+    }
+
+    fn problem(message: &str, code: &str) -> (String, String) {
+        (message.to_string(), code.to_string())
+    }
+
+    #[test]
+    fn undefined_names_are_reported() {
+        assert_eq!(
+            check("let a = 1\nprint(a + b)"),
+            [problem("Undefined variable: b", "b")]
+        );
+        assert_eq!(check("func f(x) { x + y }"), [problem("Undefined variable: y", "y")]);
+    }
+
+    #[test]
+    fn calls_with_the_wrong_number_of_arguments_are_reported() {
+        assert_eq!(
+            check("func add(a, b) { a + b }\nadd(1)"),
+            [problem("Wrong number of arguments to 'add': expected 2, got 1", "add")]
+        );
+        // A parameter of the same name shadows the function.
+        assert_eq!(check("func add(a, b) { a + b }\nfunc g(add) { add(1) }"), []);
+    }
+
+    #[test]
+    fn globals_are_used_only_after_their_let() {
+        assert_eq!(
+            check("print(x)\nlet x = 1\nprint(x)"),
+            [problem("Undefined variable: x", "x")]
+        );
+        // Function bodies run later, so they may use globals defined below them.
+        assert_eq!(check("func show() { print(x) }\nlet x = 1\nshow()"), []);
+    }
+
+    #[test]
+    fn functions_may_call_themselves_and_later_functions() {
+        let source = "func fact(n) { if n < 2 { 1 } else { n * fact(n - 1) } }\n\
+                      func even(n) { if n == 0 { true } else { odd(n - 1) } }\n\
+                      func odd(n) { if n == 0 { false } else { even(n - 1) } }\n\
+                      print(fact(5), even(4))";
+        assert_eq!(check(source), []);
+    }
+
+    #[test]
+    fn imported_names_are_defined() {
+        let source = "import \"lib.mc\" as lib\nfrom \"shapes.mc\" import square\n\
+                      print(lib.area(square(2)))";
+        assert_eq!(check(source), []);
+        assert_eq!(
+            check("from \"shapes.mc\" import square\nprint(circle(1))"),
+            [problem("Undefined variable: circle", "circle")]
+        );
+    }
+}
@@ -0,0 +1,178 @@
+use std::{collections::BTreeMap, fmt};
+
+use serde::{
+    de::{self, MapAccess, SeqAccess, Visitor},
+    ser::{self, SerializeMap, SerializeSeq},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use crate::interpreter::Value;
+
+/// Largest integer an `f64` represents exactly; whole numbers up to it are
+/// written without a fractional part.
+const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_991.0;
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Value::Number(n) if n.fract() == 0.0 && n.abs() <= MAX_SAFE_INTEGER => {
+                serializer.serialize_i64(*n as i64)
+            }
+            Value::Number(n) if n.is_finite() => serializer.serialize_f64(*n),
+            // Formats such as JSON would otherwise quietly turn these into `null`.
+            Value::Number(n) => Err(ser::Error::custom(format!(
+                "cannot serialize non-finite number {}",
+                n
+            ))),
+            Value::String(s) => serializer.serialize_str(s),
+            Value::Boolean(b) => serializer.serialize_bool(*b),
+            Value::Nil => serializer.serialize_unit(),
+            Value::List(items) => {
+                let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                for item in items {
+                    seq.serialize_element(item)?;
+                }
+                seq.end()
+            }
+            Value::Map(entries) => {
+                let mut map = serializer.serialize_map(Some(entries.len()))?;
+                for (key, value) in entries {
+                    map.serialize_entry(key, value)?;
+                }
+                map.end()
+            }
+            Value::Function { name, .. } => Err(ser::Error::custom(format!(
+                "cannot serialize function '{}'",
+                name
+            ))),
+            Value::NativeFunction(native) => Err(ser::Error::custom(format!(
+                "cannot serialize native function '{}'",
+                native.name
+            ))),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a number, string, boolean, null, list or map")
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<Value, E> {
+        Ok(Value::Boolean(v))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Value, E> {
+        Ok(Value::Number(v as f64))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Value, E> {
+        Ok(Value::Number(v as f64))
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Value, E> {
+        Ok(Value::Number(v))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Value, E> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<Value, E> {
+        Ok(Value::String(v))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Nil)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Nil)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        Deserialize::deserialize(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut items = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(item) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(Value::List(items))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut entries = BTreeMap::new();
+        while let Some((key, value)) = map.next_entry::<String, Value>()? {
+            entries.insert(key, value);
+        }
+        Ok(Value::Map(entries))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_json(value: &Value) -> String {
+        serde_json::to_string(value).expect("serializes")
+    }
+
+    fn from_json(text: &str) -> Value {
+        serde_json::from_str(text).expect("deserializes")
+    }
+
+    #[test]
+    fn values_round_trip_through_json() {
+        let value = Value::Map(BTreeMap::from([
+            ("list".to_string(), Value::List(vec![Value::Number(1.0), Value::Nil])),
+            ("name".to_string(), Value::String("m\"erc\n".to_string())),
+            ("ok".to_string(), Value::Boolean(false)),
+            ("ratio".to_string(), Value::Number(-0.25)),
+        ]));
+        let text = to_json(&value);
+        assert_eq!(
+            text,
+            r#"{"list":[1,null],"name":"m\"erc\n","ok":false,"ratio":-0.25}"#
+        );
+        assert_eq!(from_json(&text), value);
+    }
+
+    #[test]
+    fn whole_numbers_are_written_as_integers_while_exact() {
+        assert_eq!(to_json(&Value::Number(3.0)), "3");
+        assert_eq!(to_json(&Value::Number(-0.0)), "0");
+        assert_eq!(to_json(&Value::Number(MAX_SAFE_INTEGER)), "9007199254740991");
+        assert_eq!(to_json(&Value::Number(1e300)), "1e+300");
+        assert_eq!(from_json("18446744073709551615"), Value::Number(u64::MAX as f64));
+    }
+
+    #[test]
+    fn non_finite_numbers_and_functions_are_not_serialized() {
+        for n in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            let error = serde_json::to_string(&Value::List(vec![Value::Number(n)])).unwrap_err();
+            assert_eq!(error.to_string(), format!("cannot serialize non-finite number {}", n));
+        }
+        let function = crate::Interpreter::default().eval("func f() { 1 }\nf").expect("evaluates");
+        let error = serde_json::to_string(&function).unwrap_err();
+        assert_eq!(error.to_string(), "cannot serialize function 'f'");
+    }
+
+    #[test]
+    fn malformed_json_is_an_error() {
+        assert!(serde_json::from_str::<Value>("[1,").is_err());
+        assert!(serde_json::from_str::<Value>("{1: 2}").is_err());
+        assert!(serde_json::from_str::<Value>("nul").is_err());
+    }
+}
@@ -0,0 +1,44 @@
+use miette::{miette, Result};
+
+use crate::interpreter::Value;
+
+/// `assert(condition)` or `assert(condition, message)`: fails unless the
+/// condition is `true`.
+pub fn assert(args: &[Value]) -> Result<Value> {
+    let (condition, message) = match args {
+        [condition] => (condition, None),
+        [condition, message] => (condition, Some(message)),
+        _ => {
+            return Err(miette!(
+                "Wrong number of arguments to 'assert': expected 1 or 2, got {}",
+                args.len()
+            ))
+        }
+    };
+    match (condition, message) {
+        (Value::Boolean(true), _) => Ok(Value::Nil),
+        (Value::Boolean(false), Some(message)) => Err(miette!("Assertion failed: {}", message)),
+        (Value::Boolean(false), None) => Err(miette!("Assertion failed")),
+        (other, _) => Err(miette!(
+            "assert: condition must be a boolean, got {}",
+            other.type_name()
+        )),
+    }
+}
+
+/// `assert_eq(actual, expected)`: fails unless the values are equal,
+/// showing both.
+pub fn assert_eq(args: &[Value]) -> Result<Value> {
+    let [left, right] = args else {
+        unreachable!("registered with an arity of 2")
+    };
+    if left == right {
+        return Ok(Value::Nil);
+    }
+    Err(miette!(
+        help = format!("left:  {}\nright: {}", left.repr(), right.repr()),
+        "Assertion failed: {} != {}",
+        left.repr(),
+        right.repr()
+    ))
+}
@@ -0,0 +1,216 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    rc::Rc,
+};
+
+use miette::Result;
+
+use crate::{convert::IntoValue, interpreter::Value};
+
+use super::{Capabilities, Module};
+
+/// The `fs` module. Using a path outside the directories `capabilities`
+/// allow is an error; otherwise failures are returned as result maps,
+/// `{"ok": false, "value": nil, "error": "..."}`, for scripts to handle.
+pub fn module(capabilities: Rc<Capabilities>) -> Module {
+    Module::new("fs")
+        .typed_function("read", {
+            let caps = capabilities.clone();
+            move |path: String| read(&caps, path)
+        })
+        .typed_function("write", {
+            let caps = capabilities.clone();
+            move |path: String, contents: String| write(&caps, path, contents)
+        })
+        .typed_function("append", {
+            let caps = capabilities.clone();
+            move |path: String, contents: String| append(&caps, path, contents)
+        })
+        .typed_function("exists", {
+            let caps = capabilities.clone();
+            move |path: String| exists(&caps, path)
+        })
+        .typed_function("list_dir", {
+            let caps = capabilities.clone();
+            move |path: String| list_dir(&caps, path)
+        })
+        .typed_function("remove", move |path: String| remove(&capabilities, path))
+}
+
+fn read(caps: &Capabilities, path: String) -> Result<Value> {
+    let path = caps.check_fs("fs.read", &path)?;
+    Ok(result(fs::read_to_string(path)))
+}
+
+fn write(caps: &Capabilities, path: String, contents: String) -> Result<Value> {
+    let path = caps.check_fs("fs.write", &path)?;
+    Ok(result(fs::write(path, contents)))
+}
+
+/// Adds `contents` to the end of the file at `path`, creating it if needed.
+fn append(caps: &Capabilities, path: String, contents: String) -> Result<Value> {
+    let path = caps.check_fs("fs.append", &path)?;
+    let appended = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()));
+    Ok(result(appended))
+}
+
+fn exists(caps: &Capabilities, path: String) -> Result<bool> {
+    Ok(caps.check_fs("fs.exists", &path)?.exists())
+}
+
+/// The names of the entries of a directory, sorted.
+fn list_dir(caps: &Capabilities, path: String) -> Result<Value> {
+    let path = caps.check_fs("fs.list_dir", &path)?;
+    Ok(result(entries(&path)))
+}
+
+fn entries(path: &Path) -> io::Result<Vec<String>> {
+    let mut names = fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.file_name().to_string_lossy().into_owned()))
+        .collect::<io::Result<Vec<_>>>()?;
+    names.sort();
+    Ok(names)
+}
+
+/// Removes a file or an empty directory.
+fn remove(caps: &Capabilities, path: String) -> Result<Value> {
+    let path = caps.check_fs("fs.remove", &path)?;
+    let removed = match path.is_dir() {
+        true => fs::remove_dir(path),
+        false => fs::remove_file(path),
+    };
+    Ok(result(removed))
+}
+
+/// The result map for the outcome of a filesystem operation.
+fn result<T: IntoValue>(outcome: io::Result<T>) -> Value {
+    let (ok, value, error) = match outcome {
+        Ok(value) => (true, value.into_value(), Value::Nil),
+        Err(error) => (false, Value::Nil, Value::String(error.to_string())),
+    };
+    BTreeMap::from([
+        ("ok".to_string(), Value::Boolean(ok)),
+        ("value".to_string(), value),
+        ("error".to_string(), error),
+    ])
+    .into_value()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, path::PathBuf};
+
+    use crate::Interpreter;
+
+    use super::*;
+
+    /// A directory of its own for one test, removed afterwards.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = env::temp_dir().join(format!("merc-fs-{}-{}", name, std::process::id()));
+            fs::create_dir_all(&dir).expect("temporary directory is created");
+            Self(fs::canonicalize(dir).expect("temporary directory exists"))
+        }
+
+        fn path(&self, name: &str) -> String {
+            self.0.join(name).display().to_string()
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn eval(capabilities: Capabilities, source: &str) -> Result<Value> {
+        let mut interpreter = Interpreter::default();
+        interpreter.set_capabilities(capabilities);
+        interpreter.eval(source)
+    }
+
+    /// The `ok` and `value` of a result map.
+    fn outcome(value: Value) -> (bool, Value) {
+        let Value::Map(mut result) = value else {
+            panic!("expected a result map, got {:?}", value);
+        };
+        (result["ok"] == Value::Boolean(true), result.remove("value").unwrap())
+    }
+
+    #[test]
+    fn files_are_written_read_and_removed_inside_allowed_directories() {
+        let dir = TempDir::new("round-trip");
+        let caps = || Capabilities::none().allow_fs(&dir.0);
+        let file = dir.path("notes.txt");
+
+        let write = format!(r#"fs.write("{}", "a")"#, file);
+        assert!(outcome(eval(caps(), &write).unwrap()).0);
+        let append = format!(r#"fs.append("{}", "b")"#, file);
+        assert!(outcome(eval(caps(), &append).unwrap()).0);
+        let read = format!(r#"fs.read("{}")"#, file);
+        assert_eq!(outcome(eval(caps(), &read).unwrap()), (true, Value::String("ab".into())));
+        let list = format!(r#"fs.list_dir("{}")"#, dir.path(""));
+        let names = Value::List(vec![Value::String("notes.txt".into())]);
+        assert_eq!(outcome(eval(caps(), &list).unwrap()), (true, names));
+
+        let remove = format!(r#"fs.remove("{}")"#, file);
+        assert!(outcome(eval(caps(), &remove).unwrap()).0);
+        let exists = format!(r#"fs.exists("{}")"#, file);
+        assert_eq!(eval(caps(), &exists).unwrap(), Value::Boolean(false));
+    }
+
+    #[test]
+    fn failures_inside_allowed_directories_are_result_maps() {
+        let dir = TempDir::new("failures");
+        let read = format!(r#"fs.read("{}")"#, dir.path("missing.txt"));
+        let result = eval(Capabilities::none().allow_fs(&dir.0), &read).unwrap();
+        let Value::Map(result) = result else {
+            panic!("expected a result map");
+        };
+        assert_eq!(result["ok"], Value::Boolean(false));
+        assert_eq!(result["value"], Value::Nil);
+        assert!(matches!(&result["error"], Value::String(error) if !error.is_empty()));
+    }
+
+    #[test]
+    fn paths_outside_allowed_directories_are_errors() {
+        let dir = TempDir::new("outside");
+        let error = eval(Capabilities::default(), r#"fs.read("notes.txt")"#).unwrap_err();
+        assert_eq!(error.to_string(), "fs.read: filesystem access is disabled");
+
+        let allowed = TempDir::new("outside-allowed");
+        let escape = format!(r#"fs.write("{}/../escape.txt", "x")"#, allowed.0.display());
+        let error = eval(Capabilities::none().allow_fs(&allowed.0), &escape).unwrap_err();
+        assert!(error.to_string().starts_with("fs.write: access to"), "{}", error);
+
+        #[cfg(unix)]
+        {
+            let link = allowed.0.join("link");
+            std::os::unix::fs::symlink(&dir.0, &link).expect("symlink is created");
+            let read = format!(r#"fs.list_dir("{}")"#, link.display());
+            let error = eval(Capabilities::none().allow_fs(&allowed.0), &read).unwrap_err();
+            assert!(error.to_string().contains("is not allowed"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn arguments_of_the_wrong_type_are_errors_at_the_argument() {
+        let source = r#"fs.write("notes.txt", 1)"#;
+        let error = eval(Capabilities::default(), source).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid argument 2 to 'fs.write': expected string, got number"
+        );
+        let label = error.labels().and_then(|mut labels| labels.next()).expect("a label");
+        assert_eq!(&source[label.offset()..label.offset() + label.len()], "1");
+    }
+}
@@ -0,0 +1,77 @@
+use std::{
+    io::{self, BufRead, Read},
+    rc::Rc,
+};
+
+use miette::{miette, IntoDiagnostic, Result};
+
+use super::{Capabilities, Module};
+
+/// The `io` module: `read_line` and `read_all_stdin`, which read the
+/// process stdin unless `capabilities` disallow it.
+pub fn module(capabilities: Rc<Capabilities>) -> Module {
+    Module::new("io")
+        .typed_function("read_line", {
+            let caps = capabilities.clone();
+            move || read_line(&caps)
+        })
+        .typed_function("read_all_stdin", move || read_all_stdin(&capabilities))
+}
+
+fn check_stdin(caps: &Capabilities, function: &str) -> Result<()> {
+    match caps.stdin {
+        true => Ok(()),
+        false => Err(miette!("{}: reading stdin is disabled", function)),
+    }
+}
+
+/// The next line of stdin without its line ending, or `nil` at the end of
+/// the input.
+fn read_line(caps: &Capabilities) -> Result<Option<String>> {
+    check_stdin(caps, "io.read_line")?;
+    let mut line = String::new();
+    if io::stdin().lock().read_line(&mut line).into_diagnostic()? == 0 {
+        return Ok(None);
+    }
+    let len = line.trim_end_matches(['\n', '\r']).len();
+    line.truncate(len);
+    Ok(Some(line))
+}
+
+/// The rest of stdin.
+fn read_all_stdin(caps: &Capabilities) -> Result<String> {
+    check_stdin(caps, "io.read_all_stdin")?;
+    let mut input = String::new();
+    io::stdin().read_to_string(&mut input).into_diagnostic()?;
+    Ok(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{interpreter::Value, Interpreter};
+
+    use super::*;
+
+    fn eval(capabilities: Capabilities, source: &str) -> Result<Value> {
+        let mut interpreter = Interpreter::default();
+        interpreter.set_capabilities(capabilities);
+        interpreter.eval(source)
+    }
+
+    #[test]
+    fn stdin_is_refused_without_the_capability() {
+        let error = eval(Capabilities::none(), "io.read_line()").unwrap_err();
+        assert_eq!(error.to_string(), "io.read_line: reading stdin is disabled");
+        let error = eval(Capabilities::none(), "io.read_all_stdin()").unwrap_err();
+        assert_eq!(error.to_string(), "io.read_all_stdin: reading stdin is disabled");
+    }
+
+    #[test]
+    fn arguments_are_rejected() {
+        let error = eval(Capabilities::none(), r#"io.read_line("> ")"#).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Wrong number of arguments to 'io.read_line': expected 0, got 1"
+        );
+    }
+}
@@ -0,0 +1,152 @@
+use miette::{miette, Report, Result};
+use serde::Serialize;
+
+use crate::{
+    convert::{ArgumentError, ConversionError},
+    interpreter::Value,
+};
+
+use super::Module;
+
+/// The `json` module: `json.parse(text)` and `json.stringify(value, indent)`.
+pub fn module() -> Module {
+    Module::new("json")
+        .typed_function("parse", parse)
+        .variadic_function("stringify", |args| match args {
+            [value] => stringify(value, 0).map(Value::String),
+            [value, spaces] => stringify(value, indent(spaces)?).map(Value::String),
+            _ => Err(miette!(
+                "Wrong number of arguments to 'json.stringify': expected 1 or 2, got {}",
+                args.len()
+            )),
+        })
+}
+
+/// The widest indent `json.stringify` accepts, so that a stray argument
+/// fails instead of exhausting memory.
+const MAX_INDENT: usize = 16;
+
+fn indent(spaces: &Value) -> Result<usize> {
+    let problem = match spaces {
+        Value::Number(n) if n.fract() == 0.0 && (0.0..=MAX_INDENT as f64).contains(n) => {
+            return Ok(*n as usize)
+        }
+        Value::Number(n) => format!("expected an indent from 0 to {}, got {}", MAX_INDENT, n),
+        other => ConversionError::new("number", other).to_string(),
+    };
+    Err(Report::new(ArgumentError::new("json.stringify", 1, problem)))
+}
+
+pub fn parse(text: String) -> Result<Value> {
+    serde_json::from_str(&text).map_err(|err| miette!("json.parse: {}", err))
+}
+
+/// Serializes `value` as JSON, pretty-printed with `indent` spaces when non-zero.
+pub fn stringify(value: &Value, indent: usize) -> Result<String> {
+    if indent == 0 {
+        return serde_json::to_string(value).map_err(|err| miette!("json.stringify: {}", err));
+    }
+
+    let indent = " ".repeat(indent);
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(indent.as_bytes());
+    let mut output = Vec::new();
+    let mut serializer = serde_json::Serializer::with_formatter(&mut output, formatter);
+    value
+        .serialize(&mut serializer)
+        .map_err(|err| miette!("json.stringify: {}", err))?;
+    Ok(String::from_utf8(output).expect("serde_json emits valid UTF-8"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Interpreter;
+
+    use super::*;
+
+    fn eval(source: &str) -> Value {
+        Interpreter::default().eval(source).expect("evaluates")
+    }
+
+    /// The message of the error `source` fails with and the code it points at.
+    fn error(source: &str) -> (String, String) {
+        let report = Interpreter::default().eval(source).expect_err("fails");
+        let label = report.labels().and_then(|mut labels| labels.next()).expect("a label");
+        let span = label.offset()..label.offset() + label.len();
+        (report.to_string(), source[span].to_string())
+    }
+
+    fn string(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+
+    #[test]
+    fn stringify_and_parse_round_trip() {
+        let source = r#"let doc = {
+    name: "merc",
+    tags: ["a", "b"],
+    version: 1.5,
+    nested: {ok: true, none: nil}
+}
+json.parse(json.stringify(doc)) == doc"#;
+        assert_eq!(eval(source), Value::Boolean(true));
+        assert_eq!(
+            eval(r#"json.stringify({b: [1, 2], a: "x"})"#),
+            string(r#"{"a":"x","b":[1,2]}"#)
+        );
+        assert_eq!(eval(r#"json.parse("[1, null, \"a\"]")[2]"#), string("a"));
+    }
+
+    #[test]
+    fn stringify_indents_by_the_given_width() {
+        assert_eq!(eval("json.stringify([1], 2)"), string("[\n  1\n]"));
+        assert_eq!(eval("json.stringify([1], 0)"), string("[1]"));
+        let widest = format!("[\n{}1\n]", " ".repeat(16));
+        assert_eq!(eval("json.stringify([1], 16)"), string(&widest));
+    }
+
+    #[test]
+    fn indents_must_be_small_whole_numbers() {
+        assert_eq!(
+            error("json.stringify([1, 2], 1000000000000000000)"),
+            (
+                "Invalid argument 2 to 'json.stringify': \
+                 expected an indent from 0 to 16, got 1000000000000000000"
+                    .to_string(),
+                "1000000000000000000".to_string()
+            )
+        );
+        assert_eq!(error("json.stringify([1], 17)").1, "17");
+        assert_eq!(error("json.stringify([1], -1)").1, "-1");
+        assert_eq!(error("json.stringify([1], 1.5)").1, "1.5");
+        assert_eq!(error("json.stringify([1], math.inf)").1, "math.inf");
+        assert_eq!(error("json.stringify([1], math.nan)").1, "math.nan");
+        assert_eq!(
+            error(r#"json.stringify([1], "  ")"#).0,
+            "Invalid argument 2 to 'json.stringify': expected number, got string"
+        );
+    }
+
+    #[test]
+    fn values_json_cannot_hold_are_errors() {
+        let message = |source| Interpreter::default().eval(source).expect_err("fails").to_string();
+        assert_eq!(
+            message("json.stringify([math.nan])"),
+            "json.stringify: cannot serialize non-finite number NaN"
+        );
+        assert_eq!(
+            message("json.stringify({x: -math.inf})"),
+            "json.stringify: cannot serialize non-finite number -inf"
+        );
+        assert_eq!(
+            message("func f() { 1 }\njson.stringify(f)"),
+            "json.stringify: cannot serialize function 'f'"
+        );
+        assert!(message(r#"json.parse("{\"a\": }")"#).starts_with("json.parse: expected value"));
+        let unclosed = message(r#"json.parse("[1, 2")"#);
+        assert!(unclosed.starts_with("json.parse: EOF while parsing a list"), "{}", unclosed);
+        assert_eq!(
+            message("json.stringify()"),
+            "Wrong number of arguments to 'json.stringify': expected 1 or 2, got 0"
+        );
+    }
+}
@@ -0,0 +1,229 @@
+use std::f64::consts;
+
+use miette::{miette, Report, Result};
+
+use crate::{
+    convert::{ArgumentError, ConversionError},
+    interpreter::Value,
+};
+
+use super::Module;
+
+/// The `math` module: rounding, powers and logarithms, trigonometry,
+/// `min`/`max`/`clamp`, `gcd`/`lcm` and the constants `pi`, `e`, `inf` and
+/// `nan`.
+pub fn module() -> Module {
+    Module::new("math")
+        .constant("pi", consts::PI)
+        .constant("e", consts::E)
+        .constant("inf", f64::INFINITY)
+        .constant("nan", f64::NAN)
+        .typed_function("abs", f64::abs)
+        .typed_function("floor", f64::floor)
+        .typed_function("ceil", f64::ceil)
+        .typed_function("round", f64::round)
+        .typed_function("trunc", f64::trunc)
+        .typed_function("sqrt", |x: f64| {
+            check("math.sqrt", 0, x, x >= 0.0, "a non-negative number").map(f64::sqrt)
+        })
+        .typed_function("pow", f64::powf)
+        .typed_function("exp", f64::exp)
+        .variadic_function("log", log)
+        .typed_function("sin", f64::sin)
+        .typed_function("cos", f64::cos)
+        .typed_function("tan", f64::tan)
+        .typed_function("asin", |x: f64| {
+            check("math.asin", 0, x, (-1.0..=1.0).contains(&x), "a number from -1 to 1")
+                .map(f64::asin)
+        })
+        .typed_function("acos", |x: f64| {
+            check("math.acos", 0, x, (-1.0..=1.0).contains(&x), "a number from -1 to 1")
+                .map(f64::acos)
+        })
+        .typed_function("atan", f64::atan)
+        .typed_function("atan2", f64::atan2)
+        .variadic_function("min", |args| extreme("math.min", args, f64::min))
+        .variadic_function("max", |args| extreme("math.max", args, f64::max))
+        .typed_function("clamp", clamp)
+        .typed_function("gcd", gcd)
+        .typed_function("lcm", lcm)
+        .typed_function("is_nan", f64::is_nan)
+}
+
+/// `x` if `valid`, or an error saying the argument at `position` should
+/// have been `expected`.
+fn check(function: &str, position: usize, x: f64, valid: bool, expected: &str) -> Result<f64> {
+    if valid {
+        return Ok(x);
+    }
+    let problem = format!("expected {}, got {}", expected, x);
+    Err(Report::new(ArgumentError::new(function, position, problem)))
+}
+
+/// `log(x)` for the natural logarithm, or `log(x, base)`.
+fn log(args: &[Value]) -> Result<Value> {
+    let numbers = numbers("math.log", args)?;
+    let (x, base) = match numbers.as_slice() {
+        [x] => (*x, consts::E),
+        [x, base] => (*x, *base),
+        _ => {
+            return Err(miette!(
+                "Wrong number of arguments to 'math.log': expected 1 or 2, got {}",
+                args.len()
+            ))
+        }
+    };
+    check("math.log", 0, x, x > 0.0, "a positive number")?;
+    check("math.log", 1, base, base > 0.0 && base != 1.0, "a positive base other than 1")?;
+    Ok(Value::Number(x.log(base)))
+}
+
+/// The smallest or largest of the arguments, or of the items of a single
+/// list argument, as picked by `pick`.
+fn extreme(function: &str, args: &[Value], pick: fn(f64, f64) -> f64) -> Result<Value> {
+    let numbers = match args {
+        [Value::List(items)] => numbers(function, items)
+            .map_err(|_| ArgumentError::new(function, 0, "expected a list of numbers"))?,
+        args => numbers(function, args)?,
+    };
+    numbers.into_iter().reduce(pick).map(Value::Number).ok_or_else(|| {
+        miette!(
+            "Wrong number of arguments to '{}': expected at least 1 number, got none",
+            function
+        )
+    })
+}
+
+/// The arguments as numbers, failing at the first that isn't one.
+fn numbers(function: &str, args: &[Value]) -> Result<Vec<f64>> {
+    args.iter()
+        .enumerate()
+        .map(|(position, value)| match value {
+            Value::Number(n) => Ok(*n),
+            other => {
+                let problem = ConversionError::new("number", other);
+                Err(Report::new(ArgumentError::new(function, position, problem)))
+            }
+        })
+        .collect()
+}
+
+/// `x` limited to the range from `low` to `high`.
+fn clamp(x: f64, low: f64, high: f64) -> Result<f64> {
+    if low > high {
+        let problem = format!("expected a lower bound no greater than {}, got {}", high, low);
+        return Err(Report::new(ArgumentError::new("math.clamp", 1, problem)));
+    }
+    Ok(x.clamp(low, high))
+}
+
+/// The greatest common divisor of two integers, always non-negative.
+fn gcd(a: i64, b: i64) -> u64 {
+    let (mut a, mut b) = (a.unsigned_abs(), b.unsigned_abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// The least common multiple of two integers, always non-negative.
+fn lcm(a: i64, b: i64) -> Result<u64> {
+    if a == 0 || b == 0 {
+        return Ok(0);
+    }
+    (a.unsigned_abs() / gcd(a, b))
+        .checked_mul(b.unsigned_abs())
+        .ok_or_else(|| miette!("math.lcm: the result of lcm({}, {}) is too large", a, b))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Interpreter;
+
+    use super::*;
+
+    fn eval(source: &str) -> Value {
+        Interpreter::default().eval(source).expect("evaluates")
+    }
+
+    /// The message of the error `source` fails with and the code it points at.
+    fn error(source: &str) -> (String, String) {
+        let report = Interpreter::default().eval(source).expect_err("fails");
+        let label = report.labels().and_then(|mut labels| labels.next()).expect("a label");
+        let span = label.offset()..label.offset() + label.len();
+        (report.to_string(), source[span].to_string())
+    }
+
+    #[test]
+    fn functions_compute_their_results() {
+        assert_eq!(eval("math.abs(-3)"), Value::Number(3.0));
+        assert_eq!(eval("math.log(8, 2)"), Value::Number(3.0));
+        assert_eq!(eval("math.max(3, 7, 5)"), Value::Number(7.0));
+        assert_eq!(eval("math.min([4, 2, 8])"), Value::Number(2.0));
+        assert_eq!(eval("math.clamp(15, 0, 10)"), Value::Number(10.0));
+        assert_eq!(eval("math.gcd(-12, 18)"), Value::Number(6.0));
+        assert_eq!(eval("math.lcm(4, 6)"), Value::Number(12.0));
+        assert_eq!(eval("math.is_nan(math.nan)"), Value::Boolean(true));
+    }
+
+    #[test]
+    fn arguments_of_the_wrong_type_are_errors_at_the_argument() {
+        assert_eq!(
+            error(r#"math.floor("x")"#),
+            (
+                "Invalid argument 1 to 'math.floor': expected number, got string".to_string(),
+                r#""x""#.to_string()
+            )
+        );
+        let (message, code) = error("math.gcd(1.5, 2)");
+        assert!(message.starts_with("Invalid argument 1 to 'math.gcd'"), "{}", message);
+        assert_eq!(code, "1.5");
+        let (message, code) = error(r#"math.max(1, "a")"#);
+        assert!(message.starts_with("Invalid argument 2 to 'math.max'"), "{}", message);
+        assert_eq!(code, r#""a""#);
+    }
+
+    #[test]
+    fn escaped_string_arguments_are_labelled_whole() {
+        assert_eq!(error(r#"math.max(1, "a\"b")"#).1, r#""a\"b""#);
+        assert_eq!(error(r#"math.clamp("\t\n", 0, 1)"#).1, r#""\t\n""#);
+        assert_eq!(error(r#"math.max(1, "é\\", 2)"#).1, r#""é\\""#);
+        // Escapes earlier in the program don't shift the label.
+        assert_eq!(error("let s = \"\\\"\\t\"\nmath.floor(\"x\")").1, r#""x""#);
+    }
+
+    #[test]
+    fn arguments_outside_the_domain_are_errors_at_the_argument() {
+        assert_eq!(
+            error("math.sqrt(-1)"),
+            (
+                "Invalid argument 1 to 'math.sqrt': expected a non-negative number, got -1"
+                    .to_string(),
+                "-1".to_string()
+            )
+        );
+        assert_eq!(error("math.asin(2)").1, "2");
+        assert_eq!(error("math.log(0)").1, "0");
+        let (message, code) = error("math.log(8, 1)");
+        assert!(message.starts_with("Invalid argument 2 to 'math.log'"), "{}", message);
+        assert_eq!(code, "1");
+        let (message, code) = error("math.clamp(1, 5, 2)");
+        assert!(message.starts_with("Invalid argument 2 to 'math.clamp'"), "{}", message);
+        assert_eq!(code, "5");
+    }
+
+    #[test]
+    fn wrong_argument_counts_are_errors_at_the_call() {
+        let (message, code) = error("math.pow(1)");
+        assert_eq!(message, "Wrong number of arguments to 'math.pow': expected 2, got 1");
+        assert!(code.starts_with("math.pow("), "{}", code);
+        assert!(error("math.max()").0.contains("expected at least 1 number"));
+        assert!(error("math.log(1, 2, 3)").0.contains("expected 1 or 2, got 3"));
+    }
+
+    #[test]
+    fn results_too_large_are_errors() {
+        let (message, _) = error("math.lcm(9223372036854774784, 3)");
+        assert!(message.contains("too large"), "{}", message);
+    }
+}
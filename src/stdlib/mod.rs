@@ -0,0 +1,240 @@
+use std::{
+    collections::BTreeMap,
+    fs::canonicalize,
+    path::{Path, PathBuf},
+};
+
+use miette::{miette, Result};
+
+use crate::{
+    convert::{IntoValue, TypedFn},
+    interpreter::{NativeFunction, Value},
+};
+
+pub mod assert;
+pub mod fs;
+pub mod io;
+pub mod json;
+pub mod math;
+pub mod string;
+
+/// What scripts may do outside the interpreter, through `import` and the
+/// `io` and `fs` modules. By default they can import modules and read stdin
+/// but not otherwise touch the filesystem.
+#[derive(Debug, Clone)]
+pub struct Capabilities {
+    /// Whether `io` may read the process stdin.
+    pub stdin: bool,
+    /// The directories `fs` may read and write, everything inside them
+    /// included; none by default.
+    pub fs: Vec<PathBuf>,
+    /// Whether `import` may read modules from files.
+    pub import: bool,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self {
+            stdin: true,
+            fs: Vec::new(),
+            import: true,
+        }
+    }
+}
+
+impl Capabilities {
+    /// No access to stdin, the filesystem or modules, for sandboxed scripts.
+    pub fn none() -> Self {
+        Self {
+            stdin: false,
+            fs: Vec::new(),
+            import: false,
+        }
+    }
+
+    /// Also lets `fs` use `dir` and everything inside it.
+    pub fn allow_fs(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.fs.push(dir.into());
+        self
+    }
+
+    /// `path` made absolute, if it lies inside an allowed directory.
+    /// Symbolic links are resolved first, so they cannot lead outside.
+    pub fn check_fs(&self, function: &str, path: &str) -> Result<PathBuf> {
+        if self.fs.is_empty() {
+            return Err(miette!(
+                help = "run with `--allow-fs=DIR` to give scripts access to DIR",
+                "{}: filesystem access is disabled",
+                function
+            ));
+        }
+        let resolved = resolve(Path::new(path));
+        let allowed = self
+            .fs
+            .iter()
+            .filter_map(|dir| canonicalize(dir).ok())
+            .any(|dir| resolved.starts_with(dir));
+        if !allowed {
+            let dirs: Vec<String> = self.fs.iter().map(|dir| dir.display().to_string()).collect();
+            return Err(miette!(
+                help = format!("scripts may only use {}", dirs.join(", ")),
+                "{}: access to '{}' is not allowed",
+                function,
+                path
+            ));
+        }
+        Ok(resolved)
+    }
+
+    /// Fails unless scripts may import the module at `path`.
+    pub fn check_import(&self, path: &str) -> Result<()> {
+        if self.import {
+            return Ok(());
+        }
+        Err(miette!(
+            help = "the host running this script doesn't allow reading modules",
+            "Cannot import '{}': importing files is disabled",
+            path
+        ))
+    }
+}
+
+/// The canonical form of `path`, or of its parent joined with its name when
+/// it doesn't exist yet.
+fn resolve(path: &Path) -> PathBuf {
+    if let Ok(path) = canonicalize(path) {
+        return path;
+    }
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    match (canonicalize(parent), path.file_name()) {
+        (Ok(parent), Some(name)) => parent.join(name),
+        // Paths such as `missing/file` or `..` are checked as written,
+        // which fails unless an allowed directory is relative too.
+        _ => path.to_path_buf(),
+    }
+}
+
+/// A named collection of native functions and constants, exposed to scripts
+/// as a map so members are reached with `module.member`.
+pub struct Module {
+    name: String,
+    members: BTreeMap<String, Value>,
+}
+
+impl Module {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            members: BTreeMap::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Adds a native function taking exactly `arity` arguments.
+    pub fn function<F>(mut self, name: &str, arity: usize, func: F) -> Self
+    where
+        F: Fn(&[Value]) -> Result<Value> + 'static,
+    {
+        let native = NativeFunction::new(&self.qualified(name), Some(arity), func);
+        self.members
+            .insert(name.to_string(), Value::NativeFunction(native));
+        self
+    }
+
+    /// Adds a native function that checks its own arguments.
+    pub fn variadic_function<F>(mut self, name: &str, func: F) -> Self
+    where
+        F: Fn(&[Value]) -> Result<Value> + 'static,
+    {
+        let native = NativeFunction::new(&self.qualified(name), None, func);
+        self.members
+            .insert(name.to_string(), Value::NativeFunction(native));
+        self
+    }
+
+    /// Adds a plain Rust function, converting its arguments and return value.
+    pub fn typed_function<Args, F>(mut self, name: &str, func: F) -> Self
+    where
+        F: TypedFn<Args>,
+    {
+        let native = func.into_native(&self.qualified(name));
+        self.members
+            .insert(name.to_string(), Value::NativeFunction(native));
+        self
+    }
+
+    pub fn constant(mut self, name: &str, value: impl IntoValue) -> Self {
+        self.members.insert(name.to_string(), value.into_value());
+        self
+    }
+
+    fn qualified(&self, member: &str) -> String {
+        format!("{}.{}", self.name, member)
+    }
+}
+
+impl IntoValue for Module {
+    fn into_value(self) -> Value {
+        Value::Map(self.members)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Interpreter;
+
+    use super::*;
+
+    /// An interpreter running `tests/modules/main.mc` with `capabilities`.
+    fn interpreter(capabilities: Capabilities) -> Interpreter<'static> {
+        let mut interpreter = Interpreter::default();
+        let main = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/modules/main.mc");
+        interpreter.set_file(&main);
+        interpreter.set_capabilities(capabilities);
+        interpreter
+    }
+
+    #[test]
+    fn imports_are_allowed_by_default() {
+        let mut interpreter = interpreter(Capabilities::default());
+        let value = interpreter.eval(r#"import "lib/shapes.mc" as shapes
+shapes.square(3)"#);
+        assert_eq!(value.expect("imports"), Value::Number(9.0));
+    }
+
+    #[test]
+    fn imports_are_refused_without_the_capability() {
+        let without_import = Capabilities {
+            import: false,
+            ..Capabilities::default()
+        };
+        for capabilities in [Capabilities::none(), without_import] {
+            let error = interpreter(capabilities)
+                .eval(r#"from "lib/shapes.mc" import square"#)
+                .expect_err("import is refused");
+            assert_eq!(
+                error.to_string(),
+                "Cannot import 'lib/shapes.mc': importing files is disabled"
+            );
+        }
+        // Files that don't exist are refused the same way, so their absence
+        // doesn't show either.
+        let error = interpreter(Capabilities::none())
+            .eval(r#"import "missing.mc" as missing"#)
+            .expect_err("import is refused");
+        assert!(error.to_string().contains("importing files is disabled"), "{}", error);
+    }
+
+    #[test]
+    fn allowing_fs_does_not_allow_imports() {
+        let capabilities = Capabilities::none().allow_fs(env!("CARGO_MANIFEST_DIR"));
+        let result = interpreter(capabilities).eval(r#"import "lib/shapes.mc" as shapes"#);
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,320 @@
+use miette::{miette, Report, Result};
+
+use crate::{convert::ArgumentError, interpreter::Value};
+
+use super::Module;
+
+/// The `string` module. Its functions take the string first, so they can
+/// also be called as methods: `s.upper()` is `string.upper(s)`.
+pub fn module() -> Module {
+    Module::new("string")
+        .function("len", 1, len)
+        .typed_function("upper", |s: String| s.to_uppercase())
+        .typed_function("lower", |s: String| s.to_lowercase())
+        .typed_function("trim", |s: String| s.trim().to_string())
+        .typed_function("split", split)
+        .typed_function("join", join)
+        .typed_function("replace", |s: String, from: String, to: String| {
+            s.replace(&from, &to)
+        })
+        .typed_function("contains", |s: String, part: String| s.contains(&part))
+        .typed_function("starts_with", |s: String, prefix: String| s.starts_with(&prefix))
+        .typed_function("ends_with", |s: String, suffix: String| s.ends_with(&suffix))
+        .typed_function("find", find)
+        .typed_function("repeat", repeat)
+        .typed_function("chars", |s: String| {
+            s.chars().map(String::from).collect::<Vec<_>>()
+        })
+        .variadic_function("substring", substring)
+        .variadic_function("format", format)
+        .function("to_number", 1, to_number)
+        .function("to_string", 1, to_string)
+}
+
+/// `len(value)`: the number of characters of a string, or of items of a
+/// list or map.
+pub fn len(args: &[Value]) -> Result<Value> {
+    let len = match &args[0] {
+        Value::String(s) => s.chars().count(),
+        Value::List(items) => items.len(),
+        Value::Map(entries) => entries.len(),
+        other => {
+            let problem = format!("expected a string, list or map, got {}", other.type_name());
+            return Err(Report::new(ArgumentError::new("len", 0, problem)));
+        }
+    };
+    Ok(Value::Number(len as f64))
+}
+
+fn split(s: String, separator: String) -> Result<Vec<String>> {
+    if separator.is_empty() {
+        let problem = "expected a non-empty separator; use `chars` to split into characters";
+        return Err(Report::new(ArgumentError::new("string.split", 1, problem)));
+    }
+    Ok(s.split(&separator).map(str::to_string).collect())
+}
+
+/// `separator.join(items)`: the items, which must be strings, with the
+/// separator between them.
+fn join(separator: String, items: Vec<Value>) -> Result<String> {
+    let mut parts = Vec::with_capacity(items.len());
+    for item in items {
+        match item {
+            Value::String(s) => parts.push(s),
+            other => {
+                let problem = format!("expected a list of strings, found a {}", other.type_name());
+                return Err(Report::new(ArgumentError::new("string.join", 1, problem)));
+            }
+        }
+    }
+    Ok(parts.join(&separator))
+}
+
+/// The longest string `repeat` builds, so that a stray count fails instead
+/// of exhausting memory.
+const MAX_REPEAT_LEN: usize = 1 << 30;
+
+fn repeat(s: String, count: usize) -> Result<String> {
+    match s.len().checked_mul(count) {
+        Some(len) if len <= MAX_REPEAT_LEN => Ok(s.repeat(count)),
+        _ => {
+            let problem = format!(
+                "expected a count making at most {} bytes, got {}",
+                MAX_REPEAT_LEN, count
+            );
+            Err(Report::new(ArgumentError::new("string.repeat", 1, problem)))
+        }
+    }
+}
+
+/// The character index of the first occurrence of `part`, or `nil`.
+fn find(s: String, part: String) -> Option<usize> {
+    s.find(&part).map(|byte| s[..byte].chars().count())
+}
+
+/// `substring(s, start)` or `substring(s, start, end)`, by character
+/// index, with `end` exclusive.
+fn substring(args: &[Value]) -> Result<Value> {
+    let (s, start, end) = match args {
+        [Value::String(s), start] => (s, start, None),
+        [Value::String(s), start, end] => (s, start, Some(end)),
+        [_, _] | [_, _, _] => {
+            let problem = format!("expected string, got {}", args[0].type_name());
+            return Err(Report::new(ArgumentError::new("string.substring", 0, problem)));
+        }
+        _ => {
+            return Err(miette!(
+                "Wrong number of arguments to 'string.substring': expected 2 or 3, got {}",
+                args.len()
+            ))
+        }
+    };
+    let count = s.chars().count();
+    let start = index(start, 1, count)?;
+    let end = match end {
+        Some(end) => index(end, 2, count)?,
+        None => count,
+    };
+    if start > end {
+        let problem = format!("expected an end no smaller than the start {}, got {}", start, end);
+        return Err(Report::new(ArgumentError::new("string.substring", 2, problem)));
+    }
+    Ok(Value::String(s.chars().skip(start).take(end - start).collect()))
+}
+
+/// A character index from 0 to `count`, inclusive.
+fn index(value: &Value, position: usize, count: usize) -> Result<usize> {
+    match value {
+        Value::Number(n) if n.fract() == 0.0 && *n >= 0.0 && *n <= count as f64 => Ok(*n as usize),
+        Value::Number(n) => {
+            let problem = format!("expected an index from 0 to {}, got {}", count, n);
+            Err(Report::new(ArgumentError::new("string.substring", position, problem)))
+        }
+        other => {
+            let problem = format!("expected number, got {}", other.type_name());
+            Err(Report::new(ArgumentError::new("string.substring", position, problem)))
+        }
+    }
+}
+
+/// `format(template, values...)`: the template with each `{}` replaced by
+/// the next value; `{{` and `}}` stand for literal braces.
+pub fn format(args: &[Value]) -> Result<Value> {
+    let Some((template, values)) = args.split_first() else {
+        return Err(miette!(
+            "Wrong number of arguments to 'format': expected at least 1, got 0"
+        ));
+    };
+    let Value::String(template) = template else {
+        let problem = format!("expected string, got {}", template.type_name());
+        return Err(Report::new(ArgumentError::new("format", 0, problem)));
+    };
+
+    let mut out = String::with_capacity(template.len());
+    let mut values = values.iter();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('{', Some('{')) | ('}', Some('}')) => {
+                chars.next();
+                out.push(c);
+            }
+            ('{', Some('}')) => {
+                chars.next();
+                let value = values.next().ok_or_else(|| {
+                    miette!(
+                        help = "escape literal braces as `{{` and `}}`",
+                        "format: not enough values for the `{{}}` placeholders, got {}",
+                        args.len() - 1
+                    )
+                })?;
+                out.push_str(&value.to_string());
+            }
+            ('{' | '}', _) => {
+                return Err(miette!(
+                    help = "use `{}` for a value, and `{{` or `}}` for a literal brace",
+                    "format: unmatched `{}` in template",
+                    c
+                ))
+            }
+            _ => out.push(c),
+        }
+    }
+    if values.len() > 0 {
+        return Err(miette!(
+            "format: more values than `{{}}` placeholders, {} left over",
+            values.len()
+        ));
+    }
+    Ok(Value::String(out))
+}
+
+/// `to_number(value)`: a number parsed from a string, or `nil` if the
+/// string isn't one.
+pub fn to_number(args: &[Value]) -> Result<Value> {
+    match &args[0] {
+        Value::Number(n) => Ok(Value::Number(*n)),
+        Value::String(s) => Ok(s
+            .trim()
+            .parse::<f64>()
+            .map_or(Value::Nil, Value::Number)),
+        other => {
+            let problem = format!("expected a string or number, got {}", other.type_name());
+            Err(Report::new(ArgumentError::new("to_number", 0, problem)))
+        }
+    }
+}
+
+/// `to_string(value)`: the value as `print` shows it.
+pub fn to_string(args: &[Value]) -> Result<Value> {
+    Ok(Value::String(args[0].to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Interpreter;
+
+    use super::*;
+
+    fn eval(source: &str) -> Value {
+        Interpreter::default().eval(source).expect("evaluates")
+    }
+
+    /// The message of the error `source` fails with and the code it points at.
+    fn error(source: &str) -> (String, String) {
+        let report = Interpreter::default().eval(source).expect_err("fails");
+        let label = report.labels().and_then(|mut labels| labels.next()).expect("a label");
+        let span = label.offset()..label.offset() + label.len();
+        (report.to_string(), source[span].to_string())
+    }
+
+    #[test]
+    fn functions_compute_their_results() {
+        assert_eq!(eval(r#"len("héllo")"#), Value::Number(5.0));
+        assert_eq!(eval(r#""héllo".substring(1, 3)"#), Value::String("él".to_string()));
+        assert_eq!(eval(r#""héllo".find("l")"#), Value::Number(2.0));
+        assert_eq!(eval(r#""abc".find("x")"#), Value::Nil);
+        assert_eq!(eval(r#"format("{}-{{}}", 1)"#), Value::String("1-{}".to_string()));
+        assert_eq!(eval(r#"to_number("x")"#), Value::Nil);
+    }
+
+    #[test]
+    fn arguments_of_the_wrong_type_are_errors_at_the_argument() {
+        assert_eq!(
+            error("string.upper(1)"),
+            (
+                "Invalid argument 1 to 'string.upper': expected string, got number".to_string(),
+                "1".to_string()
+            )
+        );
+        let (message, code) = error("len(3)");
+        assert!(message.starts_with("Invalid argument 1 to 'len'"), "{}", message);
+        assert_eq!(code, "3");
+        let (message, code) = error("string.substring(1, 2)");
+        assert!(message.starts_with("Invalid argument 1 to 'string.substring'"), "{}", message);
+        assert_eq!(code, "1");
+        let (message, code) = error("format(1)");
+        assert!(message.starts_with("Invalid argument 1 to 'format'"), "{}", message);
+        assert_eq!(code, "1");
+    }
+
+    #[test]
+    fn indexes_outside_the_string_are_errors_at_the_argument() {
+        for index in ["5", "1.5"] {
+            let (message, code) = error(&format!(r#"string.substring("abc", {})"#, index));
+            let problem = format!("expected an index from 0 to 3, got {}", index);
+            assert_eq!(message, format!("Invalid argument 2 to 'string.substring': {}", problem));
+            assert_eq!(code, index);
+        }
+    }
+
+    #[test]
+    fn wrong_argument_counts_are_errors_at_the_call() {
+        let (message, code) = error(r#"string.replace("a", "b")"#);
+        assert_eq!(message, "Wrong number of arguments to 'string.replace': expected 3, got 2");
+        assert!(code.starts_with("string.replace("), "{}", code);
+        let (message, _) = error(r#""a".replace("b")"#);
+        assert_eq!(message, "Wrong number of arguments to 'replace': expected 2, got 1");
+        let (message, _) = error(r#"format("{} {}", 1)"#);
+        assert!(message.contains("not enough values"), "{}", message);
+    }
+
+    #[test]
+    fn repeat_refuses_counts_too_large_instead_of_panicking() {
+        assert_eq!(eval(r#""ab".repeat(3)"#), Value::String("ababab".to_string()));
+        assert_eq!(eval(r#""".repeat(9007199254740991)"#), Value::String(String::new()));
+        for count in ["9007199254740991", "1073741825"] {
+            let (message, code) = error(&format!(r#""x".repeat({})"#, count));
+            assert!(message.starts_with("Invalid argument 1 to method 'repeat'"), "{}", message);
+            assert_eq!(code, count);
+        }
+        let (message, _) = error(r#"string.repeat("x", 9007199254740991)"#);
+        assert!(message.starts_with("Invalid argument 2 to 'string.repeat'"), "{}", message);
+    }
+
+    #[test]
+    fn method_errors_count_the_arguments_in_parentheses() {
+        let (message, code) = error(r#""x".repeat(-1)"#);
+        assert!(message.starts_with("Invalid argument 1 to method 'repeat'"), "{}", message);
+        assert_eq!(code, "-1");
+        let (message, code) = error(r#""ab".substring(1, 0)"#);
+        assert!(message.starts_with("Invalid argument 2 to method 'substring'"), "{}", message);
+        assert_eq!(code, "0");
+        let (message, code) = error(r#""a,b".split("")"#);
+        assert!(message.starts_with("Invalid argument 1 to method 'split'"), "{}", message);
+        assert_eq!(code, r#""""#);
+        let (message, code) = error(r#"", ".join(["a", 1])"#);
+        assert!(message.starts_with("Invalid argument 1 to method 'join'"), "{}", message);
+        assert!(code.contains(r#""a", 1"#), "{}", code);
+    }
+
+    #[test]
+    fn methods_on_other_values_are_reported_by_type() {
+        let report = Interpreter::default().eval("[1, 2].len()").expect_err("fails");
+        assert_eq!(report.to_string(), "No method 'len' on list");
+        let report = Interpreter::default().eval("(3).floor()").expect_err("fails");
+        assert_eq!(report.to_string(), "No method 'floor' on number");
+        let report = Interpreter::default().eval(r#""x".nope()"#).expect_err("fails");
+        assert_eq!(report.to_string(), "No method 'nope' on string");
+    }
+}
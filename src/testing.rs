@@ -0,0 +1,167 @@
+//! Finding and running the tests behind `merc test`.
+//!
+//! Test files are scripts named like `parser_test.mc`. Each `test "name" {
+//! ... }` block in them is a test, run in a fresh interpreter after the
+//! rest of the file, so tests share the file's functions and variables but
+//! not each other's changes to them. A file without `test` blocks is a
+//! single test that passes if the whole file runs without an error.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use miette::{IntoDiagnostic, Report, Result, WrapErr};
+
+use crate::{
+    coverage::Coverage,
+    output::OutputBuffer,
+    stdlib::Capabilities,
+    Interpreter, Parser, ScriptError, TokenKind, S,
+};
+
+/// The end of a test file's name.
+pub const SUFFIX: &str = "_test.mc";
+
+/// The test files among `paths`: files are taken as given, and directories
+/// are searched recursively, skipping hidden ones.
+pub fn discover(paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            search(path, &mut files)?;
+        } else {
+            files.push(path.clone());
+        }
+    }
+    Ok(files)
+}
+
+fn search(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Failed to read directory: {}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    entries.sort();
+
+    for path in entries {
+        let name = path.file_name().map(|name| name.to_string_lossy());
+        if name.as_deref().is_some_and(|name| name.starts_with('.')) {
+            continue;
+        }
+        if path.is_dir() {
+            search(&path, files)?;
+        } else if name.as_deref().is_some_and(|name| name.ends_with(SUFFIX)) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// The result of running one test.
+pub struct Outcome {
+    pub name: String,
+    pub result: Result<()>,
+    /// What the test printed.
+    pub output: String,
+}
+
+/// A parsed test file.
+pub struct Suite {
+    pub path: PathBuf,
+    source: String,
+    program: Vec<S>,
+    /// The names of the `test` blocks and their index in the program.
+    tests: Vec<(String, usize)>,
+}
+
+impl Suite {
+    /// Reads and parses the test file at `path`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let source = fs::read_to_string(path)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Failed to read file: {}", path.display()))?;
+
+        let mut parser = Parser::new(&source);
+        let mut program = Vec::new();
+        while let Some(statement) = parser.parse_statement() {
+            program.push(statement.map_err(|e| Report::new(ScriptError::Parse(e)))?);
+        }
+        let tests = program
+            .iter()
+            .enumerate()
+            .filter_map(|(index, statement)| Some((test_name(statement)?.to_string(), index)))
+            .collect();
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            source,
+            program,
+            tests,
+        })
+    }
+
+    /// How many tests the file holds.
+    pub fn count(&self) -> usize {
+        self.tests.len().max(1)
+    }
+
+    /// The name of the test at `index` prefixed with the file's, or just
+    /// the file's for a file without `test` blocks.
+    pub fn name(&self, index: usize) -> String {
+        match self.tests.get(index) {
+            Some((name, _)) => format!("{}: {}", self.path.display(), name),
+            None => self.path.display().to_string(),
+        }
+    }
+
+    /// Runs the test at `index` in a fresh interpreter with `capabilities`,
+    /// recording what it covers in `coverage`.
+    pub fn run(
+        &self,
+        index: usize,
+        coverage: Option<&Coverage>,
+        capabilities: &Capabilities,
+    ) -> Outcome {
+        let output = OutputBuffer::new();
+        let mut interpreter = Interpreter::default();
+        interpreter.set_file(&self.path);
+        interpreter.set_capabilities(capabilities.clone());
+        interpreter.set_source(&self.source);
+        interpreter.set_stdout(Box::new(output.clone()));
+        if let Some(coverage) = coverage {
+            coverage.include(&self.path, &self.source);
+            interpreter.set_hook(coverage.clone());
+        }
+
+        let mut result = self
+            .program
+            .iter()
+            .filter(|statement| test_name(statement).is_none())
+            .try_for_each(|statement| interpreter.execute(statement).map(|_| ()));
+        if let (Ok(()), Some((_, test))) = (&result, self.tests.get(index)) {
+            result = interpreter.run_test(&self.program[*test]);
+        }
+
+        Outcome {
+            name: self.name(index),
+            result,
+            output: output.take(),
+        }
+    }
+}
+
+/// The name of a `test "name" { ... }` statement.
+fn test_name(statement: &S) -> Option<&str> {
+    match statement {
+        S::Cons(token, args) if token.kind == TokenKind::Test => match args.first() {
+            Some(S::Atom(name)) => match &name.kind {
+                TokenKind::String(name) => Some(name),
+                _ => None,
+            },
+            _ => None,
+        },
+        _ => None,
+    }
+}
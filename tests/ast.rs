@@ -0,0 +1,54 @@
+//! Golden tests for `merc ast`: each `tests/ast/*.mc` script is printed in
+//! every format and compared with the file next to it named after the
+//! format, e.g. `expressions.sexpr`. Run with `MERC_BLESS=1` to rewrite the
+//! expected files after an intended change.
+
+use std::{env, fs, path::Path, process::Command};
+
+const FORMATS: &[&str] = &["tree", "sexpr"];
+
+#[test]
+fn ast_output_matches_golden_files() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/ast");
+    let bless = env::var_os("MERC_BLESS").is_some();
+    let mut scripts: Vec<_> = fs::read_dir(&dir)
+        .expect("tests/ast exists")
+        .map(|entry| entry.expect("readable entry").path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "mc"))
+        .collect();
+    scripts.sort();
+    assert!(!scripts.is_empty(), "no scripts in {}", dir.display());
+
+    let mut mismatches = Vec::new();
+    for script in &scripts {
+        for format in FORMATS {
+            let output = Command::new(env!("CARGO_BIN_EXE_merc"))
+                .args(["ast", "--color", "never", "--format", format])
+                .arg(script)
+                .output()
+                .expect("merc runs");
+            assert!(output.status.success(), "merc ast failed on {}", script.display());
+            let actual = String::from_utf8(output.stdout).expect("UTF-8 output");
+
+            let golden = script.with_extension(format);
+            if bless {
+                fs::write(&golden, &actual).expect("golden file is writable");
+                continue;
+            }
+            let expected = fs::read_to_string(&golden).unwrap_or_default();
+            if actual != expected {
+                mismatches.push(format!(
+                    "{}:\n--- expected\n{}--- actual\n{}",
+                    golden.display(),
+                    expected,
+                    actual
+                ));
+            }
+        }
+    }
+    assert!(
+        mismatches.is_empty(),
+        "output differs from the golden files; rerun with MERC_BLESS=1 if intended\n\n{}",
+        mismatches.join("\n")
+    );
+}
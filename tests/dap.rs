@@ -0,0 +1,125 @@
+//! Tests for `merc dap`, driving the adapter over its stdin and stdout as a
+//! client would.
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    path::Path,
+    process::{ChildStdin, ChildStdout, Command, Stdio},
+};
+
+use serde_json::{json, Value};
+
+struct Client {
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    seq: i64,
+}
+
+impl Client {
+    fn request(&mut self, command: &str, arguments: Value) {
+        self.seq += 1;
+        let body = json!({
+            "seq": self.seq,
+            "type": "request",
+            "command": command,
+            "arguments": arguments,
+        })
+        .to_string();
+        write!(self.stdin, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+        self.stdin.flush().unwrap();
+    }
+
+    /// Reads the next `Content-Length` framed message.
+    fn message(&mut self) -> Value {
+        let mut length = 0;
+        loop {
+            let mut header = String::new();
+            assert_ne!(self.stdout.read_line(&mut header).unwrap(), 0, "the adapter hung up");
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some(value) = header.strip_prefix("Content-Length:") {
+                length = value.trim().parse().unwrap();
+            }
+        }
+        let mut body = vec![0; length];
+        self.stdout.read_exact(&mut body).unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    /// Reads messages up to the response to `command`, returning it.
+    fn response(&mut self, command: &str) -> Value {
+        loop {
+            let message = self.message();
+            if message["type"] == "response" && message["command"] == command {
+                return message;
+            }
+        }
+    }
+
+    /// Reads messages up to the event `event`, returning it and the output
+    /// events seen on the way.
+    fn event(&mut self, event: &str) -> (Value, String) {
+        let mut output = String::new();
+        loop {
+            let message = self.message();
+            if message["event"] == "output" {
+                output.push_str(message["body"]["output"].as_str().unwrap());
+            } else if message["event"] == event {
+                return (message, output);
+            }
+        }
+    }
+}
+
+#[test]
+fn breakpoints_stop_the_program_until_it_continues() {
+    let program = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/debug/square.mc");
+    let program = program.to_str().unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_merc"))
+        .arg("dap")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("merc runs");
+    let mut client = Client {
+        stdin: child.stdin.take().unwrap(),
+        stdout: BufReader::new(child.stdout.take().unwrap()),
+        seq: 0,
+    };
+
+    client.request("initialize", json!({ "adapterID": "merc" }));
+    assert_eq!(client.response("initialize")["success"], true);
+    client.event("initialized");
+    client.request("launch", json!({ "program": program }));
+    assert_eq!(client.response("launch")["success"], true);
+    client.request(
+        "setBreakpoints",
+        json!({ "source": { "path": program }, "breakpoints": [{ "line": 3 }] }),
+    );
+    let breakpoints = client.response("setBreakpoints");
+    assert_eq!(breakpoints["body"]["breakpoints"][0]["verified"], true, "{}", breakpoints);
+    client.request("configurationDone", json!({}));
+
+    let (stopped, output) = client.event("stopped");
+    assert_eq!(stopped["body"]["reason"], "breakpoint");
+    assert_eq!(stopped["body"]["threadId"], 1);
+    assert_eq!(output, "");
+    client.request("stackTrace", json!({ "threadId": 1 }));
+    let trace = client.response("stackTrace");
+    let frames = &trace["body"]["stackFrames"];
+    assert_eq!(frames[0]["name"], "square", "{}", trace);
+    assert_eq!(frames[0]["line"], 3);
+    assert_eq!(frames[1]["name"], "<script>");
+    assert_eq!(frames[1]["line"], 5);
+
+    client.request("continue", json!({ "threadId": 1 }));
+    assert_eq!(client.response("continue")["success"], true);
+    let (exited, output) = client.event("exited");
+    assert_eq!(exited["body"]["exitCode"], 0);
+    assert_eq!(output, "9\n");
+    client.request("disconnect", json!({}));
+    client.response("disconnect");
+    assert!(child.wait().unwrap().success());
+}
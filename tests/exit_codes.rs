@@ -0,0 +1,43 @@
+//! Tests that `merc run` tells failures apart by exit code: 65 when the
+//! script does not parse and 70 when it fails while running.
+
+use std::{
+    path::Path,
+    process::{Command, Output},
+};
+
+fn run(script: &str) -> Output {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/errors").join(script);
+    Command::new(env!("CARGO_BIN_EXE_merc"))
+        .args(["run", "--color", "never"])
+        .arg(path)
+        .output()
+        .expect("merc runs")
+}
+
+#[test]
+fn parse_errors_exit_with_65() {
+    let output = run("parse_error.mc");
+    assert_eq!(output.status.code(), Some(65));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Expected Identifier, got Equal"), "{}", stderr);
+}
+
+#[test]
+fn runtime_errors_exit_with_70() {
+    let output = run("runtime_error.mc");
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "before\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Undefined variable: missing"), "{}", stderr);
+}
+
+#[test]
+fn successful_runs_exit_with_0() {
+    let output = Command::new(env!("CARGO_BIN_EXE_merc"))
+        .args(["run", "-e", "print(1)"])
+        .output()
+        .expect("merc runs");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n");
+}
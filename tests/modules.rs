@@ -0,0 +1,67 @@
+//! Tests for `import` across files, run through the `merc` binary on the
+//! scripts in `tests/modules`.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+fn script(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/modules").join(name)
+}
+
+/// Runs `merc` with `args` from the temporary directory, so that paths only
+/// resolve relative to the importing file.
+fn merc(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_merc"))
+        .args(args)
+        .current_dir(env::temp_dir())
+        .output()
+        .expect("merc runs")
+}
+
+#[test]
+fn imports_resolve_relative_to_the_importing_file() {
+    let main = script("main.mc");
+    let output = merc(&["run", main.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "9\n16\n");
+}
+
+#[test]
+fn import_cycles_are_reported() {
+    let cycle = script("cycle_a.mc");
+    let output = merc(&["run", cycle.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(70));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Import cycle"), "{}", stderr);
+}
+
+#[test]
+fn coverage_has_a_record_per_file() {
+    let lcov = env::temp_dir().join(format!("merc-modules-{}.info", std::process::id()));
+    let test = script("shapes_test.mc");
+    let output = merc(&[
+        "test",
+        "--coverage",
+        "--lcov",
+        lcov.to_str().unwrap(),
+        test.to_str().unwrap(),
+    ]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+    let report = fs::read_to_string(&lcov).expect("lcov report is written");
+    let _ = fs::remove_file(&lcov);
+
+    let records: Vec<&str> = report.split("end_of_record\n").filter(|r| !r.is_empty()).collect();
+    assert_eq!(records.len(), 2, "{}", report);
+    let module = records
+        .iter()
+        .find(|record| record.contains("lib/shapes.mc\n"))
+        .expect("a record for the imported module");
+    // `square` ran, but only the `else` branch of `describe`.
+    assert!(module.contains("DA:4,1\n"), "{}", module);
+    assert!(module.contains("DA:9,0\n"), "{}", module);
+    assert!(module.contains("BRDA:8,0,0,0\nBRDA:8,0,1,1\n"), "{}", module);
+    assert!(records.iter().any(|record| record.contains("shapes_test.mc\n")));
+}
@@ -0,0 +1,23 @@
+//! Tests for `merc test`, run through the `merc` binary on the test files in
+//! `tests/testing`.
+
+use std::{path::Path, process::Command};
+
+#[test]
+fn failing_tests_are_reported_and_fail_the_run() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/testing");
+    let output = Command::new(env!("CARGO_BIN_EXE_merc"))
+        .arg("test")
+        .arg(&dir)
+        .output()
+        .expect("merc runs");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(1), "{}", stdout);
+    assert!(stdout.ends_with("\n1 passed, 1 failed\n"), "{}", stdout);
+    assert!(stdout.contains("FAIL "), "{}", stdout);
+    assert!(stdout.contains("double_test.mc: halving"), "{}", stdout);
+    assert!(!stdout.contains("doubling"), "{}", stdout);
+    assert!(stderr.contains("Assertion failed"), "{}", stderr);
+}